@@ -16,10 +16,16 @@
 --   2. Execute this file: \i sql/install_for_users.sql
 --   3. Install for specific user: SELECT pglinter_install_for_user('username');
 --   4. Or install for current user: SELECT pglinter_install_for_user();
+--   5. Let a user manage rules: SELECT pglinter_grant_to_user('username', true);
+--
+-- Users are granted the pglinter_reader role, which runs the linter and reads
+-- its results, or the pglinter_admin role, which also manages rules, messages,
+-- profiles and files.
 --
 -- Security Note:
---   This creates functions that require superuser privileges to execute
---   properly, but allows delegation of pglinter access to regular users.
+--   Only superusers can run the install, uninstall and grant functions.
+--   Regular users get access to pglinter through the pglinter_reader and
+--   pglinter_admin roles.
 --
 -- =============================================================================
 
@@ -28,7 +34,8 @@
 
 -- Function to install pglinter extension for regular users
 CREATE OR REPLACE FUNCTION pglinter_install_for_user(
-    target_user text DEFAULT current_user
+    target_user text DEFAULT current_user,
+    as_admin boolean DEFAULT false
 )
 RETURNS text AS $$
 DECLARE
@@ -43,18 +50,12 @@ BEGIN
         result_msg := 'pglinter extension installed successfully';
     END IF;
 
-    -- Grant schema usage
-    EXECUTE format('GRANT USAGE ON SCHEMA pglinter TO %I', target_user);
-
-    -- Grant table permissions
-    EXECUTE format('GRANT SELECT ON ALL TABLES IN SCHEMA pglinter TO %I', target_user);
-
-    -- Grant function execution permissions
-    EXECUTE format('GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter TO %I', target_user);
-
-    -- Grant permissions for future objects
-    EXECUTE format('ALTER DEFAULT PRIVILEGES IN SCHEMA pglinter GRANT SELECT ON TABLES TO %I', target_user);
-    EXECUTE format('ALTER DEFAULT PRIVILEGES IN SCHEMA pglinter GRANT EXECUTE ON FUNCTIONS TO %I', target_user);
+    -- Grant the pglinter role
+    EXECUTE format(
+        'GRANT %I TO %I',
+        CASE WHEN as_admin THEN 'pglinter_admin' ELSE 'pglinter_reader' END,
+        target_user
+    );
 
     RETURN result_msg || format(' and permissions granted to user %s', target_user);
 EXCEPTION
@@ -101,7 +102,10 @@ END;
 $$ LANGUAGE plpgsql SECURITY DEFINER;
 
 -- Function to grant pglinter permissions to a user
-CREATE OR REPLACE FUNCTION pglinter_grant_to_user(target_user text)
+CREATE OR REPLACE FUNCTION pglinter_grant_to_user(
+    target_user text,
+    as_admin boolean DEFAULT false
+)
 RETURNS text AS $$
 BEGIN
     -- Validate user exists
@@ -109,10 +113,12 @@ BEGIN
         RETURN format('Error: User %s does not exist', target_user);
     END IF;
 
-    -- Grant permissions
-    EXECUTE format('GRANT USAGE ON SCHEMA pglinter TO %I', target_user);
-    EXECUTE format('GRANT SELECT ON ALL TABLES IN SCHEMA pglinter TO %I', target_user);
-    EXECUTE format('GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter TO %I', target_user);
+    -- Grant the pglinter role
+    EXECUTE format(
+        'GRANT %I TO %I',
+        CASE WHEN as_admin THEN 'pglinter_admin' ELSE 'pglinter_reader' END,
+        target_user
+    );
 
     RETURN format('pglinter permissions granted to user %s', target_user);
 EXCEPTION
@@ -121,11 +127,12 @@ EXCEPTION
 END;
 $$ LANGUAGE plpgsql SECURITY DEFINER;
 
--- Grant execute permissions to public (or specific roles)
-GRANT EXECUTE ON FUNCTION pglinter_install_for_user(text) TO public;
-GRANT EXECUTE ON FUNCTION pglinter_uninstall() TO public;
+-- Granting pglinter roles and dropping the extension are kept to superusers,
+-- everyone can check the status
+REVOKE EXECUTE ON FUNCTION pglinter_install_for_user(text, boolean) FROM public;
+REVOKE EXECUTE ON FUNCTION pglinter_uninstall() FROM public;
 GRANT EXECUTE ON FUNCTION pglinter_status() TO public;
-GRANT EXECUTE ON FUNCTION pglinter_grant_to_user(text) TO public;
+REVOKE EXECUTE ON FUNCTION pglinter_grant_to_user(text, boolean) FROM public;
 
 -- Usage instructions
 DO $$
@@ -136,6 +143,7 @@ BEGIN
     RAISE NOTICE '  SELECT pglinter_install_for_user(''username'');  -- Install for specific user';
     RAISE NOTICE '  SELECT pglinter_status();  -- Check installation status';
     RAISE NOTICE '  SELECT pglinter_grant_to_user(''username'');  -- Grant permissions to user';
+    RAISE NOTICE '  SELECT pglinter_grant_to_user(''username'', true);  -- Let user manage rules';
     RAISE NOTICE '  SELECT pglinter_uninstall();  -- Uninstall extension';
 END;
 $$;
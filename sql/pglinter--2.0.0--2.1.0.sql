@@ -0,0 +1,1150 @@
+-- =============================================================================
+-- pglinter upgrade from 2.0.0 to 2.1.0
+-- =============================================================================
+--
+-- Executed by ALTER EXTENSION pglinter UPDATE TO '2.1.0'. The built-in rules
+-- are updated in place: rules created by users and the enable flags changed
+-- on built-in rules are kept.
+--
+-- Statements changing built-in rules must run before the user configuration
+-- triggers are created, otherwise they would be recorded as user overrides.
+-- =============================================================================
+
+-- complain if script is sourced in psql, rather than via ALTER EXTENSION
+\echo Use "ALTER EXTENSION pglinter UPDATE TO '2.1.0'" to load this file. \quit
+
+-- =============================================================================
+-- Rules table
+-- =============================================================================
+
+ALTER TABLE pglinter.rules
+ADD COLUMN IF NOT EXISTS q1 TEXT,
+ADD COLUMN IF NOT EXISTS warning_level INT,
+ADD COLUMN IF NOT EXISTS error_level INT,
+ADD COLUMN IF NOT EXISTS tags TEXT [] DEFAULT '{}',
+ADD COLUMN IF NOT EXISTS user_defined BOOL DEFAULT FALSE;
+
+-- Rules imported with import_rules_from_yaml() are not built-in rules
+UPDATE pglinter.rules SET user_defined = TRUE
+WHERE code NOT IN (
+    'B001', 'B002', 'B003', 'B004', 'B005', 'B006', 'B007',
+    'B008', 'B009', 'B010', 'B011', 'B012', 'B013', 'S001',
+    'S002', 'S003', 'S004', 'S005', 'C001', 'C002', 'C003'
+);
+
+-- User rules and messages created before 2.1.0 share the ids below 1000 with
+-- the built-in ones: move them after the highest id (see User Configuration)
+UPDATE pglinter.rules AS r SET id = m.new_id
+FROM (
+    SELECT
+        id,
+        (SELECT GREATEST(999, max(id)) FROM pglinter.rules)
+        + row_number() OVER (ORDER BY id) AS new_id
+    FROM pglinter.rules
+    WHERE user_defined AND id < 1000
+) AS m
+WHERE r.id = m.id;
+
+UPDATE pglinter.rule_messages AS rm SET id = m.new_id
+FROM (
+    SELECT
+        id,
+        (SELECT GREATEST(999, max(id)) FROM pglinter.rule_messages)
+        + row_number() OVER (ORDER BY id) AS new_id
+    FROM pglinter.rule_messages
+    WHERE
+        id < 1000
+        AND code IN (SELECT code FROM pglinter.rules WHERE user_defined)
+) AS m
+WHERE rm.id = m.id;
+
+-- =============================================================================
+-- Ratio thresholds
+-- =============================================================================
+--
+-- q1 counts the objects a rule checks. When it is set, the number of
+-- violations is compared to that total and the resulting percentage is
+-- checked against warning_level and error_level (see get_rules_summary).
+-- Rules without q1 are reported on their violation count only.
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B001
+SELECT COUNT(*)
+FROM pg_tables AS pt
+WHERE pt.schemaname NOT IN (
+    'pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb'
+)$q$
+WHERE code = 'B001';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of indexes checked by B002
+SELECT COUNT(*)
+FROM pg_index ind
+JOIN pg_class c ON c.oid = ind.indrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    NOT ind.indisexclusion
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B002';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign keys checked by B003
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND c.relkind = 'r'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B003';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of manual indexes checked by B004
+SELECT COUNT(*)
+FROM pg_stat_user_indexes AS psu
+JOIN pg_index AS pgi ON psu.indexrelid = pgi.indexrelid
+WHERE
+    pgi.indisprimary = FALSE
+    AND pgi.indisunique = FALSE
+    AND psu.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B004';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B006
+SELECT COUNT(*)
+FROM pg_stat_user_tables AS psu
+WHERE psu.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B006';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign keys checked by B007
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B007';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign key columns checked by B008
+SELECT COUNT(*)
+FROM pg_constraint con
+CROSS JOIN LATERAL unnest(con.conkey) AS k(attnum)
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B008';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of triggers checked by B009
+SELECT COUNT(*)
+FROM pg_trigger tg
+JOIN pg_class c ON c.oid = tg.tgrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    NOT tg.tgisinternal
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B009';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B011
+SELECT COUNT(*)
+FROM pg_tables AS t
+WHERE t.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B011';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of primary keys checked by B012
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'p'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B012';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of PL/pgSQL triggers checked by B013
+SELECT COUNT(*)
+FROM pg_trigger tg
+JOIN pg_class c ON c.oid = tg.tgrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+JOIN pg_proc p ON p.oid = tg.tgfoid
+WHERE
+    NOT tg.tgisinternal
+    AND p.prolang = (SELECT oid FROM pg_language WHERE lanname = 'plpgsql')
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B013';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S001
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE
+    n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')
+    AND n.nspname NOT LIKE 'pg_%'$q$
+WHERE code = 'S001';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S002
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S002';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S003
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S003';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S004
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S004';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by S005
+SELECT COUNT(*)
+FROM pg_namespace n
+JOIN pg_class c ON c.relnamespace = n.oid
+WHERE
+    n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')
+    AND n.nspname NOT LIKE 'pg_temp%'
+    AND c.relkind = 'r'$q$
+WHERE code = 'S005';
+
+-- =============================================================================
+-- Rule Tags
+-- =============================================================================
+--
+-- Tags group rules across scopes, see enable_rules_by_tag() and the tag
+-- filter of get_violations(). Rules depending on pg_stat_* counters are
+-- tagged stats-dependent: their result is only meaningful once statistics
+-- have been collected for a while.
+
+UPDATE pglinter.rules r SET tags = t.tags
+FROM (VALUES
+    ('B001', ARRAY['design']),
+    ('B002', ARRAY['performance']),
+    ('B003', ARRAY['performance']),
+    ('B004', ARRAY['performance', 'stats-dependent']),
+    ('B005', ARRAY['naming']),
+    ('B006', ARRAY['design', 'stats-dependent']),
+    ('B007', ARRAY['design']),
+    ('B008', ARRAY['design', 'performance']),
+    ('B009', ARRAY['design']),
+    ('B010', ARRAY['naming']),
+    ('B011', ARRAY['design', 'security']),
+    ('B012', ARRAY['design']),
+    ('B013', ARRAY['performance']),
+    ('S001', ARRAY['security']),
+    ('S002', ARRAY['naming']),
+    ('S003', ARRAY['security']),
+    ('S004', ARRAY['security']),
+    ('S005', ARRAY['design', 'security']),
+    ('C001', ARRAY['security']),
+    ('C002', ARRAY['security']),
+    ('C003', ARRAY['security'])
+) AS t (code, tags)
+WHERE r.code = t.code;
+
+-- =============================================================================
+-- Rule Profiles
+-- =============================================================================
+--
+-- A profile is a named set of rules (dev, staging, prod...). Each rule of a
+-- profile may override the thresholds and the severity of the rule. Profiles
+-- are activated with apply_profile() or used for a single run with
+-- get_violations(profile => ...).
+CREATE TABLE IF NOT EXISTS pglinter.profiles (
+    name TEXT PRIMARY KEY,
+    description TEXT
+);
+
+CREATE TABLE IF NOT EXISTS pglinter.profile_rules (
+    profile_name TEXT NOT NULL
+    REFERENCES pglinter.profiles (name) ON UPDATE CASCADE ON DELETE CASCADE,
+    rule_code TEXT NOT NULL,
+    warning_level INT,
+    error_level INT,
+    severity TEXT,
+    PRIMARY KEY (profile_name, rule_code)
+);
+
+-- =============================================================================
+-- Functions
+-- =============================================================================
+
+-- get_violations() gained the profile and tag filters, and the details
+-- column returned by q4
+DROP FUNCTION IF EXISTS pglinter.get_violations();
+CREATE FUNCTION pglinter."get_violations"(
+    "profile" TEXT DEFAULT NULL,
+    "tag" TEXT DEFAULT NULL
+) RETURNS TABLE (
+    "rule_code" TEXT,
+    "classid" INT,
+    "objid" INT,
+    "objsubid" INT,
+    "message" TEXT,
+    "details" JSONB
+)
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_violations_wrapper';
+
+-- The rules imports gained the import mode
+DROP FUNCTION IF EXISTS pglinter.import_rules_from_yaml(TEXT);
+CREATE FUNCTION pglinter."import_rules_from_yaml"(
+    "yaml_content" TEXT,
+    "mode" TEXT DEFAULT 'merge'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_rules_from_yaml_wrapper';
+
+DROP FUNCTION IF EXISTS pglinter.import_rules_from_file(TEXT);
+CREATE FUNCTION pglinter."import_rules_from_file"(
+    "file_path" TEXT,
+    "mode" TEXT DEFAULT 'merge'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_rules_from_file_wrapper';
+
+CREATE FUNCTION pglinter."check_import"(
+    "yaml_content" TEXT,
+    "kind" TEXT DEFAULT 'rules'
+) RETURNS TABLE (
+    "item" TEXT,
+    "error" TEXT
+)
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'check_import_wrapper';
+
+CREATE FUNCTION pglinter."import_rule_messages_from_file"(
+    "file_path" TEXT
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_rule_messages_from_file_wrapper';
+
+CREATE FUNCTION pglinter."export_config"(
+    "format" TEXT DEFAULT 'yaml'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'export_config_wrapper';
+
+CREATE FUNCTION pglinter."export_config_to_file"(
+    "file_path" TEXT,
+    "format" TEXT DEFAULT 'yaml'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'export_config_to_file_wrapper';
+
+CREATE FUNCTION pglinter."import_config"(
+    "content" TEXT,
+    "mode" TEXT DEFAULT 'merge'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_config_wrapper';
+
+CREATE FUNCTION pglinter."import_config_from_file"(
+    "file_path" TEXT,
+    "mode" TEXT DEFAULT 'merge'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_config_from_file_wrapper';
+
+CREATE FUNCTION pglinter."export_config_audit_to_yaml"(
+    "since" TIMESTAMP WITH TIME ZONE DEFAULT NULL
+) RETURNS TEXT
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'export_config_audit_to_yaml_wrapper';
+
+CREATE FUNCTION pglinter."rules_catalog"() RETURNS TABLE (
+    "code" TEXT,
+    "name" TEXT,
+    "scope" TEXT,
+    "enabled" BOOL,
+    "severity" TEXT,
+    "tags" TEXT [],
+    "message" TEXT,
+    "fixes" TEXT [],
+    "params" JSONB,
+    "docs_url" TEXT
+)
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'rules_catalog_wrapper';
+
+CREATE FUNCTION pglinter."rule_info"(
+    "rule_code" TEXT
+) RETURNS TABLE (
+    "code" TEXT,
+    "name" TEXT,
+    "scope" TEXT,
+    "enabled" BOOL,
+    "severity" TEXT,
+    "tags" TEXT [],
+    "message" TEXT,
+    "fixes" TEXT [],
+    "params" JSONB,
+    "docs_url" TEXT
+)
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'rule_info_wrapper';
+
+CREATE FUNCTION pglinter."generate_fix_script"(
+    "rule_code" TEXT DEFAULT NULL
+) RETURNS TEXT
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'generate_fix_script_wrapper';
+
+CREATE FUNCTION pglinter."apply_fixes"(
+    "rule_codes" TEXT [] DEFAULT NULL,
+    "dry_run" BOOL DEFAULT true,
+    "allow_destructive" BOOL DEFAULT false,
+    "lock_timeout" TEXT DEFAULT '5s'
+) RETURNS TABLE (
+    "rule_code" TEXT,
+    "object" TEXT,
+    "statement" TEXT,
+    "risk" TEXT,
+    "status" TEXT,
+    "detail" TEXT
+)
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'apply_fixes_wrapper';
+
+CREATE FUNCTION pglinter."check_rule_msg"(
+    "rule_msg" JSONB
+) RETURNS BOOL
+IMMUTABLE STRICT PARALLEL SAFE
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'check_rule_msg_wrapper';
+
+CREATE FUNCTION pglinter."update_rule_levels"(
+    "rule_code" TEXT,
+    "warning_level" INT,
+    "error_level" INT
+) RETURNS BOOL
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'update_rule_levels_wrapper';
+
+CREATE FUNCTION pglinter."get_rule_levels"(
+    "rule_code" TEXT
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_rule_levels_wrapper';
+
+CREATE FUNCTION pglinter."create_rule"(
+    "rule_code" TEXT,
+    "name" TEXT,
+    "scope" TEXT,
+    "q4" TEXT,
+    "message" TEXT,
+    "severity" TEXT DEFAULT 'WARNING',
+    "fixes" TEXT [] DEFAULT NULL,
+    "description" TEXT DEFAULT NULL,
+    "tags" TEXT [] DEFAULT NULL
+) RETURNS BOOL
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'create_rule_wrapper';
+
+CREATE FUNCTION pglinter."update_rule"(
+    "rule_code" TEXT,
+    "name" TEXT DEFAULT NULL,
+    "scope" TEXT DEFAULT NULL,
+    "q4" TEXT DEFAULT NULL,
+    "message" TEXT DEFAULT NULL,
+    "severity" TEXT DEFAULT NULL,
+    "fixes" TEXT [] DEFAULT NULL,
+    "description" TEXT DEFAULT NULL,
+    "tags" TEXT [] DEFAULT NULL
+) RETURNS BOOL
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'update_rule_wrapper';
+
+CREATE FUNCTION pglinter."delete_rule"(
+    "rule_code" TEXT
+) RETURNS BOOL
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'delete_rule_wrapper';
+
+CREATE FUNCTION pglinter."enable_rules_by_tag"(
+    "tag" TEXT
+) RETURNS INT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'enable_rules_by_tag_wrapper';
+
+CREATE FUNCTION pglinter."enable_rules_by_scope"(
+    "scope" TEXT
+) RETURNS INT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'enable_rules_by_scope_wrapper';
+
+CREATE FUNCTION pglinter."disable_rules_by_tag"(
+    "tag" TEXT
+) RETURNS INT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'disable_rules_by_tag_wrapper';
+
+CREATE FUNCTION pglinter."disable_rules_by_scope"(
+    "scope" TEXT
+) RETURNS INT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'disable_rules_by_scope_wrapper';
+
+CREATE FUNCTION pglinter."create_profile"(
+    "profile_name" TEXT,
+    "rule_codes" TEXT [] DEFAULT NULL,
+    "description" TEXT DEFAULT NULL
+) RETURNS BOOL
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'create_profile_wrapper';
+
+CREATE FUNCTION pglinter."drop_profile"(
+    "profile_name" TEXT
+) RETURNS BOOL
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'drop_profile_wrapper';
+
+CREATE FUNCTION pglinter."set_profile_rule"(
+    "profile_name" TEXT,
+    "rule_code" TEXT,
+    "warning_level" INT DEFAULT NULL,
+    "error_level" INT DEFAULT NULL,
+    "severity" TEXT DEFAULT NULL
+) RETURNS BOOL
+SECURITY DEFINER
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'set_profile_rule_wrapper';
+
+CREATE FUNCTION pglinter."remove_profile_rule"(
+    "profile_name" TEXT,
+    "rule_code" TEXT
+) RETURNS BOOL
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'remove_profile_rule_wrapper';
+
+CREATE FUNCTION pglinter."apply_profile"(
+    "profile_name" TEXT
+) RETURNS BOOL
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'apply_profile_wrapper';
+
+CREATE FUNCTION pglinter."export_profiles_to_yaml"() RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'export_profiles_to_yaml_wrapper';
+
+CREATE FUNCTION pglinter."import_profiles_from_yaml"(
+    "yaml_content" TEXT
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'import_profiles_from_yaml_wrapper';
+
+CREATE FUNCTION pglinter."get_rules_summary"() RETURNS TABLE (
+    "rule_code" TEXT,
+    "rule_name" TEXT,
+    "violations" BIGINT,
+    "total" BIGINT,
+    "ratio" DOUBLE PRECISION,
+    "warning_level" INT,
+    "error_level" INT,
+    "level" TEXT,
+    "score" DOUBLE PRECISION,
+    "summary" TEXT
+)
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_rules_summary_wrapper';
+
+CREATE FUNCTION pglinter."get_database_score"() RETURNS DOUBLE PRECISION
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_database_score_wrapper';
+
+CREATE FUNCTION pglinter."get_violations_junit"() RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_violations_junit_wrapper';
+
+CREATE FUNCTION pglinter."get_violations_codequality"() RETURNS JSONB
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_violations_codequality_wrapper';
+
+CREATE FUNCTION pglinter."get_report_json"() RETURNS JSONB
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_report_json_wrapper';
+
+CREATE FUNCTION pglinter."get_report"(
+    "format" TEXT DEFAULT 'markdown'
+) RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'get_report_wrapper';
+
+CREATE FUNCTION pglinter."metrics"() RETURNS TEXT
+SECURITY DEFINER
+STRICT
+LANGUAGE c
+AS 'MODULE_PATHNAME', 'metrics_wrapper';
+
+-- q4 may return a fourth details column, used by the messages below
+UPDATE pglinter.rules SET q4 = $q$
+    WITH index_info AS (
+    SELECT
+        ind.indrelid AS table_oid,
+        ind.indexrelid AS index_oid,
+        att.attname AS column_name,
+        array_position(ind.indkey, att.attnum) AS column_order,
+        ind.indisprimary
+    FROM pg_index ind
+    JOIN pg_attribute att ON att.attrelid = ind.indrelid AND att.attnum = ANY(ind.indkey)
+    WHERE NOT ind.indisexclusion
+),
+indexed_columns AS (
+    SELECT
+        table_oid,
+        index_oid,
+        string_agg(column_name, ',' ORDER BY column_order) AS indexed_columns_string,
+        MAX(indisprimary::int)::bool AS is_primary_key
+    FROM index_info
+    GROUP BY table_oid, index_oid
+),
+table_info AS (
+    SELECT
+        oid AS table_oid,
+        relname AS tablename,
+        relnamespace
+    FROM pg_class
+)
+SELECT
+    'pg_class'::regclass::oid AS classid,
+    i1.index_oid AS objid,
+    0 AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', pg_namespace.nspname, table_info.tablename),
+        'covered_by', format('%I.%I', pg_namespace.nspname, superset_index.relname)
+    ) AS details
+FROM indexed_columns AS i1
+JOIN indexed_columns AS i2 ON i1.table_oid = i2.table_oid
+JOIN pg_class redundant_index ON i1.index_oid = redundant_index.oid
+JOIN pg_class superset_index ON i2.index_oid = superset_index.oid
+JOIN table_info ON i1.table_oid = table_info.table_oid
+JOIN pg_namespace ON table_info.relnamespace = pg_namespace.oid
+WHERE
+    pg_namespace.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')
+    AND i1.index_oid <> i2.index_oid
+    AND i2.indexed_columns_string LIKE i1.indexed_columns_string || '%'$q$
+WHERE code = 'B002';
+
+UPDATE pglinter.rules SET q4 = $q$-- Returns classid, objid, objsubid for foreign key constraints lacking an index,
+-- with the table and the columns of the foreign key
+SELECT
+    'pg_constraint'::regclass::oid AS classid,
+    con.oid AS objid,
+    0 AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', n.nspname, c.relname),
+        'columns', (
+            SELECT jsonb_agg(quote_ident(a.attname) ORDER BY k.position)
+            FROM unnest(con.conkey) WITH ORDINALITY AS k (attnum, position)
+            JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+        )
+    ) AS details
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+LEFT JOIN pg_index i ON i.indrelid = c.oid AND con.conkey::smallint [] <@ i.indkey::smallint []
+WHERE
+    con.contype = 'f'
+    AND c.relkind = 'r'
+    AND i.indexrelid IS NULL
+    AND n.nspname NOT IN ('pg_catalog', 'pg_toast', 'information_schema', 'pglinter','_timescaledb', 'timescaledb')$q$
+WHERE code = 'B003';
+
+UPDATE pglinter.rules SET q4 = $q$SELECT
+    'pg_class'::regclass::oid AS classid,
+    c.oid AS objid,
+    a.attnum AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', kcu.table_schema, kcu.table_name),
+        'column', quote_ident(a.attname),
+        'column_type', col1.data_type,
+        'referenced_column', format('%I.%I.%I', ccu.table_schema, ccu.table_name, ccu.column_name),
+        'referenced_type', col2.data_type
+    ) AS details
+FROM information_schema.table_constraints AS tc
+INNER JOIN information_schema.key_column_usage AS kcu
+    ON tc.constraint_name = kcu.constraint_name
+    AND tc.table_schema = kcu.table_schema
+INNER JOIN information_schema.constraint_column_usage AS ccu
+    ON tc.constraint_name = ccu.constraint_name
+INNER JOIN information_schema.columns AS col1
+    ON kcu.table_schema = col1.table_schema
+    AND kcu.table_name = col1.table_name
+    AND kcu.column_name = col1.column_name
+INNER JOIN information_schema.columns AS col2
+    ON ccu.table_schema = col2.table_schema
+    AND ccu.table_name = col2.table_name
+    AND ccu.column_name = col2.column_name
+JOIN pg_class c
+    ON c.relname = kcu.table_name
+    AND c.relnamespace = (SELECT oid FROM pg_namespace WHERE nspname = kcu.table_schema)
+JOIN pg_attribute a
+    ON a.attrelid = c.oid
+    AND a.attname = kcu.column_name
+WHERE
+    tc.constraint_type = 'FOREIGN KEY'
+    AND tc.table_schema NOT IN (
+        'pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb'
+    )
+    AND col1.data_type != col2.data_type$q$
+WHERE code = 'B008';
+
+-- Built-in messages name the type of the object before its identity
+UPDATE pglinter.rule_messages
+SET rule_msg = jsonb_set(
+    rule_msg, '{message}', to_jsonb('{type} ' || (rule_msg ->> 'message'))
+)
+WHERE
+    code IN (
+        'B001', 'B002', 'B003', 'B004', 'B005', 'B006',
+        'B007', 'B008', 'B009', 'B010', 'B011', 'B013'
+    )
+    AND rule_msg ->> 'message' LIKE '{object} %';
+
+-- Messages using the details returned by q4
+UPDATE pglinter.rule_messages SET rule_msg =
+    '{"severity": "WARNING", "message": "{type} {object} is a redundant index, covered by {details.covered_by}.", "advices": "Remove redundant or duplicate indexes to optimize performance and storage.", "infos": ["How to fix: DROP INDEX {object}; or review constraints that may create duplicate indexes."]}'
+WHERE code = 'B002';
+
+UPDATE pglinter.rule_messages SET rule_msg =
+    '{"severity": "WARNING", "message": "{type} {object} does not have an index on its foreign key ({details.columns}).", "advices": "Create an index on the foreign key column to improve join and lookup performance.", "infos": ["How to fix: CREATE INDEX ON {details.table} ({details.columns});"]}'
+WHERE code = 'B003';
+
+UPDATE pglinter.rule_messages SET rule_msg =
+    '{"severity": "WARNING", "message": "{type} {object} has a foreign key type mismatch: {details.column_type} references {details.referenced_column} of type {details.referenced_type}.", "advices": "Adjust column types to ensure foreign key matches referenced key type.", "infos": ["How to fix: ALTER TABLE {details.table} ALTER COLUMN {details.column} TYPE {details.referenced_type};"]}'
+WHERE code = 'B008';
+
+-- One message per rule: the last written message of a code is kept. Messages
+-- written before the check are not validated, they are checked when updated.
+DELETE FROM pglinter.rule_messages m
+WHERE EXISTS (
+    SELECT 1 FROM pglinter.rule_messages d
+    WHERE d.code = m.code AND d.id > m.id
+);
+ALTER TABLE pglinter.rule_messages
+ADD CONSTRAINT rule_messages_code_key UNIQUE (code);
+ALTER TABLE pglinter.rule_messages
+ADD CONSTRAINT rule_messages_rule_msg_check
+CHECK (pglinter.check_rule_msg(rule_msg)) NOT VALID;
+
+-- =============================================================================
+-- User Configuration
+-- =============================================================================
+--
+-- Built-in definitions belong to the extension and are updated by CREATE
+-- EXTENSION and ALTER EXTENSION UPDATE. Changes made by users to built-in
+-- rules (enable flag, levels, tags, messages) are recorded in rule_overrides
+-- by the triggers below and re-applied whenever an override row is written,
+-- so they survive upgrades and dump/restore.
+--
+-- Built-in rules and messages use ids below 1000, user-defined rules and
+-- messages are numbered from 1000 so that restored rows never collide with
+-- the built-in rows of a newer version.
+CREATE TABLE IF NOT EXISTS pglinter.rule_overrides (
+    code TEXT PRIMARY KEY,
+    enable BOOL,
+    warning_level INT,
+    error_level INT,
+    tags TEXT [],
+    rule_msg JSONB
+);
+
+-- Rules disabled before the upgrade stay disabled
+INSERT INTO pglinter.rule_overrides (code, enable)
+SELECT code, enable FROM pglinter.rules
+WHERE NOT user_defined AND NOT enable
+ON CONFLICT (code) DO NOTHING;
+
+SELECT setval(
+    'pglinter.rules_id_seq',
+    GREATEST(999, (SELECT max(id) FROM pglinter.rules))
+);
+SELECT setval(
+    'pglinter.rule_messages_id_seq',
+    GREATEST(999, (SELECT max(id) FROM pglinter.rule_messages))
+);
+
+-- Record the changed columns of a built-in rule
+CREATE OR REPLACE FUNCTION pglinter.record_rule_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    -- Changes made while applying an override are already recorded
+    IF pg_trigger_depth() > 1 THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO pglinter.rule_overrides AS o (
+        code, enable, warning_level, error_level, tags
+    ) VALUES (
+        NEW.code,
+        CASE WHEN NEW.enable IS DISTINCT FROM OLD.enable THEN NEW.enable END,
+        CASE
+            WHEN NEW.warning_level IS DISTINCT FROM OLD.warning_level
+                THEN NEW.warning_level
+        END,
+        CASE
+            WHEN NEW.error_level IS DISTINCT FROM OLD.error_level
+                THEN NEW.error_level
+        END,
+        CASE WHEN NEW.tags IS DISTINCT FROM OLD.tags THEN NEW.tags END
+    )
+    ON CONFLICT (code) DO UPDATE SET
+        enable = COALESCE(excluded.enable, o.enable),
+        warning_level = COALESCE(excluded.warning_level, o.warning_level),
+        error_level = COALESCE(excluded.error_level, o.error_level),
+        tags = COALESCE(excluded.tags, o.tags);
+    RETURN NULL;
+END;
+$$;
+
+-- Record the message of a built-in rule
+CREATE OR REPLACE FUNCTION pglinter.record_rule_message_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF pg_trigger_depth() > 1
+        OR EXISTS (
+            SELECT 1 FROM pglinter.rules
+            WHERE code = NEW.code AND user_defined
+        ) THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO pglinter.rule_overrides AS o (code, rule_msg)
+    VALUES (NEW.code, NEW.rule_msg)
+    ON CONFLICT (code) DO UPDATE SET rule_msg = excluded.rule_msg;
+    RETURN NULL;
+END;
+$$;
+
+-- Apply an override to the built-in rule and its message
+CREATE OR REPLACE FUNCTION pglinter.apply_rule_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE pglinter.rules AS r SET
+        enable = COALESCE(NEW.enable, r.enable),
+        warning_level = COALESCE(NEW.warning_level, r.warning_level),
+        error_level = COALESCE(NEW.error_level, r.error_level),
+        tags = COALESCE(NEW.tags, r.tags)
+    WHERE
+        r.code = NEW.code
+        AND NOT r.user_defined
+        AND (r.enable, r.warning_level, r.error_level, r.tags)
+        IS DISTINCT FROM (
+            COALESCE(NEW.enable, r.enable),
+            COALESCE(NEW.warning_level, r.warning_level),
+            COALESCE(NEW.error_level, r.error_level),
+            COALESCE(NEW.tags, r.tags)
+        );
+
+    IF NEW.rule_msg IS NOT NULL THEN
+        UPDATE pglinter.rule_messages
+        SET rule_msg = NEW.rule_msg
+        WHERE code = NEW.code AND rule_msg IS DISTINCT FROM NEW.rule_msg;
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS record_rule_override ON pglinter.rules;
+CREATE TRIGGER record_rule_override
+AFTER UPDATE OF enable, warning_level, error_level, tags ON pglinter.rules
+FOR EACH ROW
+WHEN (
+    NOT new.user_defined
+    AND (old.enable, old.warning_level, old.error_level, old.tags)
+    IS DISTINCT FROM (new.enable, new.warning_level, new.error_level, new.tags)
+)
+EXECUTE FUNCTION pglinter.record_rule_override();
+
+DROP TRIGGER IF EXISTS record_rule_message_override ON pglinter.rule_messages;
+CREATE TRIGGER record_rule_message_override
+AFTER UPDATE OF rule_msg ON pglinter.rule_messages
+FOR EACH ROW
+WHEN (old.rule_msg IS DISTINCT FROM new.rule_msg)
+EXECUTE FUNCTION pglinter.record_rule_message_override();
+
+DROP TRIGGER IF EXISTS apply_rule_override ON pglinter.rule_overrides;
+CREATE TRIGGER apply_rule_override
+AFTER INSERT OR UPDATE ON pglinter.rule_overrides
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.apply_rule_override();
+
+-- pg_dump only dumps the user part of the extension tables: user-defined
+-- rules and messages, overrides of built-in rules and profiles.
+SELECT pg_catalog.pg_extension_config_dump(
+    'pglinter.rules', 'WHERE user_defined'
+);
+SELECT pg_catalog.pg_extension_config_dump(
+    'pglinter.rule_messages',
+    'WHERE code IN (SELECT code FROM pglinter.rules WHERE user_defined)'
+);
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rule_overrides', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.profiles', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.profile_rules', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rules_id_seq', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rule_messages_id_seq', '');
+
+-- =============================================================================
+-- Configuration Audit
+-- =============================================================================
+-- Every change to rules, rule messages and profiles is recorded with the
+-- session user that made it and the pglinter function it went through. The
+-- functions set pglinter.audit_source for the changes they make, changes made
+-- with plain SQL are recorded with the SQL source. Any user can change a
+-- setting: the role is not taken from one, and pglinter.audit_source is only
+-- read for the changes made as the role the pglinter functions run as.
+
+CREATE TABLE IF NOT EXISTS pglinter.config_audit (
+    id BIGSERIAL PRIMARY KEY,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    changed_by TEXT NOT NULL,
+    source TEXT NOT NULL,
+    object_type TEXT NOT NULL,
+    object_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    old_value JSONB,
+    new_value JSONB
+);
+
+CREATE INDEX IF NOT EXISTS config_audit_object_idx
+ON pglinter.config_audit (object_name, changed_at);
+
+-- Record a change, with only the changed columns for an update
+CREATE OR REPLACE FUNCTION pglinter.audit_config_change()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    old_value JSONB;
+    new_value JSONB;
+    object_name TEXT;
+    source TEXT := 'SQL';
+    key TEXT;
+BEGIN
+    IF TG_OP <> 'INSERT' THEN
+        old_value := to_jsonb(old);
+    END IF;
+    IF TG_OP <> 'DELETE' THEN
+        new_value := to_jsonb(new);
+    END IF;
+    object_name := COALESCE(
+        new_value ->> 'code',
+        old_value ->> 'code',
+        COALESCE(new_value, old_value) ->> 'profile_name'
+        || '/' || (COALESCE(new_value, old_value) ->> 'rule_code'),
+        new_value ->> 'name',
+        old_value ->> 'name'
+    );
+
+    IF TG_OP = 'UPDATE' THEN
+        FOR key IN SELECT jsonb_object_keys(new_value) LOOP
+            IF old_value -> key IS NOT DISTINCT FROM new_value -> key THEN
+                old_value := old_value - key;
+                new_value := new_value - key;
+            END IF;
+        END LOOP;
+        IF new_value = '{}'::JSONB THEN
+            RETURN NULL;
+        END IF;
+    END IF;
+
+    -- The SECURITY DEFINER functions run as their owner
+    IF EXISTS (
+        SELECT 1
+        FROM pg_catalog.pg_proc p
+        JOIN pg_catalog.pg_roles r ON r.oid = p.proowner
+        WHERE p.pronamespace = 'pglinter'::regnamespace
+          AND p.prosecdef
+          AND r.rolname = current_user
+    ) THEN
+        source := COALESCE(
+            NULLIF(current_setting('pglinter.audit_source', TRUE), ''),
+            'SQL'
+        );
+    END IF;
+
+    INSERT INTO pglinter.config_audit (
+        changed_by, source, object_type, object_name, action,
+        old_value, new_value
+    ) VALUES (
+        session_user,
+        source,
+        TG_ARGV[0],
+        object_name,
+        TG_OP,
+        old_value,
+        new_value
+    );
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.rules;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.rules
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('rule');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.rule_messages;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.rule_messages
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('rule_message');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.profiles;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.profiles
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('profile');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.profile_rules;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.profile_rules
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('profile_rule');
+
+SELECT pg_catalog.pg_extension_config_dump('pglinter.config_audit', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.config_audit_id_seq', '');
+
+-- =============================================================================
+-- Roles
+-- =============================================================================
+-- pglinter_reader runs the linter and reads its results. pglinter_admin can
+-- also manage rules, messages, profiles and files: the management functions
+-- check this membership themselves. Roles are shared by the databases of the
+-- cluster, and are kept when the extension is dropped.
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'pglinter_reader') THEN
+        CREATE ROLE pglinter_reader NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'pglinter_admin') THEN
+        CREATE ROLE pglinter_admin NOLOGIN;
+    END IF;
+    IF NOT pg_has_role('pglinter_admin', 'pglinter_reader', 'MEMBER') THEN
+        GRANT pglinter_reader TO pglinter_admin;
+    END IF;
+END
+$$;
+
+GRANT USAGE ON SCHEMA pglinter TO pglinter_reader;
+GRANT SELECT ON ALL TABLES IN SCHEMA pglinter TO pglinter_reader;
+REVOKE EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter FROM PUBLIC;
+GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter TO pglinter_reader;
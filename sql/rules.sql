@@ -30,12 +30,18 @@ CREATE TABLE IF NOT EXISTS pglinter.rules (
     scope TEXT,
     message TEXT,
     fixes TEXT [],
-    q4 TEXT
+    q4 TEXT,
+    q1 TEXT,
+    warning_level INT,
+    error_level INT,
+    tags TEXT [] DEFAULT '{}',
+    user_defined BOOL DEFAULT FALSE
 );
 
 
--- Clear existing data and insert comprehensive rules
-DELETE FROM pglinter.rules;
+-- Clear existing built-in rules and insert comprehensive rules,
+-- rules created with create_rule() are kept
+DELETE FROM pglinter.rules WHERE NOT user_defined;
 
 INSERT INTO pglinter.rules (
     name,
@@ -108,7 +114,11 @@ table_info AS (
 SELECT
     'pg_class'::regclass::oid AS classid,
     i1.index_oid AS objid,
-    0 AS objsubid
+    0 AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', pg_namespace.nspname, table_info.tablename),
+        'covered_by', format('%I.%I', pg_namespace.nspname, superset_index.relname)
+    ) AS details
 FROM indexed_columns AS i1
 JOIN indexed_columns AS i2 ON i1.table_oid = i2.table_oid
 JOIN pg_class redundant_index ON i1.index_oid = redundant_index.oid
@@ -124,11 +134,20 @@ WHERE
     'HowManyTableWithoutIndexOnFk', 'B003', 'BASE',
     'table without index on foreign key',
     ARRAY['create an index on foreign key columns'],
-    $q$-- Returns classid, objid, objsubid for foreign key constraints lacking an index
+    $q$-- Returns classid, objid, objsubid for foreign key constraints lacking an index,
+-- with the table and the columns of the foreign key
 SELECT
     'pg_constraint'::regclass::oid AS classid,
     con.oid AS objid,
-    0 AS objsubid
+    0 AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', n.nspname, c.relname),
+        'columns', (
+            SELECT jsonb_agg(quote_ident(a.attname) ORDER BY k.position)
+            FROM unnest(con.conkey) WITH ORDINALITY AS k (attnum, position)
+            JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+        )
+    ) AS details
 FROM pg_constraint con
 JOIN pg_class c ON c.oid = con.conrelid
 JOIN pg_namespace n ON n.oid = c.relnamespace
@@ -279,7 +298,14 @@ WHERE
     $q$SELECT
     'pg_class'::regclass::oid AS classid,
     c.oid AS objid,
-    a.attnum AS objsubid
+    a.attnum AS objsubid,
+    jsonb_build_object(
+        'table', format('%I.%I', kcu.table_schema, kcu.table_name),
+        'column', quote_ident(a.attname),
+        'column_type', col1.data_type,
+        'referenced_column', format('%I.%I.%I', ccu.table_schema, ccu.table_name, ccu.column_name),
+        'referenced_type', col2.data_type
+    ) AS details
 FROM information_schema.table_constraints AS tc
 INNER JOIN information_schema.key_column_usage AS kcu
     ON tc.constraint_name = kcu.constraint_name
@@ -689,16 +715,252 @@ WHERE
 );
 
 
+-- =============================================================================
+-- Ratio thresholds
+-- =============================================================================
+--
+-- q1 counts the objects a rule checks. When it is set, the number of
+-- violations is compared to that total and the resulting percentage is
+-- checked against warning_level and error_level (see get_rules_summary).
+-- Rules without q1 are reported on their violation count only.
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B001
+SELECT COUNT(*)
+FROM pg_tables AS pt
+WHERE pt.schemaname NOT IN (
+    'pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb'
+)$q$
+WHERE code = 'B001';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of indexes checked by B002
+SELECT COUNT(*)
+FROM pg_index ind
+JOIN pg_class c ON c.oid = ind.indrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    NOT ind.indisexclusion
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B002';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign keys checked by B003
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND c.relkind = 'r'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B003';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of manual indexes checked by B004
+SELECT COUNT(*)
+FROM pg_stat_user_indexes AS psu
+JOIN pg_index AS pgi ON psu.indexrelid = pgi.indexrelid
+WHERE
+    pgi.indisprimary = FALSE
+    AND pgi.indisunique = FALSE
+    AND psu.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B004';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B006
+SELECT COUNT(*)
+FROM pg_stat_user_tables AS psu
+WHERE psu.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B006';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign keys checked by B007
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B007';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of foreign key columns checked by B008
+SELECT COUNT(*)
+FROM pg_constraint con
+CROSS JOIN LATERAL unnest(con.conkey) AS k(attnum)
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'f'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B008';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of triggers checked by B009
+SELECT COUNT(*)
+FROM pg_trigger tg
+JOIN pg_class c ON c.oid = tg.tgrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    NOT tg.tgisinternal
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B009';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by B011
+SELECT COUNT(*)
+FROM pg_tables AS t
+WHERE t.schemaname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B011';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of primary keys checked by B012
+SELECT COUNT(*)
+FROM pg_constraint con
+JOIN pg_class c ON c.oid = con.conrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE
+    con.contype = 'p'
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B012';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of PL/pgSQL triggers checked by B013
+SELECT COUNT(*)
+FROM pg_trigger tg
+JOIN pg_class c ON c.oid = tg.tgrelid
+JOIN pg_namespace n ON n.oid = c.relnamespace
+JOIN pg_proc p ON p.oid = tg.tgfoid
+WHERE
+    NOT tg.tgisinternal
+    AND p.prolang = (SELECT oid FROM pg_language WHERE lanname = 'plpgsql')
+    AND n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'B013';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S001
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE
+    n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')
+    AND n.nspname NOT LIKE 'pg_%'$q$
+WHERE code = 'S001';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S002
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S002';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S003
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S003';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of schemas checked by S004
+SELECT COUNT(*)
+FROM pg_namespace n
+WHERE n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')$q$
+WHERE code = 'S004';
+
+UPDATE pglinter.rules SET
+    warning_level = 20,
+    error_level = 80,
+    q1 = $q$-- Number of tables checked by S005
+SELECT COUNT(*)
+FROM pg_namespace n
+JOIN pg_class c ON c.relnamespace = n.oid
+WHERE
+    n.nspname NOT IN ('pg_toast', 'pg_catalog', 'information_schema', 'pglinter', '_timescaledb', 'timescaledb')
+    AND n.nspname NOT LIKE 'pg_temp%'
+    AND c.relkind = 'r'$q$
+WHERE code = 'S005';
+
+
+-- =============================================================================
+-- Rule Tags
+-- =============================================================================
+--
+-- Tags group rules across scopes, see enable_rules_by_tag() and the tag
+-- filter of get_violations(). Rules depending on pg_stat_* counters are
+-- tagged stats-dependent: their result is only meaningful once statistics
+-- have been collected for a while.
+
+UPDATE pglinter.rules r SET tags = t.tags
+FROM (VALUES
+    ('B001', ARRAY['design']),
+    ('B002', ARRAY['performance']),
+    ('B003', ARRAY['performance']),
+    ('B004', ARRAY['performance', 'stats-dependent']),
+    ('B005', ARRAY['naming']),
+    ('B006', ARRAY['design', 'stats-dependent']),
+    ('B007', ARRAY['design']),
+    ('B008', ARRAY['design', 'performance']),
+    ('B009', ARRAY['design']),
+    ('B010', ARRAY['naming']),
+    ('B011', ARRAY['design', 'security']),
+    ('B012', ARRAY['design']),
+    ('B013', ARRAY['performance']),
+    ('S001', ARRAY['security']),
+    ('S002', ARRAY['naming']),
+    ('S003', ARRAY['security']),
+    ('S004', ARRAY['security']),
+    ('S005', ARRAY['design', 'security']),
+    ('C001', ARRAY['security']),
+    ('C002', ARRAY['security']),
+    ('C003', ARRAY['security'])
+) AS t (code, tags)
+WHERE r.code = t.code;
+
 -- =============================================================================
 -- Rule Messages Table Creation
 -- =============================================================================
+-- One message per rule, with its translations in rule_msg.locales. The check
+-- rejects unknown fields and wrongly typed values.
 CREATE TABLE IF NOT EXISTS pglinter.rule_messages (
     id SERIAL PRIMARY KEY,
-    code TEXT,
-    rule_msg JSONB
+    code TEXT UNIQUE,
+    rule_msg JSONB CHECK (pglinter.check_rule_msg(rule_msg))
 );
 
-DELETE FROM pglinter.rule_messages;
+DELETE FROM pglinter.rule_messages
+WHERE code NOT IN (SELECT code FROM pglinter.rules WHERE user_defined);
 
 INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES
 (
@@ -725,47 +987,47 @@ INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES
 INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES
 (
     'B001',
-    '{"severity": "WARNING", "message": "{object} does not have a primary key.", "advices": "Add a primary key to this table to ensure data integrity and better performance.", "infos": ["How to fix: ALTER TABLE {object} ADD PRIMARY KEY (...);"]}'
+    '{"severity": "WARNING", "message": "{type} {object} does not have a primary key.", "advices": "Add a primary key to this table to ensure data integrity and better performance.", "infos": ["How to fix: ALTER TABLE {object} ADD PRIMARY KEY (...);"]}'
 ),
 (
     'B002',
-    '{"severity": "WARNING", "message": "{object} is a redundant index.", "advices": "Remove redundant or duplicate indexes to optimize performance and storage.", "infos": ["How to fix: DROP INDEX {object}; or review constraints that may create duplicate indexes."]}'
+    '{"severity": "WARNING", "message": "{type} {object} is a redundant index, covered by {details.covered_by}.", "advices": "Remove redundant or duplicate indexes to optimize performance and storage.", "infos": ["How to fix: DROP INDEX {object}; or review constraints that may create duplicate indexes."]}'
 ),
 (
     'B003',
-    '{"severity": "WARNING", "message": "{object} does not have an index on its foreign key.", "advices": "Create an index on the foreign key column to improve join and lookup performance.", "infos": ["How to fix: CREATE INDEX ON {object} (...);"]}'
+    '{"severity": "WARNING", "message": "{type} {object} does not have an index on its foreign key ({details.columns}).", "advices": "Create an index on the foreign key column to improve join and lookup performance.", "infos": ["How to fix: CREATE INDEX ON {details.table} ({details.columns});"]}'
 ),
 (
     'B004',
-    '{"severity": "WARNING", "message": "{object} is an unused index.", "advices": "Remove unused indexes to reduce storage and maintenance overhead.", "infos": ["How to fix: DROP INDEX {object}; or review index usage statistics."]}'
+    '{"severity": "WARNING", "message": "{type} {object} is an unused index.", "advices": "Remove unused indexes to reduce storage and maintenance overhead.", "infos": ["How to fix: DROP INDEX {object}; or review index usage statistics."]}'
 ),
 (
     'B005',
-    '{"severity": "WARNING", "message": "{object} uses uppercase characters.", "advices": "Using uppercase in identifiers requires quoting and can cause case-sensitivity issues.", "infos": ["How to fix: Rename the database object to use only lowercase characters."]}'
+    '{"severity": "WARNING", "message": "{type} {object} uses uppercase characters.", "advices": "Using uppercase in identifiers requires quoting and can cause case-sensitivity issues.", "infos": ["How to fix: Rename the database object to use only lowercase characters."]}'
 ),
 (
     'B006',
-    '{"severity": "WARNING", "message": "{object} has never been selected.", "advices": "Review the necessity of this table. If it is not needed, consider removing it or archiving its data.", "infos": ["How to fix: DROP TABLE {object}; or investigate application usage."]}'
+    '{"severity": "WARNING", "message": "{type} {object} has never been selected.", "advices": "Review the necessity of this table. If it is not needed, consider removing it or archiving its data.", "infos": ["How to fix: DROP TABLE {object}; or investigate application usage."]}'
 ),
 (
     'B007',
-    '{"severity": "WARNING", "message": "{object} has foreign keys outside its schema.", "advices": "Consider restructuring schema design to keep related tables in the same schema.", "infos": ["How to fix: Move related tables into the same schema or review schema design."]}'
+    '{"severity": "WARNING", "message": "{type} {object} has foreign keys outside its schema.", "advices": "Consider restructuring schema design to keep related tables in the same schema.", "infos": ["How to fix: Move related tables into the same schema or review schema design."]}'
 ),
 (
     'B008',
-    '{"severity": "WARNING", "message": "{object} has a foreign key type mismatch.", "advices": "Adjust column types to ensure foreign key matches referenced key type.", "infos": ["How to fix: ALTER TABLE {object} ALTER COLUMN ... TYPE ...;"]}'
+    '{"severity": "WARNING", "message": "{type} {object} has a foreign key type mismatch: {details.column_type} references {details.referenced_column} of type {details.referenced_type}.", "advices": "Adjust column types to ensure foreign key matches referenced key type.", "infos": ["How to fix: ALTER TABLE {details.table} ALTER COLUMN {details.column} TYPE {details.referenced_type};"]}'
 ),
 (
     'B009',
-    '{"severity": "WARNING", "message": "{object} shares a trigger function with other tables.", "advices": "Use one trigger function per table for clarity and maintainability.", "infos": ["How to fix: CREATE a dedicated trigger function for {object} and update the trigger."]}'
+    '{"severity": "WARNING", "message": "{type} {object} shares a trigger function with other tables.", "advices": "Use one trigger function per table for clarity and maintainability.", "infos": ["How to fix: CREATE a dedicated trigger function for {object} and update the trigger."]}'
 ),
 (
     'B010',
-    '{"severity": "WARNING", "message": "{object} uses a reserved SQL keyword as its name.", "advices": "Rename database objects to avoid using reserved keywords.", "infos": ["How to fix: ALTER TABLE/INDEX/VIEW/FUNCTION/TYPE {object} RENAME TO ...;"]}'
+    '{"severity": "WARNING", "message": "{type} {object} uses a reserved SQL keyword as its name.", "advices": "Rename database objects to avoid using reserved keywords.", "infos": ["How to fix: ALTER TABLE/INDEX/VIEW/FUNCTION/TYPE {object} RENAME TO ...;"]}'
 ),
 (
     'B011',
-    '{"severity": "WARNING", "message": "{object} schema has tables with different owners.", "advices": "Change table owners to the same functional role for easier maintenance.", "infos": ["How to fix: ALTER TABLE {object} OWNER TO ...;"]}'
+    '{"severity": "WARNING", "message": "{type} {object} schema has tables with different owners.", "advices": "Change table owners to the same functional role for easier maintenance.", "infos": ["How to fix: ALTER TABLE {object} OWNER TO ...;"]}'
 ),
 (
     'B012',
@@ -773,5 +1035,335 @@ INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES
 ),
 (
     'B013',
-    '{"severity": "WARNING", "message": "{object} uses a trigger function, that uses a cursor and a row by row processing, without any WHERE clause. Fired trigger can cause performance issues.", "advices": "If possible avoid row by row processing. Use base processing instead. If not possible, then add a where clause to limit the number of returned rows.", "infos": ["How to fix: remove the cursor or add a where clause to the cursor. {object}."]}'
+    '{"severity": "WARNING", "message": "{type} {object} uses a trigger function, that uses a cursor and a row by row processing, without any WHERE clause. Fired trigger can cause performance issues.", "advices": "If possible avoid row by row processing. Use base processing instead. If not possible, then add a where clause to limit the number of returned rows.", "infos": ["How to fix: remove the cursor or add a where clause to the cursor. {object}."]}'
+);
+
+
+-- =============================================================================
+-- Rule Profiles
+-- =============================================================================
+--
+-- A profile is a named set of rules (dev, staging, prod...). Each rule of a
+-- profile may override the thresholds and the severity of the rule. Profiles
+-- are activated with apply_profile() or used for a single run with
+-- get_violations(profile => ...).
+CREATE TABLE IF NOT EXISTS pglinter.profiles (
+    name TEXT PRIMARY KEY,
+    description TEXT
 );
+
+CREATE TABLE IF NOT EXISTS pglinter.profile_rules (
+    profile_name TEXT NOT NULL
+    REFERENCES pglinter.profiles (name) ON UPDATE CASCADE ON DELETE CASCADE,
+    rule_code TEXT NOT NULL,
+    warning_level INT,
+    error_level INT,
+    severity TEXT,
+    PRIMARY KEY (profile_name, rule_code)
+);
+
+-- =============================================================================
+-- User Configuration
+-- =============================================================================
+--
+-- The rows above are the built-in definitions: they belong to the extension
+-- and are reloaded by CREATE EXTENSION and ALTER EXTENSION UPDATE. Changes
+-- made by users to built-in rules (enable flag, levels, tags, messages) are
+-- recorded in rule_overrides by the triggers below and re-applied whenever
+-- an override row is written, so they survive upgrades and dump/restore.
+--
+-- Built-in rules and messages use ids below 1000, user-defined rules and
+-- messages are numbered from 1000 so that restored rows never collide with
+-- the built-in rows of a newer version.
+CREATE TABLE IF NOT EXISTS pglinter.rule_overrides (
+    code TEXT PRIMARY KEY,
+    enable BOOL,
+    warning_level INT,
+    error_level INT,
+    tags TEXT [],
+    rule_msg JSONB
+);
+
+SELECT setval(
+    'pglinter.rules_id_seq',
+    GREATEST(999, (SELECT max(id) FROM pglinter.rules))
+);
+SELECT setval(
+    'pglinter.rule_messages_id_seq',
+    GREATEST(999, (SELECT max(id) FROM pglinter.rule_messages))
+);
+
+-- Record the changed columns of a built-in rule
+CREATE OR REPLACE FUNCTION pglinter.record_rule_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    -- Changes made while applying an override are already recorded
+    IF pg_trigger_depth() > 1 THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO pglinter.rule_overrides AS o (
+        code, enable, warning_level, error_level, tags
+    ) VALUES (
+        NEW.code,
+        CASE WHEN NEW.enable IS DISTINCT FROM OLD.enable THEN NEW.enable END,
+        CASE
+            WHEN NEW.warning_level IS DISTINCT FROM OLD.warning_level
+                THEN NEW.warning_level
+        END,
+        CASE
+            WHEN NEW.error_level IS DISTINCT FROM OLD.error_level
+                THEN NEW.error_level
+        END,
+        CASE WHEN NEW.tags IS DISTINCT FROM OLD.tags THEN NEW.tags END
+    )
+    ON CONFLICT (code) DO UPDATE SET
+        enable = COALESCE(excluded.enable, o.enable),
+        warning_level = COALESCE(excluded.warning_level, o.warning_level),
+        error_level = COALESCE(excluded.error_level, o.error_level),
+        tags = COALESCE(excluded.tags, o.tags);
+    RETURN NULL;
+END;
+$$;
+
+-- Record the message of a built-in rule
+CREATE OR REPLACE FUNCTION pglinter.record_rule_message_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF pg_trigger_depth() > 1
+        OR EXISTS (
+            SELECT 1 FROM pglinter.rules
+            WHERE code = NEW.code AND user_defined
+        ) THEN
+        RETURN NULL;
+    END IF;
+
+    INSERT INTO pglinter.rule_overrides AS o (code, rule_msg)
+    VALUES (NEW.code, NEW.rule_msg)
+    ON CONFLICT (code) DO UPDATE SET rule_msg = excluded.rule_msg;
+    RETURN NULL;
+END;
+$$;
+
+-- Apply an override to the built-in rule and its message
+CREATE OR REPLACE FUNCTION pglinter.apply_rule_override()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE pglinter.rules AS r SET
+        enable = COALESCE(NEW.enable, r.enable),
+        warning_level = COALESCE(NEW.warning_level, r.warning_level),
+        error_level = COALESCE(NEW.error_level, r.error_level),
+        tags = COALESCE(NEW.tags, r.tags)
+    WHERE
+        r.code = NEW.code
+        AND NOT r.user_defined
+        AND (r.enable, r.warning_level, r.error_level, r.tags)
+        IS DISTINCT FROM (
+            COALESCE(NEW.enable, r.enable),
+            COALESCE(NEW.warning_level, r.warning_level),
+            COALESCE(NEW.error_level, r.error_level),
+            COALESCE(NEW.tags, r.tags)
+        );
+
+    IF NEW.rule_msg IS NOT NULL THEN
+        UPDATE pglinter.rule_messages
+        SET rule_msg = NEW.rule_msg
+        WHERE code = NEW.code AND rule_msg IS DISTINCT FROM NEW.rule_msg;
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS record_rule_override ON pglinter.rules;
+CREATE TRIGGER record_rule_override
+AFTER UPDATE OF enable, warning_level, error_level, tags ON pglinter.rules
+FOR EACH ROW
+WHEN (
+    NOT new.user_defined
+    AND (old.enable, old.warning_level, old.error_level, old.tags)
+    IS DISTINCT FROM (new.enable, new.warning_level, new.error_level, new.tags)
+)
+EXECUTE FUNCTION pglinter.record_rule_override();
+
+DROP TRIGGER IF EXISTS record_rule_message_override ON pglinter.rule_messages;
+CREATE TRIGGER record_rule_message_override
+AFTER UPDATE OF rule_msg ON pglinter.rule_messages
+FOR EACH ROW
+WHEN (old.rule_msg IS DISTINCT FROM new.rule_msg)
+EXECUTE FUNCTION pglinter.record_rule_message_override();
+
+DROP TRIGGER IF EXISTS apply_rule_override ON pglinter.rule_overrides;
+CREATE TRIGGER apply_rule_override
+AFTER INSERT OR UPDATE ON pglinter.rule_overrides
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.apply_rule_override();
+
+-- pg_dump only dumps the user part of the extension tables: user-defined
+-- rules and messages, overrides of built-in rules and profiles.
+SELECT pg_catalog.pg_extension_config_dump(
+    'pglinter.rules', 'WHERE user_defined'
+);
+SELECT pg_catalog.pg_extension_config_dump(
+    'pglinter.rule_messages',
+    'WHERE code IN (SELECT code FROM pglinter.rules WHERE user_defined)'
+);
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rule_overrides', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.profiles', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.profile_rules', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rules_id_seq', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.rule_messages_id_seq', '');
+
+-- =============================================================================
+-- Configuration Audit
+-- =============================================================================
+-- Every change to rules, rule messages and profiles is recorded with the
+-- session user that made it and the pglinter function it went through. The
+-- functions set pglinter.audit_source for the changes they make, changes made
+-- with plain SQL are recorded with the SQL source. Any user can change a
+-- setting: the role is not taken from one, and pglinter.audit_source is only
+-- read for the changes made as the role the pglinter functions run as.
+
+CREATE TABLE IF NOT EXISTS pglinter.config_audit (
+    id BIGSERIAL PRIMARY KEY,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+    changed_by TEXT NOT NULL,
+    source TEXT NOT NULL,
+    object_type TEXT NOT NULL,
+    object_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    old_value JSONB,
+    new_value JSONB
+);
+
+CREATE INDEX IF NOT EXISTS config_audit_object_idx
+ON pglinter.config_audit (object_name, changed_at);
+
+-- Record a change, with only the changed columns for an update
+CREATE OR REPLACE FUNCTION pglinter.audit_config_change()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    old_value JSONB;
+    new_value JSONB;
+    object_name TEXT;
+    source TEXT := 'SQL';
+    key TEXT;
+BEGIN
+    IF TG_OP <> 'INSERT' THEN
+        old_value := to_jsonb(old);
+    END IF;
+    IF TG_OP <> 'DELETE' THEN
+        new_value := to_jsonb(new);
+    END IF;
+    object_name := COALESCE(
+        new_value ->> 'code',
+        old_value ->> 'code',
+        COALESCE(new_value, old_value) ->> 'profile_name'
+        || '/' || (COALESCE(new_value, old_value) ->> 'rule_code'),
+        new_value ->> 'name',
+        old_value ->> 'name'
+    );
+
+    IF TG_OP = 'UPDATE' THEN
+        FOR key IN SELECT jsonb_object_keys(new_value) LOOP
+            IF old_value -> key IS NOT DISTINCT FROM new_value -> key THEN
+                old_value := old_value - key;
+                new_value := new_value - key;
+            END IF;
+        END LOOP;
+        IF new_value = '{}'::JSONB THEN
+            RETURN NULL;
+        END IF;
+    END IF;
+
+    -- The SECURITY DEFINER functions run as their owner
+    IF EXISTS (
+        SELECT 1
+        FROM pg_catalog.pg_proc p
+        JOIN pg_catalog.pg_roles r ON r.oid = p.proowner
+        WHERE p.pronamespace = 'pglinter'::regnamespace
+          AND p.prosecdef
+          AND r.rolname = current_user
+    ) THEN
+        source := COALESCE(
+            NULLIF(current_setting('pglinter.audit_source', TRUE), ''),
+            'SQL'
+        );
+    END IF;
+
+    INSERT INTO pglinter.config_audit (
+        changed_by, source, object_type, object_name, action,
+        old_value, new_value
+    ) VALUES (
+        session_user,
+        source,
+        TG_ARGV[0],
+        object_name,
+        TG_OP,
+        old_value,
+        new_value
+    );
+    RETURN NULL;
+END;
+$$;
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.rules;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.rules
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('rule');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.rule_messages;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.rule_messages
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('rule_message');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.profiles;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.profiles
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('profile');
+
+DROP TRIGGER IF EXISTS audit_config_change ON pglinter.profile_rules;
+CREATE TRIGGER audit_config_change
+AFTER INSERT OR UPDATE OR DELETE ON pglinter.profile_rules
+FOR EACH ROW
+EXECUTE FUNCTION pglinter.audit_config_change('profile_rule');
+
+SELECT pg_catalog.pg_extension_config_dump('pglinter.config_audit', '');
+SELECT pg_catalog.pg_extension_config_dump('pglinter.config_audit_id_seq', '');
+
+-- =============================================================================
+-- Roles
+-- =============================================================================
+-- pglinter_reader runs the linter and reads its results. pglinter_admin can
+-- also manage rules, messages, profiles and files: the management functions
+-- check this membership themselves. Roles are shared by the databases of the
+-- cluster, and are kept when the extension is dropped.
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'pglinter_reader') THEN
+        CREATE ROLE pglinter_reader NOLOGIN;
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'pglinter_admin') THEN
+        CREATE ROLE pglinter_admin NOLOGIN;
+    END IF;
+    IF NOT pg_has_role('pglinter_admin', 'pglinter_reader', 'MEMBER') THEN
+        GRANT pglinter_reader TO pglinter_admin;
+    END IF;
+END
+$$;
+
+GRANT USAGE ON SCHEMA pglinter TO pglinter_reader;
+GRANT SELECT ON ALL TABLES IN SCHEMA pglinter TO pglinter_reader;
+REVOKE EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter FROM PUBLIC;
+GRANT EXECUTE ON ALL FUNCTIONS IN SCHEMA pglinter TO pglinter_reader;
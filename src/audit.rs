@@ -0,0 +1,88 @@
+use pgrx::datum::TimestampWithTimeZone;
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A change recorded in pglinter.config_audit
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEntry {
+    pub changed_at: String,
+    pub changed_by: String,
+    pub source: String,
+    pub object_type: String,
+    pub object_name: String,
+    pub action: String,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditExportMetadata {
+    pub export_timestamp: String,
+    pub total_entries: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditExport {
+    pub metadata: AuditExportMetadata,
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Set the source recorded by the audit trigger. An empty value makes the
+/// trigger fall back to the SQL source.
+fn set_audit_source(source: &str) {
+    let query = "SELECT set_config('pglinter.audit_source', $1, TRUE)";
+    if let Err(e) = Spi::run_with_args(query, &[source.into()]) {
+        pgrx::warning!("Failed to set the audit source: {}", e);
+    }
+}
+
+/// Run `change`, recording `source` with the changes it makes to the
+/// configuration. The trigger records the session user itself.
+pub fn tracked<T>(source: &str, change: impl FnOnce() -> T) -> T {
+    set_audit_source(source);
+    let result = change();
+    set_audit_source("");
+    result
+}
+
+/// Load the audit trail, oldest change first
+pub fn load_audit(since: Option<TimestampWithTimeZone>) -> Result<Vec<AuditEntry>, spi::SpiError> {
+    let query = "
+        SELECT to_json(changed_at) #>> '{}', changed_by, source, object_type,
+               object_name, action, old_value::TEXT, new_value::TEXT
+        FROM pglinter.config_audit
+        WHERE $1 IS NULL OR changed_at >= $1
+        ORDER BY id";
+
+    let parse = |value: Option<String>| value.and_then(|v| serde_json::from_str(&v).ok());
+    Spi::connect(|client| {
+        let mut entries = Vec::new();
+        for row in client.select(query, None, &[since.into()])? {
+            entries.push(AuditEntry {
+                changed_at: row.get(1)?.unwrap_or_default(),
+                changed_by: row.get(2)?.unwrap_or_default(),
+                source: row.get(3)?.unwrap_or_default(),
+                object_type: row.get(4)?.unwrap_or_default(),
+                object_name: row.get(5)?.unwrap_or_default(),
+                action: row.get(6)?.unwrap_or_default(),
+                old_value: parse(row.get(7)?),
+                new_value: parse(row.get(8)?),
+            });
+        }
+        Ok(entries)
+    })
+}
+
+/// Export the audit trail to YAML, from `since` when given
+pub fn export_config_audit_to_yaml(since: Option<TimestampWithTimeZone>) -> Result<String, String> {
+    let entries = load_audit(since).map_err(|e| format!("Database error: {}", e))?;
+    let export_data = AuditExport {
+        metadata: AuditExportMetadata {
+            export_timestamp: chrono::Utc::now().to_rfc3339(),
+            total_entries: entries.len(),
+        },
+        entries,
+    };
+
+    serde_yaml::to_string(&export_data).map_err(|e| format!("YAML serialization error: {}", e))
+}
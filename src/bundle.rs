@@ -0,0 +1,174 @@
+use crate::execute_rules::in_subtransaction;
+use crate::files::{resolve_path, FileAccess};
+use crate::manage_rules::{self, ImportErrors, ImportMode, Rule};
+use crate::profiles::{self, Profile};
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+/// Version of the bundle format written by export_config.
+pub const BUNDLE_FORMAT_VERSION: &str = "2.0";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BundleMetadata {
+    pub export_timestamp: String,
+    pub format_version: String,
+    #[serde(default)]
+    pub extension_version: Option<String>,
+}
+
+/// Everything needed to reproduce the pglinter setup of a database. Sections
+/// missing from an imported bundle are left untouched.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigBundle {
+    pub metadata: BundleMetadata,
+    #[serde(default)]
+    pub rules: Option<Vec<Rule>>,
+    #[serde(default)]
+    pub rule_messages: Option<BTreeMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub profiles: Option<Vec<Profile>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    Yaml,
+    Json,
+}
+
+impl BundleFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_lowercase().as_str() {
+            "yaml" | "yml" => Ok(BundleFormat::Yaml),
+            "json" => Ok(BundleFormat::Json),
+            _ => Err(format!(
+                "Invalid bundle format '{}': expected yaml or json",
+                format
+            )),
+        }
+    }
+}
+
+/// Export rules, rule messages and profiles as a single bundle
+pub fn export_config(format: BundleFormat) -> Result<String, String> {
+    let db_error = |e: spi::SpiError| format!("Database error: {}", e);
+    let bundle = ConfigBundle {
+        metadata: BundleMetadata {
+            export_timestamp: chrono::Utc::now().to_rfc3339(),
+            format_version: BUNDLE_FORMAT_VERSION.to_string(),
+            extension_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        },
+        rules: Some(manage_rules::load_rules().map_err(db_error)?),
+        rule_messages: Some(manage_rules::load_rule_messages().map_err(db_error)?),
+        profiles: Some(profiles::load_profiles().map_err(db_error)?),
+    };
+
+    match format {
+        BundleFormat::Yaml => {
+            serde_yaml::to_string(&bundle).map_err(|e| format!("YAML serialization error: {}", e))
+        }
+        BundleFormat::Json => serde_json::to_string_pretty(&bundle)
+            .map_err(|e| format!("JSON serialization error: {}", e)),
+    }
+}
+
+/// Export the configuration bundle to a file
+pub fn export_config_to_file(file_path: &str, format: BundleFormat) -> Result<String, String> {
+    let path = resolve_path(file_path, FileAccess::Write)?;
+    let content = export_config(format)?;
+
+    match std::fs::write(path, &content) {
+        Ok(_) => Ok(format!(
+            "✅ Configuration exported successfully to: {}",
+            file_path
+        )),
+        Err(e) => Err(format!("File write error: {}", e)),
+    }
+}
+
+/// The format_version of a document, which YAML may have read as a number
+fn format_version(document: &Value) -> Option<String> {
+    match document.get("metadata")?.get("format_version")? {
+        Value::String(version) => Some(version.clone()),
+        Value::Number(version) => Some(format!("{:.1}", version.as_f64()?)),
+        _ => None,
+    }
+}
+
+/// 1.0 documents are the rules or profiles exports, with their count in the
+/// metadata. Their sections have the same layout as in a bundle.
+fn migrate_from_1_0(mut document: Value) -> Value {
+    if let Some(metadata) = document.get_mut("metadata").and_then(Value::as_mapping_mut) {
+        metadata.remove("total_rules");
+        metadata.remove("total_profiles");
+        metadata.insert("format_version".into(), "2.0".into());
+    }
+    document
+}
+
+/// Reads a YAML or JSON bundle, migrating documents of older format versions
+pub fn parse_bundle(content: &str) -> Result<ConfigBundle, String> {
+    // JSON documents are valid YAML
+    let mut document: Value =
+        serde_yaml::from_str(content).map_err(|e| format!("Parsing error: {}", e))?;
+
+    loop {
+        match format_version(&document).as_deref() {
+            Some("1.0") => document = migrate_from_1_0(document),
+            Some(BUNDLE_FORMAT_VERSION) => break,
+            Some(version) => return Err(format!("Unsupported format_version {}", version)),
+            None => return Err("Missing metadata.format_version".to_string()),
+        }
+    }
+
+    serde_yaml::from_value(document).map_err(|e| format!("Parsing error: {}", e))
+}
+
+/// Import a configuration bundle. Rules are imported first so that the messages
+/// and profiles may refer to the new rules. Either the whole bundle is imported,
+/// or nothing is written and the errors found are returned.
+pub fn import_config(content: &str, mode: ImportMode) -> Result<String, ImportErrors> {
+    let bundle = parse_bundle(content)?;
+
+    pgrx::notice!(
+        "📥 Importing configuration bundle: {} rules, {} rule messages, {} profiles",
+        bundle.rules.as_ref().map_or(0, Vec::len),
+        bundle.rule_messages.as_ref().map_or(0, BTreeMap::len),
+        bundle.profiles.as_ref().map_or(0, Vec::len)
+    );
+
+    if mode == ImportMode::DryRun {
+        return match &bundle.rules {
+            Some(rules) => manage_rules::import_rules(rules, mode),
+            None => Ok("🔍 Dry run: the bundle has no rules".to_string()),
+        };
+    }
+
+    let replace = mode == ImportMode::Replace;
+    in_subtransaction(|| {
+        let mut summary = Vec::new();
+        if let Some(rules) = &bundle.rules {
+            summary.push(manage_rules::import_rules(rules, mode)?);
+        }
+        if let Some(messages) = &bundle.rule_messages {
+            summary.push(manage_rules::import_rule_messages(messages, replace)?);
+        }
+        if let Some(profiles) = &bundle.profiles {
+            summary.push(profiles::import_profiles(profiles, replace)?);
+        }
+        Ok(summary.join("\n"))
+    })
+}
+
+/// Import a configuration bundle from a file
+pub fn import_config_from_file(file_path: &str, mode: ImportMode) -> Result<String, ImportErrors> {
+    let path = resolve_path(file_path, FileAccess::Read)?;
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("File read error: {}", e).into()),
+    };
+
+    pgrx::notice!("📂 Reading configuration from: {}", file_path);
+    import_config(&content, mode)
+}
@@ -1,91 +1,244 @@
+use crate::parallel::TaskResult;
+use crate::templates;
+use pgrx::pg_sys::panic::CaughtError;
 use pgrx::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-type ViolationLocation = (i32, i32, i32);
-type RuleViolations = (String, Vec<ViolationLocation>);
+pub type ViolationLocation = (i32, i32, i32);
+#[cfg(any(test, feature = "pg_test"))]
+type RuleViolations = (String, Vec<Violation>);
 
-/// Collects violations for all enabled rules by calling get_violations_for_rule for each rule.
-pub fn get_violations() -> Result<Vec<RuleViolations>, String> {
-    pgrx::debug1!("get_violations; Starting to collect violations for all enabled rules");
-    let rules_query = "SELECT code FROM pglinter.rules WHERE enable = true ORDER BY code";
-    let rule_codes: Result<Vec<String>, String> = Spi::connect(|client| {
-        let mut codes = Vec::new();
-        for row in client.select(rules_query, None, &[])? {
+/// An object returned by a q4 query, with the optional details column.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub classid: i32,
+    pub objid: i32,
+    pub objsubid: i32,
+    pub details: Option<serde_json::Value>,
+}
+
+impl Violation {
+    pub fn location(&self) -> ViolationLocation {
+        (self.classid, self.objid, self.objsubid)
+    }
+}
+
+/// Outcome of executing the q4 query of one enabled rule. The objects of the
+/// violations are only described by run_described_rules.
+pub struct RuleRun {
+    pub code: String,
+    pub name: String,
+    pub scope: String,
+    pub violations: Result<Vec<DescribedViolation>, String>,
+    pub duration: Duration,
+}
+
+/// Identity of a database object as reported by pg_identify_object.
+pub struct ObjectIdentity {
+    pub object_type: String,
+    pub schema: Option<String>,
+    pub name: Option<String>,
+    pub identity: String,
+}
+
+/// Executes every enabled rule and keeps per-rule errors instead of discarding them.
+pub fn run_enabled_rules() -> Result<Vec<RuleRun>, String> {
+    run_rules(None, None, None, false)
+}
+
+/// Executes every enabled rule, describing the object of each violation in its q4
+/// query, see templates::described_query.
+pub fn run_described_rules() -> Result<Vec<RuleRun>, String> {
+    run_rules(None, None, None, true)
+}
+
+/// Executes the rules enabled globally or, when a profile is given, the rules of that
+/// profile, regardless of their global enable flag. A tag restricts the run to the rules
+/// carrying it, and `codes` to the rules listed.
+pub fn run_rules(
+    profile: Option<&str>,
+    tag: Option<&str>,
+    codes: Option<&[&str]>,
+    described: bool,
+) -> Result<Vec<RuleRun>, String> {
+    pgrx::debug1!("run_enabled_rules; Starting to execute all enabled rules");
+    let rules = select_rules(profile, tag, codes)?;
+    let mut worker_runs = if crate::parallel::max_workers() > 0 && rules.len() > 1 {
+        run_in_workers(&rules, described)
+    } else {
+        HashMap::new()
+    };
+
+    let mut runs = Vec::with_capacity(rules.len());
+    for (code, name, scope) in rules {
+        // Rules not run by a worker run in the session
+        let (violations, duration) = worker_runs.remove(&code).unwrap_or_else(|| {
+            let started = Instant::now();
+            let violations = in_subtransaction(|| run_rule(&code, described));
+            (violations, started.elapsed())
+        });
+        if let Err(e) = &violations {
+            pgrx::debug1!("run_enabled_rules; Error for rule {}: {}", code, e);
+        }
+        runs.push(RuleRun {
+            code,
+            name,
+            scope,
+            violations,
+            duration,
+        });
+    }
+    pgrx::debug1!("run_enabled_rules; Completed executing all enabled rules");
+    Ok(runs)
+}
+
+/// Runs the q4 queries of the rules in background workers, see parallel::run_tasks.
+/// Rules without q4, or that no worker ran, are missing from the result.
+fn run_in_workers(
+    rules: &[(String, String, String)],
+    described: bool,
+) -> HashMap<String, TaskResult> {
+    let tasks: Vec<(String, String)> = rules
+        .iter()
+        .filter_map(|(code, _, _)| {
+            let q4_sql = rule_q4(code).ok().flatten()?;
+            let sql = if described {
+                templates::described_query(&q4_sql)
+            } else {
+                q4_sql
+            };
+            Some((code.clone(), sql))
+        })
+        .collect();
+    let results = crate::parallel::run_tasks(&tasks, described);
+    tasks
+        .into_iter()
+        .zip(results)
+        .filter_map(|((code, _), result)| Some((code, result?)))
+        .collect()
+}
+
+/// Returns the (code, name, scope) of the rules selected by run_rules, ordered by code.
+fn select_rules(
+    profile: Option<&str>,
+    tag: Option<&str>,
+    codes: Option<&[&str]>,
+) -> Result<Vec<(String, String, String)>, String> {
+    if let Some(profile) = profile {
+        if !crate::profiles::profile_exists(profile)? {
+            return Err(format!("Profile '{profile}' not found"));
+        }
+    }
+    let rules_query = "
+        SELECT code, name, scope
+        FROM pglinter.rules r
+        WHERE CASE
+            WHEN $1::TEXT IS NULL THEN r.enable
+            ELSE EXISTS (
+                SELECT 1
+                FROM pglinter.profile_rules pr
+                WHERE pr.profile_name = $1 AND pr.rule_code = r.code
+            )
+        END
+        AND ($2::TEXT IS NULL OR lower($2) = ANY(r.tags))
+        AND ($3::TEXT[] IS NULL OR r.code = ANY($3))
+        ORDER BY code";
+    let codes: Option<Vec<String>> =
+        codes.map(|codes| codes.iter().map(|c| c.to_string()).collect());
+    Spi::connect(|client| {
+        let mut rules = Vec::new();
+        let args = [profile.into(), tag.into(), codes.into()];
+        for row in client.select(rules_query, None, &args)? {
             let code: String = row.get(1)?.unwrap_or_default();
-            codes.push(code);
+            let name: String = row.get(2)?.unwrap_or_default();
+            let scope: String = row.get(3)?.unwrap_or_default();
+            rules.push((code, name, scope));
         }
-        Ok(codes)
+        Ok(rules)
     })
-    .map_err(|e: spi::SpiError| format!("Database error fetching rule codes: {e}"));
+    .map_err(|e: spi::SpiError| format!("Database error fetching rule codes: {e}"))
+}
 
-    let _rule_codes = rule_codes?;
+/// Runs `f` in an internal subtransaction, so that an error raised by a rule query is
+/// returned as `Err` instead of aborting the whole transaction. The subtransaction is
+/// rolled back when `f` fails or returns `Err`.
+pub fn in_subtransaction<T, E: From<String>>(
+    f: impl FnOnce() -> Result<T, E> + std::panic::UnwindSafe,
+) -> Result<T, E> {
+    let (old_context, old_owner) =
+        unsafe { (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner) };
+    unsafe {
+        pg_sys::BeginInternalSubTransaction(std::ptr::null());
+        pg_sys::MemoryContextSwitchTo(old_context);
+    }
 
-    let mut all_violations = Vec::new();
-    for code in _rule_codes {
-        match get_violations_for_rule(&code) {
-            Ok(violations) => {
-                all_violations.push((code.clone(), violations));
-            }
-            Err(e) => {
-                pgrx::debug1!("get_violations; Error for rule {}: {}", code, e);
-                // Optionally, you could push an empty vector or skip on error
-                all_violations.push((code.clone(), vec![]));
+    PgTryBuilder::new(|| {
+        let result = f();
+        unsafe {
+            if result.is_ok() {
+                pg_sys::ReleaseCurrentSubTransaction();
+            } else {
+                pg_sys::RollbackAndReleaseCurrentSubTransaction();
             }
+            pg_sys::MemoryContextSwitchTo(old_context);
+            pg_sys::CurrentResourceOwner = old_owner;
         }
-    }
+        result
+    })
+    .catch_others(|e| {
+        unsafe {
+            pg_sys::RollbackAndReleaseCurrentSubTransaction();
+            pg_sys::MemoryContextSwitchTo(old_context);
+            pg_sys::CurrentResourceOwner = old_owner;
+        }
+        let message = match e {
+            CaughtError::PostgresError(report)
+            | CaughtError::ErrorReport(report)
+            | CaughtError::RustPanic {
+                ereport: report, ..
+            } => report.message().to_string(),
+        };
+        Err(E::from(message))
+    })
+    .execute()
+}
+
+/// Collects violations for all enabled rules, or for the rules of a profile, optionally
+/// restricted to a tag, by calling get_violations_for_rule for each rule. The SQL
+/// function streams them instead, see stream_violations.
+#[cfg(any(test, feature = "pg_test"))]
+pub fn get_violations(
+    profile: Option<&str>,
+    tag: Option<&str>,
+) -> Result<Vec<RuleViolations>, String> {
+    pgrx::debug1!("get_violations; Starting to collect violations for all enabled rules");
+    let all_violations = run_rules(profile, tag, None, false)?
+        .into_iter()
+        // A failing rule is reported with no violations, its error is logged by run_enabled_rules
+        .map(|run| {
+            let violations = run.violations.unwrap_or_default();
+            (run.code, violations.into_iter().map(|(v, _)| v).collect())
+        })
+        .collect();
     pgrx::debug1!("get_violations; Completed collecting violations for all rules");
     Ok(all_violations)
 }
 
-/// Executes the q4 query for the given rule_id and returns (classid, objid, objsubid) tuples.
-pub fn get_violations_for_rule(rule_id: &str) -> Result<Vec<(i32, i32, i32)>, String> {
+/// Executes the q4 query for the given rule_id and returns its (classid, objid, objsubid)
+/// rows, with the details column when the query returns one.
+pub fn get_violations_for_rule(rule_id: &str) -> Result<Vec<Violation>, String> {
     pgrx::debug1!("get_violations_for_rule; Starting for rule_id: {}", rule_id);
 
-    // Read q4 SQL from the database
-    let q4_sql: Option<String> = Spi::connect(|client| {
-        let mut rows = client.select(
-            "SELECT q4 FROM pglinter.rules WHERE code = $1",
-            None,
-            &[rule_id.into()],
-        )?;
-        if let Some(row) = rows.next() {
-            Ok(row.get::<String>(1)?)
-        } else {
-            Ok(None)
-        }
-    })
-    .map_err(|e: spi::SpiError| {
-        format!("Database error fetching q4 for rule '{}': {e}", rule_id)
-    })?;
-
-    let q4_sql = match q4_sql {
-        Some(q) if !q.is_empty() => q,
-        _ => {
-            pgrx::debug1!(
-                "get_violations_for_rule; No q4 query for rule_id '{}', returning empty",
-                rule_id
-            );
-            return Ok(vec![]);
-        }
+    let Some(q4_sql) = rule_q4(rule_id)? else {
+        pgrx::debug1!(
+            "get_violations_for_rule; No q4 query for rule_id '{}', returning empty",
+            rule_id
+        );
+        return Ok(vec![]);
     };
 
-    // Execute the q4 SQL and collect results
-    let result: Result<Vec<(i32, i32, i32)>, String> = Spi::connect(|client| {
-        use pgrx::pg_sys::Oid;
-        let mut results = Vec::new();
-        let query_result = client.select(&q4_sql, None, &[])?;
-        for row in query_result {
-            let classid_oid = row.get::<Oid>(1)?.unwrap_or(Oid::INVALID);
-            let objid_oid = row.get::<Oid>(2)?.unwrap_or(Oid::INVALID);
-            let classid = u32::from(classid_oid) as i32;
-            let objid = u32::from(objid_oid) as i32;
-            let objsubid = row.get::<i32>(3)?.unwrap_or_default();
-            results.push((classid, objid, objsubid));
-        }
-        Ok(results)
-    })
-    .map_err(|e: spi::SpiError| format!("SPI error executing q4: {e}"));
-
-    match result {
+    match run_q4(&q4_sql) {
         Ok(res) => {
             pgrx::debug1!(
                 "get_violations_for_rule; Completed successfully for rule_id: {} ({} violations)",
@@ -101,154 +254,538 @@ pub fn get_violations_for_rule(rule_id: &str) -> Result<Vec<(i32, i32, i32)>, St
     }
 }
 
-pub fn get_sanitized_message(rule_id: &str, classid: i32, objid: i32, objsubid: i32) -> String {
-    // Fetch the rule_msg (jsonb) from the rules table as serde_json::Value
-    let query = "SELECT rule_msg::TEXT FROM pglinter.rule_messages WHERE code = $1";
-    let rule_msg_json: Option<serde_json::Value> = match Spi::connect(|client| {
-        let mut rows = client.select(query, None, &[rule_id.into()])?;
-        if let Some(row) = rows.next() {
-            // Use Option<serde_json::Value> for jsonb
-            let val: Option<String> = row.get(1)?;
-            let json_val = match val {
-                Some(s) => serde_json::from_str(&s).ok(),
-                None => None,
-            };
-            Ok::<Option<serde_json::Value>, spi::SpiError>(json_val)
-        } else {
-            Ok(None)
+/// Executes the q4 query of a rule, described or not, as run_rules does.
+fn run_rule(rule_id: &str, described: bool) -> Result<Vec<DescribedViolation>, String> {
+    if !described {
+        let violations = get_violations_for_rule(rule_id)?;
+        return Ok(violations
+            .into_iter()
+            .map(|v| (v, Err("object not described".to_string())))
+            .collect());
+    }
+    let Some(q4_sql) = rule_q4(rule_id)? else {
+        return Ok(vec![]);
+    };
+    Spi::connect(|client| {
+        let table = client.select(&templates::described_query(&q4_sql), None, &[])?;
+        let columns = table.columns()?;
+        let mut results = Vec::with_capacity(table.len());
+        for row in table {
+            results.push(read_described_violation(&row, columns)?);
         }
-    }) {
-        Ok(val) => val,
-        Err(e) => {
-            pgrx::debug1!(
-                "get_sanitized_message; Failed to get rule_msg for {}: {}",
-                rule_id,
-                e
-            );
-            return format!("[pglinter: error fetching rule message for {}]", rule_id);
+        Ok(results)
+    })
+    .map_err(|e: spi::SpiError| format!("SPI error executing q4: {e}"))
+}
+
+/// Reads the q4 query of a rule, `None` when the rule has none.
+pub fn rule_q4(rule_id: &str) -> Result<Option<String>, String> {
+    let q4_sql = Spi::get_one_with_args::<String>(
+        "SELECT q4 FROM pglinter.rules WHERE code = $1",
+        &[rule_id.into()],
+    )
+    .or_else(|e| match e {
+        spi::SpiError::InvalidPosition => Ok(None),
+        e => Err(format!(
+            "Database error fetching q4 for rule '{}': {e}",
+            rule_id
+        )),
+    })?;
+    Ok(q4_sql.filter(|q| !q.trim().is_empty()))
+}
+
+/// Executes a q4 query and collects its (classid, objid, objsubid[, details]) rows.
+pub fn run_q4(q4_sql: &str) -> Result<Vec<Violation>, String> {
+    Spi::connect(|client| {
+        let mut results = Vec::new();
+        let query_result = client.select(q4_sql, None, &[])?;
+        let has_details = query_result.columns()? > 3;
+        for row in query_result {
+            results.push(read_violation(&row, has_details)?);
         }
+        Ok(results)
+    })
+    .map_err(|e: spi::SpiError| format!("SPI error executing q4: {e}"))
+}
+
+/// Reads one (classid, objid, objsubid[, details]) row of a q4 query.
+pub fn read_violation(
+    row: &spi::SpiHeapTupleData<'_>,
+    has_details: bool,
+) -> spi::Result<Violation> {
+    use pgrx::pg_sys::Oid;
+    let classid_oid = row.get::<Oid>(1)?.unwrap_or(Oid::INVALID);
+    let objid_oid = row.get::<Oid>(2)?.unwrap_or(Oid::INVALID);
+    let details = if has_details {
+        row.get::<pgrx::JsonB>(4)?
+            .map(|details| details.0)
+            .filter(|details| !details.is_null())
+    } else {
+        None
     };
+    Ok(Violation {
+        classid: u32::from(classid_oid) as i32,
+        objid: u32::from(objid_oid) as i32,
+        objsubid: row.get::<i32>(3)?.unwrap_or_default(),
+        details,
+    })
+}
+
+/// Reads one row of a described_query with `columns` columns.
+pub fn read_described_violation(
+    row: &spi::SpiHeapTupleData<'_>,
+    columns: usize,
+) -> spi::Result<DescribedViolation> {
+    let description =
+        templates::read_description(row, columns - templates::DESCRIPTION_COLUMNS + 1)?;
+    let has_details = columns > 3 + templates::DESCRIPTION_COLUMNS;
+    Ok((read_violation(row, has_details)?, description))
+}
 
-    // Optionally, you can fetch a message template from another table if needed
-    let message_template = String::new();
+/// Number of q4 rows fetched at once by ViolationStream.
+pub const STREAM_BATCH_SIZE: i64 = 1000;
 
-    // Helper to resolve object name from classid, objid, objsubid
-    fn resolve_object_name(classid: i32, objid: i32, objsubid: i32) -> String {
-        // Legacy rule rows may use pg_attribute (1249) for column references.
-        // pg_identify_object expects relation columns as (pg_class, relid, attnum).
-        let (mut classid, objid, objsubid) = (classid, objid, objsubid);
-        if classid == 1249 && objsubid != 0 {
-            classid = 1259;
+/// Streams the violations of the rules selected by run_rules, rule after rule, with
+/// their rendered message.
+///
+/// The q4 query of the current rule runs behind an SPI cursor, kept open by name
+/// between calls, so at most one batch of rows is held in memory whatever the number
+/// of violations. The cursor also describes the object of each row, and the message
+/// of the rule is read once when its cursor is opened. Each open and fetch runs in a
+/// subtransaction: a failing rule is logged and its remaining rows skipped, the
+/// following rules are still streamed. A cursor left open when the stream is not
+/// consumed to the end is closed with the transaction.
+///
+/// With pglinter.max_parallel_workers, the queries run in background workers and
+/// their batches are read from the workers in rule order instead, see ParallelRun.
+/// A rule whose worker exits before sending any row runs in the session, one whose
+/// worker exits after that raises an error rather than returning part of its rows.
+pub struct ViolationStream {
+    /// Rule codes, with their task in `workers`
+    rules: std::vec::IntoIter<(String, Option<usize>)>,
+    workers: Option<crate::parallel::ParallelRun>,
+    code: String,
+    message: Option<RuleMessage>,
+    source: Option<RowSource>,
+    batch: std::collections::VecDeque<DescribedViolation>,
+}
+
+/// Where the rows of the current rule are read from
+enum RowSource {
+    Cursor(String),
+    Worker { task: usize, received: bool },
+}
+
+/// A violation with the description of its object, see templates::described_query
+pub type DescribedViolation = (Violation, Result<templates::ObjectDescription, String>);
+
+/// Starts streaming the violations of all enabled rules, or of the rules of a profile,
+/// optionally restricted to a tag. Only the rule codes are read up front, and the
+/// q4 queries when they run in background workers.
+pub fn stream_violations(
+    profile: Option<&str>,
+    tag: Option<&str>,
+) -> Result<ViolationStream, String> {
+    let codes: Vec<String> = select_rules(profile, tag, None)?
+        .into_iter()
+        .map(|(code, _, _)| code)
+        .collect();
+
+    let mut rules: Vec<(String, Option<usize>)> = codes.into_iter().map(|c| (c, None)).collect();
+    let mut workers = None;
+    if crate::parallel::max_workers() > 0 {
+        let mut tasks = Vec::new();
+        for (code, task) in rules.iter_mut() {
+            if let Some(q4_sql) = rule_q4(code).ok().flatten() {
+                *task = Some(tasks.len());
+                tasks.push((code.clone(), templates::described_query(&q4_sql)));
+            }
         }
+        workers = crate::parallel::ParallelRun::start(&tasks, true);
+    }
+    Ok(ViolationStream::new(rules, workers))
+}
 
-        let sql = "SELECT type, schema, name, identity FROM pg_catalog.pg_identify_object($1::oid, $2::oid, $3)";
-        let result: Result<Option<(String, String, String, String)>, spi::SpiError> =
+impl ViolationStream {
+    /// Streams the rules in order, the rules with a task reading their rows from
+    /// `workers`.
+    pub fn new(
+        rules: Vec<(String, Option<usize>)>,
+        workers: Option<crate::parallel::ParallelRun>,
+    ) -> Self {
+        ViolationStream {
+            rules: rules.into_iter(),
+            workers,
+            code: String::new(),
+            message: None,
+            source: None,
+            batch: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Opens a cursor on the q4 query of `rule_code`, or returns `None` when the rule
+    /// has no q4.
+    fn open(rule_code: &str) -> Option<RowSource> {
+        let cursor_name = in_subtransaction(|| {
+            let Some(q4_sql) = rule_q4(rule_code)? else {
+                return Ok(None);
+            };
+            let described_sql = templates::described_query(&q4_sql);
             Spi::connect(|client| {
-                let try_result = std::panic::catch_unwind(|| {
-                    let mut rows = client.select(
-                        sql,
-                        None,
-                        &[classid.into(), objid.into(), objsubid.into()],
-                    )?;
-                    if let Some(row) = rows.next() {
-                        let type_: Option<String> = row.get(1)?;
-                        let schema: Option<String> = row.get(2)?;
-                        let name: Option<String> = row.get(3)?;
-                        let identity: Option<String> = row.get(4)?;
-                        Ok(type_
-                            .zip(schema)
-                            .zip(name)
-                            .zip(identity)
-                            .map(|(((t, s), n), i)| (t, s, n, i)))
-                    } else {
-                        Ok(None)
+                client
+                    .try_open_cursor(described_sql.as_str(), &[])
+                    .map(|cursor| Some(cursor.detach_into_name()))
+            })
+            .map_err(|e| format!("SPI error executing q4: {e}"))
+        });
+        match cursor_name {
+            Ok(cursor_name) => cursor_name.map(RowSource::Cursor),
+            Err(e) => {
+                pgrx::debug1!("stream_violations; Error for rule {}: {}", rule_code, e);
+                None
+            }
+        }
+    }
+
+    /// Fetches the next batch of the cursor. The cursor is closed once exhausted.
+    fn fetch(cursor_name: &str) -> Result<(Vec<DescribedViolation>, bool), String> {
+        in_subtransaction(|| {
+            Spi::connect(|client| {
+                // A cursor failing during the fetch is left to the transaction cleanup
+                let mut cursor = std::mem::ManuallyDrop::new(client.find_cursor(cursor_name)?);
+                let rows = cursor.fetch(STREAM_BATCH_SIZE)?;
+                let columns = if rows.is_empty() { 0 } else { rows.columns()? };
+                let mut violations = Vec::with_capacity(rows.len());
+                for row in rows {
+                    violations.push(read_described_violation(&row, columns)?);
+                }
+                let exhausted = (violations.len() as i64) < STREAM_BATCH_SIZE;
+                if exhausted {
+                    drop(std::mem::ManuallyDrop::into_inner(cursor));
+                }
+                Ok((violations, exhausted))
+            })
+            .map_err(|e: spi::SpiError| format!("SPI error executing q4: {e}"))
+        })
+    }
+
+    /// Reads the next batch of the current rule into `batch`, and returns where the
+    /// following one is read from.
+    fn read_batch(&mut self, source: RowSource) -> Option<RowSource> {
+        use crate::parallel::TaskMessage;
+        match source {
+            RowSource::Cursor(cursor_name) => match Self::fetch(&cursor_name) {
+                Ok((violations, exhausted)) => {
+                    self.batch.extend(violations);
+                    (!exhausted).then_some(RowSource::Cursor(cursor_name))
+                }
+                Err(e) => {
+                    pgrx::debug1!("stream_violations; Error for rule {}: {}", self.code, e);
+                    None
+                }
+            },
+            RowSource::Worker { task, received } => {
+                let workers = self.workers.as_mut()?;
+                match workers.receive(task) {
+                    TaskMessage::Rows(violations) => {
+                        self.batch.extend(violations);
+                        Some(RowSource::Worker {
+                            task,
+                            received: true,
+                        })
                     }
-                });
-                match try_result {
-                    Ok(inner) => inner,
-                    Err(_) => {
-                        pgrx::warning!(
-                            "pg_identify_object failed for classid={}, objid={}, objsubid={}",
-                            classid,
-                            objid,
-                            objsubid
-                        );
-                        Ok(None)
+                    TaskMessage::Done(_) => None,
+                    TaskMessage::Failed(e, _) => {
+                        pgrx::debug1!("stream_violations; Error for rule {}: {}", self.code, e);
+                        None
                     }
+                    // Some rows are already returned, the rule cannot be run again
+                    TaskMessage::Lost if received => pgrx::error!(
+                        "pglinter worker running rule {} exited before sending all its violations",
+                        self.code
+                    ),
+                    TaskMessage::Lost => Self::open(&self.code),
                 }
-            });
-        match result {
-            Ok(Some((type_, schema, name, _identity))) => {
-                format!("{type_} in schema: {schema} named: {name}")
             }
-            _ => {
-                pgrx::debug1!(
-                    "Could not resolve object name for classid={}, objid={}, objsubid={}",
-                    classid,
-                    objid,
-                    objsubid
+        }
+    }
+}
+
+impl Iterator for ViolationStream {
+    /// Rule code, violation and message
+    type Item = (String, Violation, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((violation, description)) = self.batch.pop_front() {
+                let (classid, objid, objsubid) = violation.location();
+                let message = RuleMessage::render_or_error(
+                    self.message.as_ref(),
+                    &self.code,
+                    templates::described_context(classid, objid, objsubid, description),
+                    violation.details.as_ref(),
                 );
-                format!(
-                    "classid={}, objid={}, objsubid={}",
-                    classid, objid, objsubid
-                )
+                return Some((self.code.clone(), violation, message));
+            }
+            if let Some(source) = self.source.take() {
+                self.source = self.read_batch(source);
+                continue;
             }
+            let (code, task) = self.rules.next()?;
+            self.code = code;
+            self.source = match task.filter(|_| self.workers.is_some()) {
+                Some(task) => Some(RowSource::Worker {
+                    task,
+                    received: false,
+                }),
+                None => Self::open(&self.code),
+            };
+            self.message = RuleMessage::find(&self.code);
         }
     }
+}
 
-    let object_name = resolve_object_name(classid, objid, objsubid);
-
-    // Replace placeholders in the message template
-    let msg = message_template
-        .replace("{object}", &object_name)
-        .replace("{0}", &object_name)
-        .replace("{classid}", &classid.to_string())
-        .replace("{objid}", &objid.to_string())
-        .replace("{objsubid}", &objsubid.to_string());
-
-    // Replace placeholders in the rule_msg JSON if present
-    let rule_msg_json_replaced = rule_msg_json.map(|mut json_val| {
-        // Recursively replace placeholders in all string values
-        fn replace_in_json(
-            val: &mut serde_json::Value,
-            object_name: &str,
-            classid: i32,
-            objid: i32,
-            objsubid: i32,
-        ) {
-            match val {
-                serde_json::Value::String(s) => {
-                    let replaced = s
-                        .replace("{object}", object_name)
-                        .replace("{0}", object_name)
-                        .replace("{classid}", &classid.to_string())
-                        .replace("{objid}", &objid.to_string())
-                        .replace("{objsubid}", &objsubid.to_string());
-                    *s = replaced;
-                }
-                serde_json::Value::Array(arr) => {
-                    for v in arr.iter_mut() {
-                        replace_in_json(v, object_name, classid, objid, objsubid);
-                    }
-                }
-                serde_json::Value::Object(map) => {
-                    for v in map.values_mut() {
-                        replace_in_json(v, object_name, classid, objid, objsubid);
-                    }
-                }
-                _ => {}
+/// Executes the q1 query of a rule in a subtransaction and returns the number of
+/// objects the rule checks, or `None` when the rule declares no q1.
+pub fn count_checked_objects(q1_sql: Option<&str>) -> Result<Option<i64>, String> {
+    let q1_sql = match q1_sql {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return Ok(None),
+    };
+
+    // The cast lets a q1 returning int or numeric be read as bigint
+    let count_sql = format!("SELECT ({}\n)::bigint", q1_sql.trim().trim_end_matches(';'));
+    in_subtransaction(move || {
+        Spi::get_one::<i64>(&count_sql).map_err(|e| format!("SPI error executing q1: {e}"))
+    })
+}
+
+/// Checks, without fetching any row, that a q4 query runs and returns the
+/// (classid, objid, objsubid[, details]) columns expected by get_violations_for_rule.
+pub fn validate_q4(q4_sql: &str) -> Result<(), String> {
+    use pgrx::pg_sys::{INT4OID, JSONBOID, OIDOID, REGCLASSOID};
+
+    let probe_sql = format!(
+        "SELECT * FROM ({}\n) AS q4 LIMIT 0",
+        q4_sql.trim().trim_end_matches(';')
+    );
+    let column_types = in_subtransaction(move || {
+        Spi::connect(|client| {
+            let table = client.select(&probe_sql, None, &[])?;
+            let mut types = Vec::new();
+            for ordinal in 1..=table.columns()? {
+                types.push(table.column_type_oid(ordinal)?.value());
             }
+            Ok(types)
+        })
+        .map_err(|e: spi::SpiError| format!("SPI error: {e}"))
+    })
+    .map_err(|e| format!("q4 query is invalid: {e}"))?;
+
+    let expected = [
+        ("classid", "oid", [OIDOID, REGCLASSOID, INT4OID].as_slice()),
+        ("objid", "oid", [OIDOID, INT4OID].as_slice()),
+        ("objsubid", "integer", [INT4OID].as_slice()),
+        ("details", "jsonb", [JSONBOID].as_slice()),
+    ];
+    if !(3..=4).contains(&column_types.len()) {
+        return Err(format!(
+            "q4 query must return 3 columns (classid, objid, objsubid) and an optional \
+             details column, it returns {}",
+            column_types.len()
+        ));
+    }
+    for ((name, type_name, accepted), type_oid) in expected.iter().zip(column_types) {
+        if !accepted.contains(&type_oid) {
+            return Err(format!(
+                "q4 column {name} must be of type {type_name} (got type oid {})",
+                u32::from(type_oid)
+            ));
         }
-        replace_in_json(&mut json_val, &object_name, classid, objid, objsubid);
-        json_val
-    });
+    }
+    Ok(())
+}
 
-    // Return both as a JSON string for now (could be a struct if needed)
-    match rule_msg_json_replaced {
-        Some(json_val) => serde_json::json!({
-            "rule_msg": json_val
+/// The message of a rule, read once and rendered for each of its violations
+pub struct RuleMessage {
+    /// The rule_msg in the language of the session
+    rule_msg: Option<serde_json::Value>,
+    message: Option<String>,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RuleMessage {
+    pub fn load(rule_id: &str) -> Result<Self, String> {
+        let query = "
+            SELECT (SELECT rule_msg::TEXT FROM pglinter.rule_messages WHERE code = $1 LIMIT 1),
+                   r.message, r.name, r.warning_level, r.error_level,
+                   current_setting('lc_messages')
+            FROM (SELECT $1::TEXT AS code) c
+            LEFT JOIN pglinter.rules r ON r.code = c.code";
+        type MessageRow = (
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<i32>,
+            Option<i32>,
+            Option<String>,
+        );
+        let (rule_msg, message, rule_name, warning_level, error_level, lc_messages) =
+            Spi::connect(|client| {
+                let row = client.select(query, None, &[rule_id.into()])?.first();
+                Ok::<MessageRow, spi::SpiError>((
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let languages = templates::preferred_languages(lc_messages.as_deref());
+        let rule_msg = rule_msg
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .map(|rule_msg| templates::localize(rule_msg, &languages));
+        let fields = serde_json::json!({
+            "rule_code": rule_id,
+            "rule_name": rule_name,
+            "warning_level": warning_level,
+            "error_level": error_level,
+        });
+        Ok(RuleMessage {
+            rule_msg,
+            message,
+            fields: fields.as_object().cloned().unwrap_or_default(),
         })
-        .to_string(),
-        None => msg,
     }
+
+    /// Renders the message for one violation, given the placeholders of its object
+    pub fn render(
+        &self,
+        object: templates::TemplateContext,
+        details: Option<&serde_json::Value>,
+    ) -> String {
+        let context = object.with_fields(&self.fields).with_details(details);
+        match &self.rule_msg {
+            Some(rule_msg) => {
+                let mut rule_msg = rule_msg.clone();
+                context.render_json(&mut rule_msg);
+                serde_json::json!({
+                    "rule_msg": rule_msg
+                })
+                .to_string()
+            }
+            None => context.render(self.message.as_deref().unwrap_or_default()),
+        }
+    }
+
+    /// Reads the message of a rule, `None` when it cannot be read
+    pub fn find(rule_id: &str) -> Option<Self> {
+        match Self::load(rule_id) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                pgrx::debug1!("Failed to get rule_msg for {}: {}", rule_id, e);
+                None
+            }
+        }
+    }
+
+    /// Renders the message of a violation, or the placeholder shown when the
+    /// message of its rule cannot be read
+    pub fn render_or_error(
+        message: Option<&Self>,
+        rule_id: &str,
+        object: templates::TemplateContext,
+        details: Option<&serde_json::Value>,
+    ) -> String {
+        match message {
+            Some(message) => message.render(object, details),
+            None => Self::error_message(rule_id),
+        }
+    }
+
+    fn error_message(rule_id: &str) -> String {
+        format!("[pglinter: error fetching rule message for {}]", rule_id)
+    }
+}
+
+/// Renders the message of a rule for one violation: the rule_msg of the rule in
+/// the language of the session, or its message when it has none, with the
+/// placeholders of the object and of the details returned by q4 replaced.
+#[cfg(any(test, feature = "pg_test"))]
+pub fn get_sanitized_message(
+    rule_id: &str,
+    classid: i32,
+    objid: i32,
+    objsubid: i32,
+    details: Option<&serde_json::Value>,
+) -> String {
+    match RuleMessage::load(rule_id) {
+        Ok(message) => {
+            let object = templates::object_context(classid, objid, objsubid);
+            message.render(object, details)
+        }
+        Err(e) => {
+            pgrx::debug1!(
+                "get_sanitized_message; Failed to get rule_msg for {}: {}",
+                rule_id,
+                e
+            );
+            RuleMessage::error_message(rule_id)
+        }
+    }
+}
+
+/// Legacy rule rows may use pg_attribute (1249) for column references.
+/// pg_identify_object expects relation columns as (pg_class, relid, attnum).
+pub fn normalize_location(classid: i32, objid: i32, objsubid: i32) -> ViolationLocation {
+    if classid == 1249 && objsubid != 0 {
+        (1259, objid, objsubid)
+    } else {
+        (classid, objid, objsubid)
+    }
+}
+
+/// Resolves classid, objid, objsubid to the object identity known by PostgreSQL.
+pub fn identify_object(classid: i32, objid: i32, objsubid: i32) -> Option<ObjectIdentity> {
+    let (classid, objid, objsubid) = normalize_location(classid, objid, objsubid);
+
+    let sql = "SELECT type, schema, name, identity FROM pg_catalog.pg_identify_object($1::oid, $2::oid, $3)";
+    let result: Result<Option<ObjectIdentity>, spi::SpiError> = Spi::connect(|client| {
+        let try_result = std::panic::catch_unwind(|| {
+            let mut rows =
+                client.select(sql, None, &[classid.into(), objid.into(), objsubid.into()])?;
+            if let Some(row) = rows.next() {
+                let object_type: Option<String> = row.get(1)?;
+                let schema: Option<String> = row.get(2)?;
+                let name: Option<String> = row.get(3)?;
+                let identity: Option<String> = row.get(4)?;
+                Ok(object_type
+                    .zip(identity)
+                    .map(|(object_type, identity)| ObjectIdentity {
+                        object_type,
+                        schema,
+                        name,
+                        identity,
+                    }))
+            } else {
+                Ok(None)
+            }
+        });
+        match try_result {
+            Ok(inner) => inner,
+            Err(_) => {
+                pgrx::warning!(
+                    "pg_identify_object failed for classid={}, objid={}, objsubid={}",
+                    classid,
+                    objid,
+                    objsubid
+                );
+                Ok(None)
+            }
+        }
+    });
+    result.ok().flatten()
 }
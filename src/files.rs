@@ -0,0 +1,108 @@
+use crate::privileges::caller_has_privs_of;
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::pg_sys;
+use std::ffi::CString;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory holding the files read and written by the *_file functions.
+/// File access is disabled while it is not set.
+pub static FILE_DIRECTORY: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub fn init_gucs() {
+    GucRegistry::define_string_guc(
+        c"pglinter.file_directory",
+        c"Directory for the files imported and exported by pglinter",
+        c"The *_file functions only read and write files in this directory. \
+          When empty, they are disabled. Only superusers can change it.",
+        &FILE_DIRECTORY,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileAccess {
+    Read,
+    Write,
+}
+
+impl FileAccess {
+    fn role(self) -> (pg_sys::Oid, &'static str) {
+        match self {
+            FileAccess::Read => (
+                pg_sys::Oid::from(pg_sys::ROLE_PG_READ_SERVER_FILES),
+                "pg_read_server_files",
+            ),
+            FileAccess::Write => (
+                pg_sys::Oid::from(pg_sys::ROLE_PG_WRITE_SERVER_FILES),
+                "pg_write_server_files",
+            ),
+        }
+    }
+}
+
+fn check_privilege(access: FileAccess) -> Result<(), String> {
+    let (role, role_name) = access.role();
+    if caller_has_privs_of(role) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Permission denied: file access requires membership in {}",
+            role_name
+        ))
+    }
+}
+
+/// Resolve `file_path` inside the directory set by `pglinter.file_directory`.
+/// Relative paths are taken from that directory. Paths going outside of it,
+/// through `..` or a symbolic link, are rejected.
+pub fn resolve_path(file_path: &str, access: FileAccess) -> Result<PathBuf, String> {
+    check_privilege(access)?;
+
+    let directory = FILE_DIRECTORY
+        .get()
+        .and_then(|dir| dir.into_string().ok())
+        .filter(|dir| !dir.trim().is_empty())
+        .ok_or("File access is disabled: pglinter.file_directory is not set")?;
+    let directory = Path::new(&directory)
+        .canonicalize()
+        .map_err(|e| format!("Invalid pglinter.file_directory '{}': {}", directory, e))?;
+
+    let path = Path::new(file_path);
+    if file_path.trim().is_empty() || path.file_name().is_none() {
+        return Err(format!("Invalid file path '{}'", file_path));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!(
+            "Invalid file path '{}': '..' is not allowed",
+            file_path
+        ));
+    }
+
+    let outside = || {
+        format!(
+            "Invalid file path '{}': outside of pglinter.file_directory",
+            file_path
+        )
+    };
+    let path = directory.join(path);
+    let parent = path
+        .parent()
+        .ok_or_else(outside)?
+        .canonicalize()
+        .map_err(|e| format!("Invalid file path '{}': {}", file_path, e))?;
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        // The file does not exist yet, and is not a dangling symbolic link
+        Err(_) if std::fs::symlink_metadata(&path).is_err() => {
+            parent.join(path.file_name().ok_or_else(outside)?)
+        }
+        Err(_) => return Err(outside()),
+    };
+
+    if resolved.starts_with(&directory) {
+        Ok(resolved)
+    } else {
+        Err(outside())
+    }
+}
@@ -0,0 +1,395 @@
+use crate::execute_rules::{
+    get_violations_for_rule, identify_object, in_subtransaction, run_rules, Violation,
+};
+use crate::privileges;
+use pgrx::prelude::*;
+use std::fmt;
+
+/// How likely a fix is to disturb the applications using the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Risk {
+    /// Adds an object without blocking writes
+    Low,
+    /// Blocks writes until the transaction commits, or changes privileges or
+    /// ownership, applications may lose access
+    Medium,
+    /// Removes an object
+    High,
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Risk::Low => "low",
+            Risk::Medium => "medium",
+            Risk::High => "high",
+        })
+    }
+}
+
+/// How the violations of some rules can be fixed. `sql` builds the statement
+/// for one violation from its objid ($1), or returns no row when the object
+/// cannot be fixed automatically. `lock` is the lock taken by the statement
+/// when apply_fixes runs it, without CONCURRENTLY.
+struct FixKind {
+    codes: &'static [&'static str],
+    risk: Risk,
+    destructive: bool,
+    lock: Option<&'static str>,
+    sql: &'static str,
+}
+
+const FIX_KINDS: [FixKind; 5] = [
+    // Indexes backing a constraint cannot be dropped on their own
+    FixKind {
+        codes: &["B002", "B004"],
+        risk: Risk::High,
+        destructive: true,
+        lock: Some("ACCESS EXCLUSIVE"),
+        sql: "
+            SELECT format('DROP INDEX CONCURRENTLY %I.%I;', n.nspname, c.relname)
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.oid = $1 AND c.relkind = 'i'
+              AND NOT EXISTS (
+                  SELECT 1 FROM pg_catalog.pg_constraint WHERE conindid = c.oid
+              )",
+    },
+    FixKind {
+        codes: &["B003"],
+        risk: Risk::Low,
+        destructive: false,
+        lock: Some("SHARE"),
+        sql: "
+            SELECT format('CREATE INDEX CONCURRENTLY ON %I.%I (%s);', n.nspname, c.relname, (
+                SELECT string_agg(quote_ident(a.attname), ', ' ORDER BY k.position)
+                FROM unnest(con.conkey) WITH ORDINALITY AS k (attnum, position)
+                JOIN pg_catalog.pg_attribute a
+                    ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+            ))
+            FROM pg_catalog.pg_constraint con
+            JOIN pg_catalog.pg_class c ON c.oid = con.conrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE con.oid = $1 AND con.contype = 'f'",
+    },
+    FixKind {
+        codes: &["S003"],
+        risk: Risk::Medium,
+        destructive: false,
+        lock: None,
+        sql: "
+            SELECT format('REVOKE CREATE ON SCHEMA %I FROM PUBLIC;', nspname)
+            FROM pg_catalog.pg_namespace
+            WHERE oid = $1",
+    },
+    // The table is given to the owner of its schema
+    FixKind {
+        codes: &["S005"],
+        risk: Risk::Medium,
+        destructive: false,
+        lock: Some("ACCESS EXCLUSIVE"),
+        sql: "
+            SELECT format('ALTER TABLE %I.%I OWNER TO %I;',
+                          n.nspname, c.relname, pg_catalog.pg_get_userbyid(n.nspowner))
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.oid = $1 AND c.relowner <> n.nspowner",
+    },
+    // The table is given to the role owning most tables of its schema
+    FixKind {
+        codes: &["B011"],
+        risk: Risk::Medium,
+        destructive: false,
+        lock: Some("ACCESS EXCLUSIVE"),
+        sql: "
+            SELECT format('ALTER TABLE %I.%I OWNER TO %I;',
+                          n.nspname, c.relname, pg_catalog.pg_get_userbyid(o.relowner))
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            CROSS JOIN LATERAL (
+                SELECT t.relowner
+                FROM pg_catalog.pg_class t
+                WHERE t.relnamespace = c.relnamespace AND t.relkind IN ('r', 'p')
+                GROUP BY t.relowner
+                ORDER BY count(*) DESC, pg_catalog.pg_get_userbyid(t.relowner)
+                LIMIT 1
+            ) o
+            WHERE c.oid = $1 AND c.relowner <> o.relowner",
+    },
+];
+
+fn fix_kind(rule_code: &str) -> Option<&'static FixKind> {
+    FIX_KINDS
+        .iter()
+        .find(|kind| kind.codes.contains(&rule_code))
+}
+
+/// Codes of the rules whose violations can be fixed automatically
+pub fn fixable_rules() -> Vec<&'static str> {
+    FIX_KINDS
+        .iter()
+        .flat_map(|kind| kind.codes.iter().copied())
+        .collect()
+}
+
+/// A statement fixing one violation
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub rule_code: String,
+    pub object: String,
+    pub statement: String,
+    pub risk: Risk,
+    pub destructive: bool,
+}
+
+impl Fix {
+    /// Whether the statement cannot run inside a transaction block
+    pub fn is_concurrent(&self) -> bool {
+        self.statement.contains(" CONCURRENTLY ")
+    }
+
+    /// The statement without CONCURRENTLY, as it can run from a function
+    pub fn transactional_statement(&self) -> String {
+        self.statement.replacen(" CONCURRENTLY ", " ", 1)
+    }
+}
+
+fn build_fix(
+    rule_code: &str,
+    kind: &FixKind,
+    violation: &Violation,
+) -> Result<Option<Fix>, String> {
+    let objid = pg_sys::Oid::from(violation.objid as u32);
+    let statement =
+        Spi::get_one_with_args::<String>(kind.sql, &[objid.into()]).or_else(|e| match e {
+            spi::SpiError::InvalidPosition => Ok(None),
+            e => Err(format!(
+                "Database error building the fix for {rule_code}: {e}"
+            )),
+        })?;
+    let Some(statement) = statement else {
+        return Ok(None);
+    };
+    let (classid, objid, objsubid) = violation.location();
+    let object = match identify_object(classid, objid, objsubid) {
+        Some(object) => format!("{} {}", object.object_type, object.identity),
+        None => format!("classid={classid}, objid={objid}, objsubid={objsubid}"),
+    };
+    Ok(Some(Fix {
+        rule_code: rule_code.to_string(),
+        object,
+        statement,
+        risk: kind.risk,
+        destructive: kind.destructive,
+    }))
+}
+
+/// Builds the fixes of the violations of `rule_code`, or of every enabled rule
+/// that can be fixed automatically. Violations reported twice are fixed once.
+pub fn generate_fixes(rule_code: Option<&str>) -> Result<Vec<Fix>, String> {
+    let runs: Vec<(String, Vec<Violation>)> = match rule_code {
+        Some(code) => {
+            let code = code.to_uppercase();
+            let exists = Spi::get_one_with_args::<bool>(
+                "SELECT EXISTS (SELECT 1 FROM pglinter.rules WHERE code = $1)",
+                &[code.as_str().into()],
+            )
+            .map_err(|e| format!("Database error: {e}"))?
+            .unwrap_or(false);
+            if !exists {
+                return Err(format!("Rule '{code}' not found"));
+            }
+            if fix_kind(&code).is_none() {
+                return Err(format!(
+                    "No automated fix is available for rule {code} (available for {})",
+                    fixable_rules().join(", ")
+                ));
+            }
+            let violations = in_subtransaction(|| get_violations_for_rule(&code))?;
+            vec![(code, violations)]
+        }
+        // Only the rules that can be fixed are run
+        None => run_rules(None, None, Some(&fixable_rules()), false)?
+            .into_iter()
+            .filter_map(|run| match run.violations {
+                Ok(violations) => {
+                    Some((run.code, violations.into_iter().map(|(v, _)| v).collect()))
+                }
+                Err(e) => {
+                    pgrx::warning!("Rule {} skipped from fixes: {}", run.code, e);
+                    None
+                }
+            })
+            .collect(),
+    };
+
+    let mut fixes: Vec<Fix> = Vec::new();
+    for (code, violations) in runs {
+        let Some(kind) = fix_kind(&code) else {
+            continue;
+        };
+        for violation in &violations {
+            if let Some(fix) = build_fix(&code, kind, violation)? {
+                if !fixes.iter().any(|f| f.statement == fix.statement) {
+                    fixes.push(fix);
+                }
+            }
+        }
+    }
+    Ok(fixes)
+}
+
+/// Renders the fixes as a SQL script, each statement preceded by its rule,
+/// object and risk.
+pub fn generate_fix_script(rule_code: Option<&str>) -> Result<String, String> {
+    let fixes = generate_fixes(rule_code)?;
+    let mut script = String::from("-- pglinter fix script\n");
+    script.push_str(&format!(
+        "-- Generated at {}\n",
+        chrono::Utc::now().to_rfc3339()
+    ));
+    if fixes.is_empty() {
+        script.push_str("-- No violation can be fixed automatically.\n");
+        return Ok(script);
+    }
+    if fixes.iter().any(Fix::is_concurrent) {
+        script.push_str("-- CONCURRENTLY statements cannot run inside a transaction block.\n");
+    }
+    for fix in &fixes {
+        script.push_str(&format!(
+            "\n-- {}: {} (risk: {}{})\n{}\n",
+            fix.rule_code,
+            fix.object,
+            fix.risk,
+            if fix.destructive { ", destructive" } else { "" },
+            fix.statement
+        ));
+    }
+    Ok(script)
+}
+
+/// What happened to a fix passed to apply_fixes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixStatus {
+    DryRun,
+    Applied,
+    Failed,
+    Skipped,
+}
+
+impl fmt::Display for FixStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FixStatus::DryRun => "dry-run",
+            FixStatus::Applied => "applied",
+            FixStatus::Failed => "failed",
+            FixStatus::Skipped => "skipped",
+        })
+    }
+}
+
+/// A fix with the statement run for it and its outcome
+#[derive(Debug, Clone)]
+pub struct FixOutcome {
+    pub fix: Fix,
+    pub statement: String,
+    pub status: FixStatus,
+    pub detail: Option<String>,
+}
+
+/// Runs the fixes of `rule_codes`, or of every enabled rule that can be fixed,
+/// one statement at a time. Each statement runs in its own subtransaction with
+/// the privileges of the caller and the given lock_timeout, so a failing fix
+/// does not stop the others. Destructive fixes are skipped unless allowed,
+/// nothing is executed in dry-run mode.
+///
+/// The locks taken by the statements are held until the calling transaction
+/// commits, the detail of each fix tells which one. CONCURRENTLY cannot run
+/// from a function, so those statements block writes and are at least of
+/// medium risk.
+pub fn apply_fixes(
+    rule_codes: Option<Vec<String>>,
+    dry_run: bool,
+    allow_destructive: bool,
+    lock_timeout: &str,
+) -> Result<Vec<FixOutcome>, String> {
+    let fixes = match rule_codes {
+        Some(codes) => {
+            // Rules may report the same object, e.g. B002 and B004 for an index
+            let mut fixes: Vec<Fix> = Vec::new();
+            for code in codes {
+                for fix in generate_fixes(Some(&code))? {
+                    if !fixes.iter().any(|f| f.statement == fix.statement) {
+                        fixes.push(fix);
+                    }
+                }
+            }
+            fixes
+        }
+        None => generate_fixes(None)?,
+    };
+
+    let previous_timeout = Spi::get_one::<String>("SELECT current_setting('lock_timeout')")
+        .map_err(|e| format!("Database error: {e}"))?
+        .unwrap_or_default();
+    let set_timeout = |value: String| {
+        in_subtransaction(move || {
+            Spi::run_with_args(
+                "SELECT set_config('lock_timeout', $1, TRUE)",
+                &[value.as_str().into()],
+            )
+            .map_err(|e| format!("Database error: {e}"))
+        })
+    };
+    if !dry_run {
+        set_timeout(lock_timeout.to_string())
+            .map_err(|e| format!("Invalid lock_timeout '{lock_timeout}': {e}"))?;
+    }
+
+    let mut outcomes = Vec::with_capacity(fixes.len());
+    for mut fix in fixes {
+        let statement = fix.transactional_statement();
+        let lock = fix_kind(&fix.rule_code).and_then(|kind| kind.lock);
+        let lock_detail = lock.map(|lock| {
+            format!(
+                "{}{lock} lock held until the transaction commits",
+                if fix.is_concurrent() {
+                    "run without CONCURRENTLY, "
+                } else {
+                    ""
+                }
+            )
+        });
+        if fix.is_concurrent() {
+            fix.risk = fix.risk.max(Risk::Medium);
+        }
+        let (status, detail) = if fix.destructive && !allow_destructive {
+            (
+                FixStatus::Skipped,
+                Some("destructive fix, pass allow_destructive => true to apply it".to_string()),
+            )
+        } else if dry_run {
+            (FixStatus::DryRun, lock_detail)
+        } else {
+            let sql = statement.clone();
+            let result: Result<(), String> = in_subtransaction(move || {
+                privileges::as_caller(|| Spi::run(&sql).map_err(|e| format!("{e}")))
+            });
+            match result {
+                Ok(()) => (FixStatus::Applied, lock_detail),
+                Err(e) => (FixStatus::Failed, Some(e)),
+            }
+        };
+        outcomes.push(FixOutcome {
+            fix,
+            statement,
+            status,
+            detail,
+        });
+    }
+
+    if !dry_run {
+        set_timeout(previous_timeout)?;
+    }
+    Ok(outcomes)
+}
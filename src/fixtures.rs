@@ -15,6 +15,18 @@ pub fn setup_test_rule(code: &str, id: i32, name: &str, enabled: bool) {
     ));
 }
 
+/// Setup function for creating an enabled test rule with a scope and a q4 query
+pub fn setup_test_rule_with_q4(code: &str, id: i32, name: &str, scope: &str, q4: &str) {
+    let _ = Spi::run(&format!(
+        "DELETE FROM pglinter.rules WHERE code = '{}'",
+        code
+    ));
+    let _ = Spi::run(&format!(
+        "INSERT INTO pglinter.rules (id, code, name, enable, scope, q4) VALUES ({}, '{}', '{}', true, '{}', $q${}$q$)",
+        id, code, name, scope, q4
+    ));
+}
+
 /// Generic cleanup function for removing test rules
 pub fn cleanup_test_rule(code: &str) {
     let _ = Spi::run(&format!(
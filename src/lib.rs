@@ -1,8 +1,17 @@
 use pgrx::pgrx_macros::extension_sql_file;
 use pgrx::prelude::*;
 
+mod audit;
+mod bundle;
 mod execute_rules;
+mod files;
+mod fixes;
 mod manage_rules;
+mod parallel;
+mod privileges;
+mod profiles;
+mod reports;
+mod templates;
 
 #[cfg(any(test, feature = "pg_test"))]
 mod fixtures;
@@ -11,6 +20,13 @@ extension_sql_file!("../sql/rules.sql", name = "pglinter", finalize);
 
 ::pgrx::pg_module_magic!();
 
+#[pg_guard]
+pub extern "C-unwind" fn _PG_init() {
+    files::init_gucs();
+    parallel::init_gucs();
+    templates::init_gucs();
+}
+
 #[pg_extern]
 fn hello_pglinter() -> &'static str {
     "Hello, pglinter"
@@ -18,13 +34,20 @@ fn hello_pglinter() -> &'static str {
 
 #[pg_schema]
 mod pglinter {
+    use crate::audit;
+    use crate::bundle;
+    use crate::fixes;
     use crate::manage_rules;
+    use crate::privileges;
+    use crate::profiles;
+    use crate::reports;
     use pgrx::prelude::*;
 
     // Rule management functions
     #[pg_extern(security_definer)]
     fn enable_rule(rule_code: &str) -> Option<bool> {
-        match manage_rules::enable_rule(rule_code) {
+        privileges::require_admin("enable_rule");
+        match audit::tracked("enable_rule", || manage_rules::enable_rule(rule_code)) {
             Ok(success) => Some(success),
             Err(e) => {
                 pgrx::warning!("Failed to enable rule {}: {}", rule_code, e);
@@ -35,7 +58,8 @@ mod pglinter {
 
     #[pg_extern(security_definer)]
     fn disable_rule(rule_code: &str) -> Option<bool> {
-        match manage_rules::disable_rule(rule_code) {
+        privileges::require_admin("disable_rule");
+        match audit::tracked("disable_rule", || manage_rules::disable_rule(rule_code)) {
             Ok(success) => Some(success),
             Err(e) => {
                 pgrx::warning!("Failed to disable rule {}: {}", rule_code, e);
@@ -66,6 +90,115 @@ mod pglinter {
         }
     }
 
+    #[pg_extern(security_definer)]
+    fn update_rule_levels(
+        rule_code: &str,
+        warning_level: Option<i32>,
+        error_level: Option<i32>,
+    ) -> Option<bool> {
+        privileges::require_admin("update_rule_levels");
+        match audit::tracked("update_rule_levels", || {
+            manage_rules::update_rule_levels(rule_code, warning_level, error_level)
+        }) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to update levels of rule {}: {}", rule_code, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_rule_levels(rule_code: &str) -> Option<String> {
+        match manage_rules::get_rule_levels(rule_code) {
+            Ok(levels) => Some(levels),
+            Err(e) => {
+                pgrx::warning!("Failed to get levels of rule {}: {}", rule_code, e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    #[allow(clippy::too_many_arguments)]
+    fn create_rule(
+        rule_code: &str,
+        name: &str,
+        scope: &str,
+        q4: &str,
+        message: &str,
+        severity: default!(&str, "'WARNING'"),
+        fixes: default!(Option<Vec<String>>, "NULL"),
+        description: default!(Option<&str>, "NULL"),
+        tags: default!(Option<Vec<String>>, "NULL"),
+    ) -> Option<bool> {
+        privileges::require_admin("create_rule");
+        let rule = manage_rules::RuleDefinition {
+            code: rule_code,
+            name: Some(name),
+            scope: Some(scope),
+            q4: Some(q4),
+            message: Some(message),
+            severity: Some(severity),
+            fixes,
+            description,
+            tags,
+        };
+        match audit::tracked("create_rule", || manage_rules::create_rule(rule)) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to create rule {}: {}", rule_code, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    #[allow(clippy::too_many_arguments)]
+    fn update_rule(
+        rule_code: &str,
+        name: default!(Option<&str>, "NULL"),
+        scope: default!(Option<&str>, "NULL"),
+        q4: default!(Option<&str>, "NULL"),
+        message: default!(Option<&str>, "NULL"),
+        severity: default!(Option<&str>, "NULL"),
+        fixes: default!(Option<Vec<String>>, "NULL"),
+        description: default!(Option<&str>, "NULL"),
+        tags: default!(Option<Vec<String>>, "NULL"),
+    ) -> Option<bool> {
+        privileges::require_admin("update_rule");
+        let rule = manage_rules::RuleDefinition {
+            code: rule_code,
+            name,
+            scope,
+            q4,
+            message,
+            severity,
+            fixes,
+            description,
+            tags,
+        };
+        match audit::tracked("update_rule", || manage_rules::update_rule(rule)) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to update rule {}: {}", rule_code, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn delete_rule(rule_code: &str) -> Option<bool> {
+        privileges::require_admin("delete_rule");
+        match audit::tracked("delete_rule", || manage_rules::delete_rule(rule_code)) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to delete rule {}: {}", rule_code, e);
+                Some(false)
+            }
+        }
+    }
+
     #[pg_extern(security_definer)]
     fn explain_rule(rule_code: &str) -> Option<bool> {
         match manage_rules::explain_rule(rule_code) {
@@ -82,7 +215,8 @@ mod pglinter {
 
     #[pg_extern(security_definer)]
     fn enable_all_rules() -> Option<i32> {
-        match manage_rules::enable_all_rules() {
+        privileges::require_admin("enable_all_rules");
+        match audit::tracked("enable_all_rules", manage_rules::enable_all_rules) {
             Ok(count) => Some(count as i32),
             Err(e) => {
                 pgrx::warning!("Failed to enable all rules: {}", e);
@@ -93,7 +227,8 @@ mod pglinter {
 
     #[pg_extern(security_definer)]
     fn disable_all_rules() -> Option<i32> {
-        match manage_rules::disable_all_rules() {
+        privileges::require_admin("disable_all_rules");
+        match audit::tracked("disable_all_rules", manage_rules::disable_all_rules) {
             Ok(count) => Some(count as i32),
             Err(e) => {
                 pgrx::warning!("Failed to disable all rules: {}", e);
@@ -102,6 +237,62 @@ mod pglinter {
         }
     }
 
+    #[pg_extern(security_definer)]
+    fn enable_rules_by_tag(tag: &str) -> Option<i32> {
+        privileges::require_admin("enable_rules_by_tag");
+        match audit::tracked("enable_rules_by_tag", || {
+            manage_rules::enable_rules_by_tag(tag)
+        }) {
+            Ok(count) => Some(count as i32),
+            Err(e) => {
+                pgrx::warning!("Failed to enable rules by tag {}: {}", tag, e);
+                Some(-1)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn enable_rules_by_scope(scope: &str) -> Option<i32> {
+        privileges::require_admin("enable_rules_by_scope");
+        match audit::tracked("enable_rules_by_scope", || {
+            manage_rules::enable_rules_by_scope(scope)
+        }) {
+            Ok(count) => Some(count as i32),
+            Err(e) => {
+                pgrx::warning!("Failed to enable rules by scope {}: {}", scope, e);
+                Some(-1)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn disable_rules_by_tag(tag: &str) -> Option<i32> {
+        privileges::require_admin("disable_rules_by_tag");
+        match audit::tracked("disable_rules_by_tag", || {
+            manage_rules::disable_rules_by_tag(tag)
+        }) {
+            Ok(count) => Some(count as i32),
+            Err(e) => {
+                pgrx::warning!("Failed to disable rules by tag {}: {}", tag, e);
+                Some(-1)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn disable_rules_by_scope(scope: &str) -> Option<i32> {
+        privileges::require_admin("disable_rules_by_scope");
+        match audit::tracked("disable_rules_by_scope", || {
+            manage_rules::disable_rules_by_scope(scope)
+        }) {
+            Ok(count) => Some(count as i32),
+            Err(e) => {
+                pgrx::warning!("Failed to disable rules by scope {}: {}", scope, e);
+                Some(-1)
+            }
+        }
+    }
+
     #[pg_extern(security_definer)]
     fn show_rule_queries(rule_code: &str) -> Option<String> {
         match manage_rules::show_rule_queries(rule_code) {
@@ -124,6 +315,20 @@ mod pglinter {
         }
     }
 
+    /// Check constraint of pglinter.rule_messages, raises an error describing
+    /// what is wrong with an invalid rule_msg
+    #[pg_extern(immutable, parallel_safe)]
+    fn check_rule_msg(rule_msg: pgrx::JsonB) -> bool {
+        if let Err(e) = manage_rules::validate_rule_msg(&rule_msg.0) {
+            ereport!(
+                ERROR,
+                PgSqlErrorCode::ERRCODE_CHECK_VIOLATION,
+                format!("invalid rule_msg: {}", e)
+            );
+        }
+        true
+    }
+
     #[pg_extern(security_definer)]
     fn export_rulemessages_to_yaml() -> Option<String> {
         match manage_rules::export_rule_messages_to_yaml() {
@@ -137,6 +342,7 @@ mod pglinter {
 
     #[pg_extern(security_definer)]
     fn export_rules_to_file(file_path: &str) -> Option<String> {
+        privileges::require_admin("export_rules_to_file");
         match manage_rules::export_rules_to_file(file_path) {
             Ok(result) => Some(result.to_string()),
             Err(e) => {
@@ -146,39 +352,243 @@ mod pglinter {
         }
     }
 
+    /// Raises the errors of a failed import, listing them all in the detail
+    fn raise_import_errors(errors: manage_rules::ImportErrors) -> ! {
+        let message = format!("Failed to import: {}", errors);
+        if errors.0.len() > 1 {
+            ereport!(
+                ERROR,
+                PgSqlErrorCode::ERRCODE_DATA_EXCEPTION,
+                message,
+                errors.detail()
+            );
+        } else {
+            ereport!(ERROR, PgSqlErrorCode::ERRCODE_DATA_EXCEPTION, message);
+        }
+    }
+
     #[pg_extern(security_definer)]
-    fn import_rules_from_yaml(yaml_content: &str) -> Option<String> {
-        match manage_rules::import_rules_from_yaml(yaml_content) {
-            Ok(result) => Some(result.to_string()),
+    fn import_rules_from_yaml(yaml_content: &str, mode: default!(&str, "'merge'")) -> String {
+        privileges::require_admin("import_rules_from_yaml");
+        let result = manage_rules::ImportMode::parse(mode)
+            .map_err(manage_rules::ImportErrors::from)
+            .and_then(|mode| {
+                audit::tracked("import_rules_from_yaml", || {
+                    manage_rules::import_rules_from_yaml(yaml_content, mode)
+                })
+            });
+        result.unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_rules_from_file(file_path: &str, mode: default!(&str, "'merge'")) -> String {
+        privileges::require_admin("import_rules_from_file");
+        let result = manage_rules::ImportMode::parse(mode)
+            .map_err(manage_rules::ImportErrors::from)
+            .and_then(|mode| {
+                audit::tracked("import_rules_from_file", || {
+                    manage_rules::import_rules_from_file(file_path, mode)
+                })
+            });
+        result.unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_rule_messages_from_yaml(yaml_content: &str) -> String {
+        privileges::require_admin("import_rule_messages_from_yaml");
+        audit::tracked("import_rule_messages_from_yaml", || {
+            manage_rules::import_rule_messages_from_yaml(yaml_content)
+        })
+        .unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_rule_messages_from_file(file_path: &str) -> String {
+        privileges::require_admin("import_rule_messages_from_file");
+        audit::tracked("import_rule_messages_from_file", || {
+            manage_rules::import_rule_messages_from_file(file_path)
+        })
+        .unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    #[pg_extern(security_definer)]
+    fn export_config(format: default!(&str, "'yaml'")) -> Option<String> {
+        match bundle::BundleFormat::parse(format).and_then(bundle::export_config) {
+            Ok(content) => Some(content),
             Err(e) => {
-                pgrx::warning!("Failed to import: {}", e);
-                Some(e.to_string())
+                pgrx::warning!("Failed to export configuration: {}", e);
+                None
             }
         }
     }
 
+    /// Changes of the configuration recorded in pglinter.config_audit, from `since`
     #[pg_extern(security_definer)]
-    fn import_rules_from_file(file_path: &str) -> Option<String> {
-        match manage_rules::import_rules_from_file(file_path) {
-            Ok(result) => Some(result.to_string()),
+    fn export_config_audit_to_yaml(
+        since: default!(Option<pgrx::datum::TimestampWithTimeZone>, "NULL"),
+    ) -> Option<String> {
+        match audit::export_config_audit_to_yaml(since) {
+            Ok(result) => Some(result),
             Err(e) => {
-                pgrx::warning!("Failed to import: {}", e);
-                Some(e.to_string())
+                pgrx::warning!("Failed to export the configuration audit: {}", e);
+                None
             }
         }
     }
 
     #[pg_extern(security_definer)]
-    fn import_rule_messages_from_yaml(yaml_content: &str) -> Option<String> {
-        match manage_rules::import_rule_messages_from_yaml(yaml_content) {
-            Ok(result) => Some(result.to_string()),
+    fn export_config_to_file(file_path: &str, format: default!(&str, "'yaml'")) -> Option<String> {
+        privileges::require_admin("export_config_to_file");
+        let result = bundle::BundleFormat::parse(format)
+            .and_then(|format| bundle::export_config_to_file(file_path, format));
+        match result {
+            Ok(message) => Some(message),
             Err(e) => {
-                pgrx::warning!("Failed to import rule messages: {}", e);
-                Some(e.to_string())
+                pgrx::warning!("Failed to export configuration: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_config(content: &str, mode: default!(&str, "'merge'")) -> String {
+        privileges::require_admin("import_config");
+        let result = manage_rules::ImportMode::parse(mode)
+            .map_err(manage_rules::ImportErrors::from)
+            .and_then(|mode| {
+                audit::tracked("import_config", || bundle::import_config(content, mode))
+            });
+        result.unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_config_from_file(file_path: &str, mode: default!(&str, "'merge'")) -> String {
+        privileges::require_admin("import_config_from_file");
+        let result = manage_rules::ImportMode::parse(mode)
+            .map_err(manage_rules::ImportErrors::from)
+            .and_then(|mode| {
+                audit::tracked("import_config_from_file", || {
+                    bundle::import_config_from_file(file_path, mode)
+                })
+            });
+        result.unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
+    /// Runs an import without applying it and returns the errors it finds, one row per
+    /// error. `kind` is rules, rule_messages, profiles or config.
+    #[pg_extern(security_definer)]
+    fn check_import(
+        yaml_content: &str,
+        kind: default!(&str, "'rules'"),
+    ) -> TableIterator<'static, (name!(item, String), name!(error, String))> {
+        privileges::require_admin("check_import");
+        let errors = match kind.to_lowercase().as_str() {
+            "rules" => manage_rules::check_import(|| {
+                manage_rules::import_rules_from_yaml(yaml_content, manage_rules::ImportMode::Merge)
+            }),
+            "rule_messages" => manage_rules::check_import(|| {
+                manage_rules::import_rule_messages_from_yaml(yaml_content)
+            }),
+            "profiles" => {
+                manage_rules::check_import(|| profiles::import_profiles_from_yaml(yaml_content))
+            }
+            "config" => manage_rules::check_import(|| {
+                bundle::import_config(yaml_content, manage_rules::ImportMode::Merge)
+            }),
+            _ => vec![manage_rules::ImportError {
+                item: String::new(),
+                message: format!(
+                    "Invalid import kind '{}': expected rules, rule_messages, profiles or config",
+                    kind
+                ),
+            }],
+        };
+        TableIterator::new(errors.into_iter().map(|e| (e.item, e.message)))
+    }
+
+    type RuleInfoRow = (
+        String,
+        String,
+        String,
+        bool,
+        Option<String>,
+        Vec<String>,
+        Option<String>,
+        Vec<Option<String>>,
+        pgrx::JsonB,
+        Option<String>,
+    );
+
+    fn rule_info_rows(rule_code: Option<&str>) -> Vec<RuleInfoRow> {
+        match manage_rules::rules_catalog(rule_code) {
+            Ok(rules) => rules
+                .into_iter()
+                .map(|rule| {
+                    (
+                        rule.code,
+                        rule.name,
+                        rule.scope,
+                        rule.enabled,
+                        rule.severity,
+                        rule.tags,
+                        rule.message,
+                        rule.fixes,
+                        pgrx::JsonB(rule.params),
+                        rule.docs_url,
+                    )
+                })
+                .collect(),
+            Err(e) => {
+                pgrx::warning!("Failed to read the rules catalog: {}", e);
+                Vec::new()
             }
         }
     }
 
+    /// One row per rule, for the clients that cannot read the output of show_rules()
+    #[pg_extern(security_definer)]
+    #[allow(clippy::type_complexity)]
+    fn rules_catalog() -> TableIterator<
+        'static,
+        (
+            name!(code, String),
+            name!(name, String),
+            name!(scope, String),
+            name!(enabled, bool),
+            name!(severity, Option<String>),
+            name!(tags, Vec<String>),
+            name!(message, Option<String>),
+            name!(fixes, Vec<Option<String>>),
+            name!(params, pgrx::JsonB),
+            name!(docs_url, Option<String>),
+        ),
+    > {
+        TableIterator::new(rule_info_rows(None))
+    }
+
+    /// The row of rules_catalog() describing a rule, no row when it does not exist
+    #[pg_extern(security_definer)]
+    #[allow(clippy::type_complexity)]
+    fn rule_info(
+        rule_code: &str,
+    ) -> TableIterator<
+        'static,
+        (
+            name!(code, String),
+            name!(name, String),
+            name!(scope, String),
+            name!(enabled, bool),
+            name!(severity, Option<String>),
+            name!(tags, Vec<String>),
+            name!(message, Option<String>),
+            name!(fixes, Vec<Option<String>>),
+            name!(params, pgrx::JsonB),
+            name!(docs_url, Option<String>),
+        ),
+    > {
+        TableIterator::new(rule_info_rows(Some(rule_code)))
+    }
+
     #[pg_extern(security_definer)]
     fn list_rules() -> Option<String> {
         match manage_rules::list_rules() {
@@ -212,8 +622,124 @@ mod pglinter {
         }
     }
 
+    // Rule profiles
+    #[pg_extern(security_definer)]
+    fn create_profile(
+        profile_name: &str,
+        rule_codes: default!(Option<Vec<String>>, "NULL"),
+        description: default!(Option<&str>, "NULL"),
+    ) -> Option<bool> {
+        privileges::require_admin("create_profile");
+        match audit::tracked("create_profile", || {
+            profiles::create_profile(profile_name, rule_codes, description)
+        }) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to create profile {}: {}", profile_name, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn drop_profile(profile_name: &str) -> Option<bool> {
+        privileges::require_admin("drop_profile");
+        match audit::tracked("drop_profile", || profiles::drop_profile(profile_name)) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to drop profile {}: {}", profile_name, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn set_profile_rule(
+        profile_name: &str,
+        rule_code: &str,
+        warning_level: default!(Option<i32>, "NULL"),
+        error_level: default!(Option<i32>, "NULL"),
+        severity: default!(Option<&str>, "NULL"),
+    ) -> Option<bool> {
+        privileges::require_admin("set_profile_rule");
+        match audit::tracked("set_profile_rule", || {
+            profiles::set_profile_rule(
+                profile_name,
+                rule_code,
+                warning_level,
+                error_level,
+                severity,
+            )
+        }) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!(
+                    "Failed to set rule {} in profile {}: {}",
+                    rule_code,
+                    profile_name,
+                    e
+                );
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn remove_profile_rule(profile_name: &str, rule_code: &str) -> Option<bool> {
+        privileges::require_admin("remove_profile_rule");
+        match audit::tracked("remove_profile_rule", || {
+            profiles::remove_profile_rule(profile_name, rule_code)
+        }) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!(
+                    "Failed to remove rule {} from profile {}: {}",
+                    rule_code,
+                    profile_name,
+                    e
+                );
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn apply_profile(profile_name: &str) -> Option<bool> {
+        privileges::require_admin("apply_profile");
+        match audit::tracked("apply_profile", || profiles::apply_profile(profile_name)) {
+            Ok(success) => Some(success),
+            Err(e) => {
+                pgrx::warning!("Failed to apply profile {}: {}", profile_name, e);
+                Some(false)
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn export_profiles_to_yaml() -> Option<String> {
+        match profiles::export_profiles_to_yaml() {
+            Ok(result) => Some(result),
+            Err(e) => {
+                pgrx::warning!("Failed to export profiles: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn import_profiles_from_yaml(yaml_content: &str) -> String {
+        privileges::require_admin("import_profiles_from_yaml");
+        audit::tracked("import_profiles_from_yaml", || {
+            profiles::import_profiles_from_yaml(yaml_content)
+        })
+        .unwrap_or_else(|errors| raise_import_errors(errors))
+    }
+
     #[pg_extern(security_definer)]
-    fn get_violations() -> TableIterator<
+    fn get_violations(
+        profile: default!(Option<&str>, "NULL"),
+        tag: default!(Option<&str>, "NULL"),
+    ) -> TableIterator<
         'static,
         (
             name!(rule_code, String),
@@ -221,25 +747,196 @@ mod pglinter {
             name!(objid, i32),
             name!(objsubid, i32),
             name!(message, String),
+            name!(details, Option<pgrx::JsonB>),
         ),
     > {
-        use crate::execute_rules::{get_sanitized_message, get_violations};
-        let mut rows = Vec::new();
-        match get_violations() {
-            Ok(violations) => {
-                for (rule_code, violations_vec) in violations {
-                    for (classid, objid, objsubid) in violations_vec {
-                        let message = get_sanitized_message(&rule_code, classid, objid, objsubid);
-                        rows.push((rule_code.clone(), classid, objid, objsubid, message));
-                    }
-                }
+        use crate::execute_rules::stream_violations;
+        let severities = match profile.map(profiles::profile_severities).transpose() {
+            Ok(severities) => severities.unwrap_or_default(),
+            Err(e) => {
+                pgrx::warning!("pglinter get_violations failed: {}", e);
+                return TableIterator::new(Vec::new());
             }
+        };
+        let violations = match stream_violations(profile, tag) {
+            Ok(violations) => violations,
             Err(e) => {
                 pgrx::warning!("pglinter get_violations failed: {}", e);
+                return TableIterator::new(Vec::new());
+            }
+        };
+        // Rows are produced one call at a time, messages are rendered as they are returned
+        TableIterator::new(violations.map(move |(rule_code, violation, mut message)| {
+            let (classid, objid, objsubid) = violation.location();
+            if let Some(severity) = severities.get(&rule_code) {
+                message = profiles::with_severity(message, severity);
+            }
+            let details = violation.details.map(pgrx::JsonB);
+            (rule_code, classid, objid, objsubid, message, details)
+        }))
+    }
+
+    #[pg_extern(security_definer)]
+    #[allow(clippy::type_complexity)]
+    fn get_rules_summary() -> TableIterator<
+        'static,
+        (
+            name!(rule_code, String),
+            name!(rule_name, String),
+            name!(violations, i64),
+            name!(total, Option<i64>),
+            name!(ratio, Option<f64>),
+            name!(warning_level, Option<i32>),
+            name!(error_level, Option<i32>),
+            name!(level, String),
+            name!(score, Option<f64>),
+            name!(summary, String),
+        ),
+    > {
+        let rows = match reports::rules_summary() {
+            Ok(summaries) => summaries
+                .into_iter()
+                .map(|s| {
+                    (
+                        s.code,
+                        s.name,
+                        s.violations,
+                        s.total,
+                        s.ratio,
+                        s.warning_level,
+                        s.error_level,
+                        s.level.to_string(),
+                        s.score,
+                        s.summary,
+                    )
+                })
+                .collect(),
+            Err(e) => {
+                pgrx::warning!("Failed to build rules summary: {}", e);
+                Vec::new()
+            }
+        };
+        TableIterator::new(rows)
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_database_score() -> Option<f64> {
+        match reports::database_score() {
+            Ok(score) => score,
+            Err(e) => {
+                pgrx::warning!("Failed to compute database score: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_violations_junit() -> Option<String> {
+        match reports::get_violations_junit() {
+            Ok(report) => Some(report),
+            Err(e) => {
+                pgrx::warning!("Failed to build JUnit report: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_violations_codequality() -> Option<pgrx::JsonB> {
+        match reports::get_violations_codequality() {
+            Ok(report) => Some(pgrx::JsonB(report)),
+            Err(e) => {
+                pgrx::warning!("Failed to build code quality report: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_report_json() -> Option<pgrx::JsonB> {
+        match reports::get_report_json() {
+            Ok(report) => Some(pgrx::JsonB(report)),
+            Err(e) => {
+                pgrx::warning!("Failed to build JSON report: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn get_report(format: default!(&str, "'markdown'")) -> Option<String> {
+        match reports::get_report(format) {
+            Ok(report) => Some(report),
+            Err(e) => {
+                pgrx::warning!("Failed to build report: {}", e);
+                None
+            }
+        }
+    }
+
+    #[pg_extern(security_definer)]
+    fn metrics() -> Option<String> {
+        match reports::metrics() {
+            Ok(metrics) => Some(metrics),
+            Err(e) => {
+                pgrx::warning!("Failed to build metrics: {}", e);
+                None
             }
         }
+    }
+
+    #[pg_extern(security_definer)]
+    #[allow(clippy::type_complexity)]
+    fn apply_fixes(
+        rule_codes: default!(Option<Vec<Option<String>>>, "NULL"),
+        dry_run: default!(bool, true),
+        allow_destructive: default!(bool, false),
+        lock_timeout: default!(&str, "'5s'"),
+    ) -> TableIterator<
+        'static,
+        (
+            name!(rule_code, String),
+            name!(object, String),
+            name!(statement, String),
+            name!(risk, String),
+            name!(status, String),
+            name!(detail, Option<String>),
+        ),
+    > {
+        privileges::require_admin("apply_fixes");
+        let rule_codes = rule_codes.map(|codes| codes.into_iter().flatten().collect());
+        let rows = match fixes::apply_fixes(rule_codes, dry_run, allow_destructive, lock_timeout) {
+            Ok(outcomes) => outcomes
+                .into_iter()
+                .map(|outcome| {
+                    (
+                        outcome.fix.rule_code,
+                        outcome.fix.object,
+                        outcome.statement,
+                        outcome.fix.risk.to_string(),
+                        outcome.status.to_string(),
+                        outcome.detail,
+                    )
+                })
+                .collect(),
+            Err(e) => {
+                pgrx::warning!("Failed to apply fixes: {}", e);
+                Vec::new()
+            }
+        };
         TableIterator::new(rows)
     }
+
+    #[pg_extern(security_definer)]
+    fn generate_fix_script(rule_code: default!(Option<&str>, "NULL")) -> Option<String> {
+        match fixes::generate_fix_script(rule_code) {
+            Ok(script) => Some(script),
+            Err(e) => {
+                pgrx::warning!("Failed to generate fix script: {}", e);
+                None
+            }
+        }
+    }
 }
 
 //----------------------------------------------------------------------------
@@ -728,11 +1425,18 @@ mod tests {
 
     #[pg_test]
     fn test_import_rules_from_file() {
+        Spi::run("SET pglinter.file_directory = '/tmp'").unwrap();
+
         // Test 1: Test with non-existent file
-        let result_not_found =
-            manage_rules::import_rules_from_file("/nonexistent/path/to/file.yaml");
+        let result_not_found = manage_rules::import_rules_from_file(
+            "/tmp/pglinter_nonexistent_file.yaml",
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_not_found.is_err());
-        assert!(result_not_found.unwrap_err().contains("File read error"));
+        assert!(result_not_found
+            .unwrap_err()
+            .to_string()
+            .contains("File read error"));
 
         // Test 2: Create a temporary YAML file with test rules
         let temp_yaml_content = fixtures::get_valid_yaml_content();
@@ -746,7 +1450,8 @@ mod tests {
         fixtures::cleanup_test_rule("TEST_IMPORT_2");
 
         // Test 3: Import from valid YAML file
-        let result_success = manage_rules::import_rules_from_file(temp_file_path);
+        let result_success =
+            manage_rules::import_rules_from_file(temp_file_path, manage_rules::ImportMode::Merge);
         assert!(result_success.is_ok());
         let success_msg = result_success.unwrap();
         assert!(success_msg.contains("Import completed"));
@@ -783,7 +1488,8 @@ mod tests {
         assert!(!rule2_enabled.unwrap()); // Should be false
 
         // Test 7: Test updating existing rules (import again)
-        let result_update = manage_rules::import_rules_from_file(temp_file_path);
+        let result_update =
+            manage_rules::import_rules_from_file(temp_file_path, manage_rules::ImportMode::Merge);
         assert!(result_update.is_ok());
         let update_msg = result_update.unwrap();
         assert!(update_msg.contains("updated rules"));
@@ -795,17 +1501,27 @@ mod tests {
         std::fs::write(invalid_file_path, invalid_yaml_content)
             .expect("Failed to write invalid test file");
 
-        let result_invalid = manage_rules::import_rules_from_file(invalid_file_path);
+        let result_invalid = manage_rules::import_rules_from_file(
+            invalid_file_path,
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_invalid.is_err());
-        assert!(result_invalid.unwrap_err().contains("YAML parsing error"));
+        assert!(result_invalid
+            .unwrap_err()
+            .to_string()
+            .contains("YAML parsing error"));
 
         // Test 9: Test with empty file
         let empty_file_path = "/tmp/pglinter_empty_test.yaml";
         std::fs::write(empty_file_path, "").expect("Failed to write empty test file");
 
-        let result_empty = manage_rules::import_rules_from_file(empty_file_path);
+        let result_empty =
+            manage_rules::import_rules_from_file(empty_file_path, manage_rules::ImportMode::Merge);
         assert!(result_empty.is_err());
-        assert!(result_empty.unwrap_err().contains("YAML parsing error"));
+        assert!(result_empty
+            .unwrap_err()
+            .to_string()
+            .contains("YAML parsing error"));
 
         // Test 10: Test with file that exists but has wrong permissions (if supported on system)
         let protected_file_path = "/tmp/pglinter_protected_test.yaml";
@@ -823,10 +1539,13 @@ mod tests {
             let _ = std::fs::set_permissions(protected_file_path, perms);
 
             // This should fail with permission denied (though behavior may vary)
-            let result_protected = manage_rules::import_rules_from_file(protected_file_path);
+            let result_protected = manage_rules::import_rules_from_file(
+                protected_file_path,
+                manage_rules::ImportMode::Merge,
+            );
             // We expect either success (if permissions aren't enforced) or a file read error
-            if result_protected.is_err() {
-                assert!(result_protected.unwrap_err().contains("File read error"));
+            if let Err(e) = result_protected {
+                assert!(e.to_string().contains("File read error"));
             }
         }
 
@@ -860,7 +1579,10 @@ mod tests {
         fixtures::cleanup_test_rule("TEST_IMPORT_2");
 
         // Test 2: Import from valid YAML content
-        let result_success = manage_rules::import_rules_from_yaml(valid_yaml_content);
+        let result_success = manage_rules::import_rules_from_yaml(
+            valid_yaml_content,
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_success.is_ok());
         let success_msg = result_success.unwrap();
         assert!(success_msg.contains("Import completed"));
@@ -891,7 +1613,10 @@ mod tests {
         assert!(!rule2_enabled.unwrap());
 
         // Test 7: Re-import same YAML to test updates
-        let result_update = manage_rules::import_rules_from_yaml(valid_yaml_content);
+        let result_update = manage_rules::import_rules_from_yaml(
+            valid_yaml_content,
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_update.is_ok());
         let update_msg = result_update.unwrap();
         assert!(update_msg.contains("2 updated rules"));
@@ -899,27 +1624,41 @@ mod tests {
 
         // Test 8: Test with invalid YAML structure
         let invalid_yaml_content = fixtures::get_invalid_yaml_content();
-        let result_invalid = manage_rules::import_rules_from_yaml(invalid_yaml_content);
+        let result_invalid = manage_rules::import_rules_from_yaml(
+            invalid_yaml_content,
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_invalid.is_err());
-        assert!(result_invalid.unwrap_err().contains("YAML parsing error"));
+        assert!(result_invalid
+            .unwrap_err()
+            .to_string()
+            .contains("YAML parsing error"));
 
         // Test 9: Test with valid YAML but invalid rule data
         let invalid_rule_yaml = fixtures::get_invalid_rule_yaml_content();
 
-        let result_invalid_rule = manage_rules::import_rules_from_yaml(invalid_rule_yaml);
+        let result_invalid_rule = manage_rules::import_rules_from_yaml(
+            invalid_rule_yaml,
+            manage_rules::ImportMode::Merge,
+        );
         // This should succeed from YAML parsing perspective, even if SQL is invalid
         assert!(result_invalid_rule.is_ok());
 
         // Test 10: Test with empty YAML content
         let empty_yaml = "";
-        let result_empty = manage_rules::import_rules_from_yaml(empty_yaml);
+        let result_empty =
+            manage_rules::import_rules_from_yaml(empty_yaml, manage_rules::ImportMode::Merge);
         assert!(result_empty.is_err());
-        assert!(result_empty.unwrap_err().contains("YAML parsing error"));
+        assert!(result_empty
+            .unwrap_err()
+            .to_string()
+            .contains("YAML parsing error"));
 
         // Test 11: Test with minimal valid YAML
         let minimal_yaml = fixtures::get_minimal_yaml_content();
 
-        let result_minimal = manage_rules::import_rules_from_yaml(minimal_yaml);
+        let result_minimal =
+            manage_rules::import_rules_from_yaml(minimal_yaml, manage_rules::ImportMode::Merge);
         assert!(result_minimal.is_ok());
         let minimal_msg = result_minimal.unwrap();
         assert!(minimal_msg.contains("0 new rules, 0 updated rules"));
@@ -927,7 +1666,10 @@ mod tests {
         // Test 12: Test with rule containing special characters in strings
         let special_chars_yaml = fixtures::get_special_chars_yaml_content();
 
-        let result_special = manage_rules::import_rules_from_yaml(special_chars_yaml);
+        let result_special = manage_rules::import_rules_from_yaml(
+            special_chars_yaml,
+            manage_rules::ImportMode::Merge,
+        );
         assert!(result_special.is_ok());
 
         // Verify the special characters are preserved
@@ -979,6 +1721,8 @@ mod tests {
 
     #[pg_test]
     fn test_export_rules_to_file() {
+        Spi::run("SET pglinter.file_directory = '/tmp'").unwrap();
+
         // Setup test rules
         fixtures::setup_test_rule("FILE_EXPORT_1", 9991, "File Export Test Rule", true);
 
@@ -1018,12 +1762,12 @@ mod tests {
         let invalid_path = "/tmp/nonexistent_dir/test.yaml";
         let result_invalid = manage_rules::export_rules_to_file(invalid_path);
         assert!(result_invalid.is_err());
-        assert!(result_invalid.unwrap_err().contains("File write error"));
+        assert!(result_invalid.unwrap_err().contains("Invalid file path"));
 
         // Test 4: Test with empty filename
         let result_empty = manage_rules::export_rules_to_file("");
         assert!(result_empty.is_err());
-        assert!(result_empty.unwrap_err().contains("File write error"));
+        assert!(result_empty.unwrap_err().contains("Invalid file path"));
 
         // Test 5: Test with directory path (should fail)
         let result_dir = manage_rules::export_rules_to_file("/tmp");
@@ -1081,7 +1825,7 @@ mod tests {
     fn test_get_violations() {
         // get_violations reads q4 queries from the pglinter.rules table.
         // Test that it returns a result (may or may not have violations depending on DB state).
-        let result = get_violations();
+        let result = get_violations(None, None);
         assert!(result.is_ok());
         // All enabled rules should be in the result
         let all = result.unwrap();
@@ -1089,7 +1833,7 @@ mod tests {
         // Each entry has a code and a (possibly empty) violation list
         for (code, violations) in &all {
             assert!(!code.is_empty());
-            // violations is a Vec<(i32, i32, i32)>
+            // violations is a Vec<Violation>
             let _ = violations;
         }
     }
@@ -1108,6 +1852,1721 @@ mod tests {
         assert!(result_unknown.is_ok());
         assert!(result_unknown.unwrap().is_empty());
     }
+
+    #[pg_test]
+    fn test_get_violations_junit() {
+        let _ = Spi::run("CREATE TABLE junit_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "JUNIT_FAIL",
+            9980,
+            "Junit Failing Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'junit_no_pk'::regclass::oid, 0",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "JUNIT_ERROR",
+            9981,
+            "Junit Erroring Rule",
+            "SCHEMA",
+            "SELECT classid FROM junit_table_that_does_not_exist",
+        );
+
+        let result = crate::reports::get_violations_junit();
+        assert!(result.is_ok());
+        let xml = result.unwrap();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert!(xml.contains("<testsuites name=\"pglinter\""));
+        assert!(xml.contains("<testsuite name=\"BASE\""));
+        assert!(xml.contains("<testsuite name=\"SCHEMA\""));
+        assert!(xml.contains("name=\"JUNIT_FAIL Junit Failing Rule\""));
+        assert!(xml.contains("<failure type=\"JUNIT_FAIL\""));
+        assert!(xml.contains("table public.junit_no_pk</failure>"));
+        assert!(xml.contains("name=\"JUNIT_ERROR Junit Erroring Rule\""));
+        assert!(xml.contains("<error type=\"RuleExecutionError\""));
+        assert!(xml.contains("junit_table_that_does_not_exist"));
+
+        // BASE is always reported before SCHEMA
+        assert!(xml.find("name=\"BASE\"").unwrap() < xml.find("name=\"SCHEMA\"").unwrap());
+
+        fixtures::cleanup_test_rule("JUNIT_FAIL");
+        fixtures::cleanup_test_rule("JUNIT_ERROR");
+        let _ = Spi::run("DROP TABLE junit_no_pk");
+    }
+
+    #[pg_test]
+    fn test_get_violations_codequality() {
+        let _ = Spi::run("CREATE TABLE codequality_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "CQ_TEST",
+            9982,
+            "Code Quality Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'codequality_no_pk'::regclass::oid, 0",
+        );
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('CQ_TEST', '{\"severity\": \"ERROR\", \"message\": \"{object} is checked.\"}')",
+        );
+
+        let report = crate::reports::get_violations_codequality().unwrap();
+        let issues: Vec<&serde_json::Value> = report
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|issue| issue["check_name"] == "CQ_TEST")
+            .collect();
+        assert_eq!(issues.len(), 1);
+
+        let issue = issues[0];
+        assert_eq!(issue["type"], "issue");
+        assert_eq!(issue["severity"], "major");
+        assert_eq!(issue["location"]["path"], "public.codequality_no_pk");
+        assert_eq!(issue["location"]["lines"]["begin"], 1);
+        assert!(issue["description"]
+            .as_str()
+            .unwrap()
+            .contains("codequality_no_pk is checked."));
+
+        // The fingerprint only depends on the rule and the object identity
+        let fingerprint = issue["fingerprint"].as_str().unwrap().to_string();
+        assert_eq!(
+            Some(fingerprint.clone()),
+            Spi::get_one::<String>("SELECT md5('CQ_TEST:public.codequality_no_pk')").unwrap()
+        );
+        let again = crate::reports::get_violations_codequality().unwrap();
+        assert!(again
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|issue| issue["fingerprint"] == fingerprint.as_str()));
+
+        // The severity is the one of the message returned by get_violations
+        let _ = Spi::run(
+            "UPDATE pglinter.rule_messages
+             SET rule_msg = rule_msg || '{\"locales\": {\"fr\": {\"message\": \"{object} est vérifiée.\"}}}'
+             WHERE code = 'CQ_TEST'",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rule_messages
+             SET rule_msg = jsonb_set(rule_msg, '{severity}', '\"CRITICAL\"')
+             WHERE code = 'CQ_TEST'",
+        );
+        Spi::run("SET LOCAL pglinter.language = 'fr'").unwrap();
+        let message = Spi::get_one::<pgrx::JsonB>(
+            "SELECT message::jsonb FROM pglinter.get_violations() WHERE rule_code = 'CQ_TEST'",
+        )
+        .unwrap()
+        .unwrap();
+        let report = crate::reports::get_violations_codequality().unwrap();
+        let issue = report
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|issue| issue["check_name"] == "CQ_TEST")
+            .unwrap();
+        assert_eq!(message.0["rule_msg"]["severity"], "CRITICAL");
+        assert_eq!(issue["severity"], "critical");
+        assert!(issue["description"]
+            .as_str()
+            .unwrap()
+            .contains("est vérifiée"));
+        let json_report = crate::reports::get_report_json().unwrap();
+        let rule = json_report["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|rule| rule["code"] == "CQ_TEST")
+            .unwrap();
+        assert_eq!(rule["severity"], "CRITICAL");
+        Spi::run("RESET pglinter.language").unwrap();
+
+        fixtures::cleanup_test_rule("CQ_TEST");
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'CQ_TEST'");
+        let _ = Spi::run("DROP TABLE codequality_no_pk");
+    }
+
+    #[pg_test]
+    fn test_get_report_json() {
+        let _ = Spi::run("CREATE TABLE report_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "REPORT_FAIL",
+            9983,
+            "Report Failing Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'report_no_pk'::regclass::oid, 0",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "REPORT_ERROR",
+            9984,
+            "Report Erroring Rule",
+            "BASE",
+            "SELECT 1/0, 0, 0",
+        );
+
+        let report = crate::reports::get_report_json().unwrap();
+
+        let metadata = &report["metadata"];
+        assert_eq!(metadata["tool"], "pglinter");
+        assert!(metadata["database"].is_string());
+        assert!(metadata["server_version"].is_string());
+        assert!(metadata["generated_at"].is_string());
+
+        let rules = report["rules"].as_array().unwrap();
+        let failing = rules.iter().find(|r| r["code"] == "REPORT_FAIL").unwrap();
+        assert_eq!(failing["status"], "failed");
+        assert_eq!(failing["violation_count"], 1);
+        let erroring = rules.iter().find(|r| r["code"] == "REPORT_ERROR").unwrap();
+        assert_eq!(erroring["status"], "error");
+        assert!(erroring["error"]
+            .as_str()
+            .unwrap()
+            .contains("division by zero"));
+
+        let summary = &report["summary"];
+        assert_eq!(
+            summary["rules_executed"].as_u64().unwrap() as usize,
+            rules.len()
+        );
+        assert!(summary["rules_errored"].as_u64().unwrap() >= 1);
+
+        // Violations always have the same shape, with or without a rule message
+        let violation = report["violations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|v| v["rule_code"] == "REPORT_FAIL")
+            .unwrap();
+        assert_eq!(violation["object"]["type"], "table");
+        assert_eq!(violation["object"]["identity"], "public.report_no_pk");
+        assert!(violation["message"].is_string());
+        assert!(violation["infos"].is_array());
+        assert!(violation.get("advices").is_some());
+
+        fixtures::cleanup_test_rule("REPORT_FAIL");
+        fixtures::cleanup_test_rule("REPORT_ERROR");
+        let _ = Spi::run("DROP TABLE report_no_pk");
+    }
+
+    #[pg_test]
+    fn test_get_report() {
+        let _ = Spi::run("CREATE SCHEMA report_schema");
+        let _ = Spi::run("CREATE TABLE report_schema.md_no_pk (id INT)");
+        let _ = Spi::run("DELETE FROM pglinter.rules WHERE code = 'MD_TEST'");
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rules (id, code, name, enable, scope, message, fixes, q4)
+             VALUES (9985, 'MD_TEST', 'Markdown Rule', true, 'BASE', 'table <without> pk',
+                     ARRAY['add a primary key'],
+                     $q$SELECT 'pg_class'::regclass::oid, 'report_schema.md_no_pk'::regclass::oid, 0$q$)",
+        );
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('MD_TEST', '{\"severity\": \"WARNING\", \"message\": \"{object} has no pk.\", \"advices\": \"Add one.\"}')",
+        );
+
+        let markdown = crate::reports::get_report("markdown").unwrap();
+        assert!(markdown.starts_with("# pglinter report: "));
+        assert!(markdown.contains("## Summary"));
+        assert!(markdown.contains("| MD\\_TEST | Markdown Rule | BASE | WARNING | failed | 1 |"));
+        assert!(markdown.contains("## MD\\_TEST - Markdown Rule"));
+        assert!(markdown.contains("table \\<without\\> pk"));
+        assert!(markdown.contains("**Advice:** Add one."));
+        assert!(markdown.contains("- add a primary key"));
+        assert!(markdown.contains("### Schema report\\_schema"));
+        assert!(markdown.contains("| table report\\_schema.md\\_no\\_pk |"));
+
+        // Identifiers and user-defined messages cannot break the Markdown structure
+        let _ = Spi::run("CREATE TABLE report_schema.\"a|b`#c\" (id INT)");
+        let _ = Spi::run(
+            "UPDATE pglinter.rules
+             SET q4 = $q$SELECT 'pg_class'::regclass::oid, 'report_schema.\"a|b`#c\"'::regclass::oid, 0$q$,
+                 fixes = ARRAY['run `ALTER TABLE` | then # check']
+             WHERE code = 'MD_TEST'",
+        );
+        let markdown = crate::reports::get_report("markdown").unwrap();
+        assert!(markdown.contains("| table report\\_schema.\"a\\|b\\`\\#c\" | "));
+        assert!(markdown.contains("- run \\`ALTER TABLE\\` \\| then \\# check"));
+        let table_rows = markdown
+            .lines()
+            .filter(|line| line.contains("a\\|b"))
+            .collect::<Vec<_>>();
+        assert_eq!(table_rows.len(), 1);
+        assert_eq!(table_rows[0].replace("\\|", "").matches('|').count(), 3);
+
+        let html = crate::reports::get_report("HTML").unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2 id=\"MD_TEST\">MD_TEST - Markdown Rule</h2>"));
+        assert!(html.contains("table &lt;without&gt; pk"));
+        assert!(html.contains("<li>add a primary key</li>"));
+        assert!(html.contains("<h3>Schema report_schema</h3>"));
+
+        let unknown = crate::reports::get_report("pdf");
+        assert!(unknown.is_err());
+        assert!(unknown.unwrap_err().contains("Unsupported report format"));
+
+        fixtures::cleanup_test_rule("MD_TEST");
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'MD_TEST'");
+        let _ = Spi::run("DROP SCHEMA report_schema CASCADE");
+    }
+
+    #[pg_test]
+    fn test_metrics() {
+        let _ = Spi::run("CREATE TABLE metrics_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "METRICS_FAIL",
+            9986,
+            "Metrics Failing Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'metrics_no_pk'::regclass::oid, 0",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "METRICS_ERROR",
+            9987,
+            "Metrics Erroring Rule",
+            "BASE",
+            "SELECT 1/0, 0, 0",
+        );
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('METRICS_FAIL', '{\"severity\": \"ERROR\", \"message\": \"{object}\"}')",
+        );
+
+        let metrics = crate::reports::metrics().unwrap();
+        assert!(metrics.contains("# TYPE pglinter_violations gauge"));
+        assert!(metrics.contains("rule=\"METRICS_FAIL\",severity=\"error\",schema=\"public\"} 1"));
+        assert!(metrics.contains("pglinter_rule_duration_seconds{"));
+        assert!(metrics.contains("rule=\"METRICS_ERROR\"} 1"));
+        assert!(metrics.contains("pglinter_last_run_timestamp_seconds{database="));
+
+        fixtures::cleanup_test_rule("METRICS_FAIL");
+        fixtures::cleanup_test_rule("METRICS_ERROR");
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'METRICS_FAIL'");
+        let _ = Spi::run("DROP TABLE metrics_no_pk");
+    }
+
+    #[pg_test]
+    fn test_rules_summary() {
+        let _ = Spi::run("CREATE TABLE summary_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "SUMMARY_RATIO",
+            9988,
+            "Summary Ratio Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'summary_no_pk'::regclass::oid, 0",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rules
+             SET message = 'table without primary key', q1 = 'SELECT 4',
+                 warning_level = 10, error_level = 20
+             WHERE code = 'SUMMARY_RATIO'",
+        );
+
+        let summaries = crate::reports::rules_summary().unwrap();
+        let summary = summaries
+            .iter()
+            .find(|s| s.code == "SUMMARY_RATIO")
+            .expect("summary of the test rule");
+        assert_eq!(summary.violations, 1);
+        assert_eq!(summary.total, Some(4));
+        assert_eq!(summary.ratio, Some(25.0));
+        assert_eq!(summary.level, "error");
+        assert_eq!(summary.score, Some(75.0));
+        assert_eq!(
+            summary.summary,
+            "25.0% (1/4) table without primary key (error > 20%)"
+        );
+
+        let score = crate::reports::database_score().unwrap().unwrap();
+        assert!((0.0..=100.0).contains(&score));
+
+        fixtures::cleanup_test_rule("SUMMARY_RATIO");
+        let _ = Spi::run("DROP TABLE summary_no_pk");
+    }
+
+    #[pg_test]
+    fn test_update_rule_levels() {
+        fixtures::setup_test_rule("LEVELS_TEST", 9989, "Levels Test Rule", true);
+
+        assert_eq!(
+            crate::manage_rules::update_rule_levels("LEVELS_TEST", Some(30), Some(70)),
+            Ok(true)
+        );
+        assert_eq!(
+            crate::manage_rules::get_rule_levels("LEVELS_TEST").unwrap(),
+            "warning_level=30, error_level=70"
+        );
+
+        // NULL keeps the current value
+        assert_eq!(
+            crate::manage_rules::update_rule_levels("LEVELS_TEST", None, Some(90)),
+            Ok(true)
+        );
+        assert_eq!(
+            crate::manage_rules::get_rule_levels("LEVELS_TEST").unwrap(),
+            "warning_level=30, error_level=90"
+        );
+
+        assert!(crate::manage_rules::update_rule_levels("LEVELS_TEST", Some(95), None).is_err());
+        assert!(crate::manage_rules::update_rule_levels("LEVELS_TEST", Some(101), None).is_err());
+        assert_eq!(
+            crate::manage_rules::update_rule_levels("NONEXISTENT", Some(1), Some(2)),
+            Ok(false)
+        );
+
+        fixtures::cleanup_test_rule("LEVELS_TEST");
+    }
+
+    #[pg_test]
+    fn test_profiles() {
+        let _ = Spi::run("CREATE TABLE profile_no_pk (id INT)");
+        fixtures::setup_test_rule_with_q4(
+            "PROFILE_RULE",
+            9990,
+            "Profile Test Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'profile_no_pk'::regclass::oid, 0",
+        );
+        let _ = Spi::run("UPDATE pglinter.rules SET enable = false WHERE code = 'PROFILE_RULE'");
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('PROFILE_RULE', '{\"severity\": \"WARNING\", \"message\": \"{object}\"}')",
+        );
+
+        use crate::profiles;
+        assert!(profiles::create_profile(
+            "test_prod",
+            Some(vec!["PROFILE_RULE".to_string()]),
+            Some("test profile")
+        )
+        .unwrap());
+        assert!(!profiles::create_profile("test_prod", None, None).unwrap());
+        assert!(
+            profiles::create_profile("test_bad", Some(vec!["NOPE".to_string()]), None).is_err()
+        );
+        assert!(
+            profiles::set_profile_rule("test_prod", "PROFILE_RULE", None, None, Some("error"))
+                .unwrap()
+        );
+
+        // The profile runs its own rules without touching the global configuration
+        let violations = crate::execute_rules::get_violations(Some("test_prod"), None).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].0, "PROFILE_RULE");
+        assert_eq!(
+            fixtures::get_rule_bool_property("PROFILE_RULE", "enable"),
+            Some(false)
+        );
+        assert!(crate::execute_rules::get_violations(Some("missing"), None).is_err());
+        let severities = profiles::profile_severities("test_prod").unwrap();
+        assert_eq!(
+            severities.get("PROFILE_RULE").map(String::as_str),
+            Some("ERROR")
+        );
+
+        // YAML round trip
+        let yaml = profiles::export_profiles_to_yaml().unwrap();
+        assert!(yaml.contains("name: test_prod"));
+        assert!(profiles::drop_profile("test_prod").unwrap());
+        let imported = profiles::import_profiles_from_yaml(&yaml).unwrap();
+        assert!(imported.contains("new profiles"));
+        assert!(profiles::profile_exists("test_prod").unwrap());
+
+        // A translation written before rule_msg was validated may still carry a severity
+        let _ = Spi::run(
+            "ALTER TABLE pglinter.rule_messages DROP CONSTRAINT rule_messages_rule_msg_check",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rule_messages
+             SET rule_msg = rule_msg || '{\"locales\": {\"fr\": {\"severity\": \"INFO\", \"message\": \"objet {object}\"}}}'
+             WHERE code = 'PROFILE_RULE'",
+        );
+
+        assert!(profiles::apply_profile("test_prod").unwrap());
+        assert_eq!(
+            fixtures::get_rule_bool_property("PROFILE_RULE", "enable"),
+            Some(true)
+        );
+        assert_eq!(
+            fixtures::get_rule_bool_property("B001", "enable"),
+            Some(false)
+        );
+        let severity = Spi::get_one::<String>(
+            "SELECT rule_msg->>'severity' FROM pglinter.rule_messages WHERE code = 'PROFILE_RULE'",
+        );
+        assert_eq!(severity, Ok(Some("ERROR".to_string())));
+        Spi::run("SET LOCAL pglinter.language = 'fr'").unwrap();
+        let rendered: serde_json::Value = serde_json::from_str(
+            &crate::execute_rules::get_sanitized_message("PROFILE_RULE", 1259, 1259, 0, None),
+        )
+        .unwrap();
+        Spi::run("RESET pglinter.language").unwrap();
+        assert_eq!(rendered["rule_msg"]["severity"], "ERROR");
+        assert!(rendered["rule_msg"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("objet "));
+
+        let _ = Spi::run("UPDATE pglinter.rules SET enable = true");
+        assert!(profiles::drop_profile("test_prod").unwrap());
+        fixtures::cleanup_test_rule("PROFILE_RULE");
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'PROFILE_RULE'");
+        let _ = Spi::run("DROP TABLE profile_no_pk");
+    }
+
+    #[pg_test]
+    fn test_rules_by_tag_and_scope() {
+        fixtures::setup_test_rule_with_q4(
+            "TAG_RULE_1",
+            9991,
+            "Tag Rule 1",
+            "TAGSCOPE",
+            "SELECT 1259, 1259, 0",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "TAG_RULE_2",
+            9992,
+            "Tag Rule 2",
+            "TAGSCOPE",
+            "SELECT 1259, 1259, 0",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rules SET tags = ARRAY['test-tag'] WHERE code = 'TAG_RULE_1'",
+        );
+
+        use crate::manage_rules;
+        assert_eq!(manage_rules::disable_rules_by_tag("Test-Tag"), Ok(1));
+        assert_eq!(
+            fixtures::get_rule_bool_property("TAG_RULE_1", "enable"),
+            Some(false)
+        );
+        assert_eq!(
+            fixtures::get_rule_bool_property("TAG_RULE_2", "enable"),
+            Some(true)
+        );
+        assert_eq!(manage_rules::disable_rules_by_tag("test-tag"), Ok(0));
+        assert_eq!(manage_rules::enable_rules_by_tag("test-tag"), Ok(1));
+
+        assert_eq!(manage_rules::disable_rules_by_scope("tagscope"), Ok(2));
+        assert_eq!(manage_rules::enable_rules_by_scope("TAGSCOPE"), Ok(2));
+        assert_eq!(manage_rules::enable_rules_by_tag("no-such-tag"), Ok(0));
+
+        let violations = crate::execute_rules::get_violations(None, Some("test-tag")).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].0, "TAG_RULE_1");
+
+        let security = crate::execute_rules::get_violations(None, Some("security")).unwrap();
+        assert!(security.iter().any(|(code, _)| code == "S003"));
+        assert!(security.iter().all(|(code, _)| code != "B001"));
+
+        fixtures::cleanup_test_rule("TAG_RULE_1");
+        fixtures::cleanup_test_rule("TAG_RULE_2");
+    }
+
+    #[pg_test]
+    fn test_create_update_delete_rule() {
+        use crate::manage_rules::{create_rule, delete_rule, update_rule, RuleDefinition};
+
+        let rule = || RuleDefinition {
+            code: "U001",
+            name: Some("TablesWithoutComment"),
+            scope: Some("base"),
+            q4: Some(
+                "SELECT 'pg_class'::regclass::oid, c.oid, 0
+                 FROM pg_class c WHERE c.relname = 'custom_rule_table'",
+            ),
+            message: Some("{object} has no comment."),
+            severity: Some("notice"),
+            fixes: Some(vec!["COMMENT ON TABLE ... IS '...'".to_string()]),
+            description: Some("table without comment"),
+            tags: Some(vec!["documentation".to_string()]),
+        };
+        let _ = Spi::run("CREATE TABLE custom_rule_table (id INT)");
+
+        assert_eq!(create_rule(rule()), Ok(true));
+        assert_eq!(create_rule(rule()), Ok(false));
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT user_defined FROM pglinter.rules WHERE code = 'U001'"),
+            Ok(Some(true))
+        );
+        assert_eq!(
+            Spi::get_one::<String>("SELECT scope FROM pglinter.rules WHERE code = 'U001'"),
+            Ok(Some("BASE".to_string()))
+        );
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT rule_msg->>'severity' FROM pglinter.rule_messages WHERE code = 'U001'"
+            ),
+            Ok(Some("NOTICE".to_string()))
+        );
+        let violations = crate::execute_rules::get_violations_for_rule("U001").unwrap();
+        assert_eq!(violations.len(), 1);
+
+        // Built-in codes, invalid q4 and invalid severities are rejected
+        assert!(create_rule(RuleDefinition {
+            code: "B001",
+            ..rule()
+        })
+        .is_err());
+        assert!(create_rule(RuleDefinition {
+            code: "U002",
+            q4: Some("SELECT 1"),
+            ..rule()
+        })
+        .is_err());
+        assert!(create_rule(RuleDefinition {
+            code: "U002",
+            q4: Some("SELECT * FROM no_such_table"),
+            ..rule()
+        })
+        .is_err());
+        assert!(create_rule(RuleDefinition {
+            code: "U002",
+            severity: Some("fatal"),
+            ..rule()
+        })
+        .is_err());
+
+        assert_eq!(
+            update_rule(RuleDefinition {
+                code: "U001",
+                severity: Some("error"),
+                message: Some("{object} needs a comment."),
+                ..Default::default()
+            }),
+            Ok(true)
+        );
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT rule_msg->>'severity' || ' ' || (rule_msg->>'message')
+                 FROM pglinter.rule_messages WHERE code = 'U001'"
+            ),
+            Ok(Some("ERROR {object} needs a comment.".to_string()))
+        );
+        assert_eq!(
+            Spi::get_one::<String>("SELECT name FROM pglinter.rules WHERE code = 'U001'"),
+            Ok(Some("TablesWithoutComment".to_string()))
+        );
+        assert!(update_rule(RuleDefinition {
+            code: "B001",
+            name: Some("renamed"),
+            ..Default::default()
+        })
+        .is_err());
+
+        assert!(delete_rule("B001").is_err());
+        assert_eq!(delete_rule("U001"), Ok(true));
+        assert_eq!(delete_rule("U001"), Ok(false));
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rule_messages WHERE code = 'U001'"),
+            Ok(Some(0))
+        );
+        let _ = Spi::run("DROP TABLE custom_rule_table");
+    }
+
+    #[pg_test]
+    fn test_rule_overrides() {
+        fixtures::setup_test_rule("OVERRIDE_RULE", 9993, "Override Rule", true);
+
+        use crate::manage_rules;
+        assert_eq!(manage_rules::disable_rule("OVERRIDE_RULE"), Ok(true));
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT enable FROM pglinter.rule_overrides WHERE code = 'OVERRIDE_RULE'"
+            ),
+            Ok(Some(false))
+        );
+
+        // Reloading the built-in rule loses the change until the override is applied
+        fixtures::setup_test_rule("OVERRIDE_RULE", 9993, "Override Rule", true);
+        assert_eq!(
+            fixtures::get_rule_bool_property("OVERRIDE_RULE", "enable"),
+            Some(true)
+        );
+        let _ =
+            Spi::run("UPDATE pglinter.rule_overrides SET code = code WHERE code = 'OVERRIDE_RULE'");
+        assert_eq!(
+            fixtures::get_rule_bool_property("OVERRIDE_RULE", "enable"),
+            Some(false)
+        );
+
+        // User-defined rules are not recorded
+        let _ =
+            Spi::run("UPDATE pglinter.rules SET user_defined = true WHERE code = 'OVERRIDE_RULE'");
+        let _ = Spi::run("DELETE FROM pglinter.rule_overrides WHERE code = 'OVERRIDE_RULE'");
+        assert_eq!(manage_rules::enable_rule("OVERRIDE_RULE"), Ok(true));
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM pglinter.rule_overrides WHERE code = 'OVERRIDE_RULE'"
+            ),
+            Ok(Some(0))
+        );
+
+        fixtures::cleanup_test_rule("OVERRIDE_RULE");
+    }
+
+    #[pg_test]
+    fn test_import_rules_modes() {
+        use crate::manage_rules::ImportMode;
+
+        fixtures::cleanup_test_rule("TEST_IMPORT_1");
+        fixtures::cleanup_test_rule("TEST_IMPORT_2");
+        // Same id as TEST_IMPORT_1 in the YAML document
+        fixtures::setup_test_rule("OTHER_RULE", 9998, "Other Rule", true);
+        let _ = Spi::run("UPDATE pglinter.rules SET user_defined = true WHERE code = 'OTHER_RULE'");
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('OTHER_RULE', '{\"severity\": \"WARNING\", \"message\": \"Other {object}\"}')",
+        );
+        let _ = Spi::run("INSERT INTO pglinter.profiles (name) VALUES ('import_profile')");
+        let _ = Spi::run(
+            "INSERT INTO pglinter.profile_rules (profile_name, rule_code)
+             VALUES ('import_profile', 'OTHER_RULE'), ('import_profile', 'B001')",
+        );
+        let yaml = fixtures::get_valid_yaml_content();
+
+        let preview = manage_rules::import_rules_from_yaml(yaml, ImportMode::DryRun).unwrap();
+        assert!(preview.contains("2 added"));
+        assert!(preview.contains("+ TEST_IMPORT_1 added"));
+        assert!(preview.contains("- OTHER_RULE removed"));
+        assert!(!preview.contains("- B001 removed"));
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM pglinter.rules WHERE code = 'TEST_IMPORT_1')"
+            ),
+            Ok(Some(false))
+        );
+
+        let merged = manage_rules::import_rules_from_yaml(yaml, ImportMode::Merge).unwrap();
+        assert!(merged.contains("2 new rules, 0 updated rules"));
+        assert_eq!(
+            Spi::get_one::<String>("SELECT name FROM pglinter.rules WHERE code = 'OTHER_RULE'"),
+            Ok(Some("Other Rule".to_string()))
+        );
+        assert_eq!(
+            fixtures::get_rule_bool_property("TEST_IMPORT_1", "user_defined"),
+            Some(true)
+        );
+
+        let _ = Spi::run("UPDATE pglinter.rules SET enable = true WHERE code = 'TEST_IMPORT_2'");
+        let preview = manage_rules::import_rules_from_yaml(yaml, ImportMode::DryRun).unwrap();
+        assert!(preview.contains("= TEST_IMPORT_1 unchanged"));
+        assert!(preview.contains("~ TEST_IMPORT_2 changed (enable)"));
+
+        let replaced = manage_rules::import_rules_from_yaml(yaml, ImportMode::Replace).unwrap();
+        assert!(replaced.contains("0 new rules, 2 updated rules, 1 removed rules"));
+        // Built-in rules are kept, the removed rule leaves no rows behind
+        assert_eq!(
+            Spi::get_one::<bool>("SELECT EXISTS(SELECT 1 FROM pglinter.rules WHERE code = 'B001')"),
+            Ok(Some(true))
+        );
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM pglinter.rules WHERE code = 'OTHER_RULE')"
+            ),
+            Ok(Some(false))
+        );
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM pglinter.rule_messages
+                 WHERE code NOT IN (SELECT code FROM pglinter.rules)"
+            ),
+            Ok(Some(0))
+        );
+        assert_eq!(
+            Spi::get_one::<Vec<String>>(
+                "SELECT array_agg(rule_code) FROM pglinter.profile_rules
+                 WHERE profile_name = 'import_profile'"
+            ),
+            Ok(Some(vec!["B001".to_string()]))
+        );
+        assert_eq!(
+            fixtures::get_rule_bool_property("TEST_IMPORT_2", "enable"),
+            Some(false)
+        );
+
+        assert!(ImportMode::parse("DRY_RUN").is_ok());
+        assert!(ImportMode::parse("upsert").is_err());
+
+        fixtures::cleanup_test_rule("TEST_IMPORT_1");
+        fixtures::cleanup_test_rule("TEST_IMPORT_2");
+    }
+
+    #[pg_test]
+    fn test_import_is_atomic() {
+        use crate::manage_rules::ImportMode;
+
+        fixtures::cleanup_test_rule("TEST_IMPORT_1");
+        fixtures::cleanup_test_rule("TEST_IMPORT_2");
+        let valid_yaml = fixtures::get_valid_yaml_content();
+        let invalid_yaml = valid_yaml
+            .replace("code: \"TEST_IMPORT_2\"", "code: \"TEST_IMPORT_1\"")
+            .replace(
+                "fixes: [\"Fix A\", \"Fix B\", \"Fix C\"]",
+                "fixes: [\"Fix A\"]\n    warning_level: 90\n    error_level: 10",
+            );
+
+        let errors =
+            manage_rules::import_rules_from_yaml(&invalid_yaml, ImportMode::Merge).unwrap_err();
+        assert_eq!(errors.0.len(), 2);
+        assert!(errors.0.iter().all(|e| e.item == "TEST_IMPORT_1"));
+        assert_eq!(errors.to_string(), "2 errors, nothing was imported");
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM pglinter.rules WHERE code = 'TEST_IMPORT_1')"
+            ),
+            Ok(Some(false))
+        );
+
+        // check_import reports the errors and never writes
+        assert!(manage_rules::check_import(|| {
+            manage_rules::import_rules_from_yaml(valid_yaml, ImportMode::Merge)
+        })
+        .is_empty());
+        assert_eq!(
+            Spi::get_one::<bool>(
+                "SELECT EXISTS(SELECT 1 FROM pglinter.rules WHERE code = 'TEST_IMPORT_1')"
+            ),
+            Ok(Some(false))
+        );
+        let errors = manage_rules::check_import(|| {
+            manage_rules::import_rules_from_yaml(&invalid_yaml, ImportMode::Merge)
+        });
+        assert_eq!(errors.len(), 2);
+
+        let messages_before =
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rule_messages").unwrap();
+        let result = manage_rules::import_rule_messages_from_yaml("not: [valid");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("YAML parsing error"));
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rule_messages").unwrap(),
+            messages_before
+        );
+    }
+
+    #[pg_test]
+    fn test_config_bundle() {
+        use crate::bundle::{self, BundleFormat};
+        use crate::manage_rules::ImportMode;
+
+        fixtures::cleanup_test_rule("TEST_IMPORT_1");
+        fixtures::cleanup_test_rule("TEST_IMPORT_2");
+        let count_messages = || {
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rule_messages")
+                .unwrap()
+                .unwrap_or(0)
+        };
+
+        // A 1.0 rules export is migrated, messages and profiles are left untouched
+        let messages_before = count_messages();
+        let result =
+            bundle::import_config(fixtures::get_valid_yaml_content(), ImportMode::Merge).unwrap();
+        assert!(result.contains("2 new rules"));
+        assert_eq!(count_messages(), messages_before);
+
+        for format in [BundleFormat::Yaml, BundleFormat::Json] {
+            let exported = bundle::export_config(format).unwrap();
+            let parsed = bundle::parse_bundle(&exported).unwrap();
+            assert_eq!(
+                parsed.metadata.format_version,
+                bundle::BUNDLE_FORMAT_VERSION
+            );
+            assert!(parsed
+                .rules
+                .unwrap()
+                .iter()
+                .any(|rule| rule.code == "TEST_IMPORT_1"));
+            assert_eq!(parsed.rule_messages.unwrap().len() as i64, messages_before);
+
+            let result = bundle::import_config(&exported, ImportMode::Merge).unwrap();
+            assert!(result.contains("0 new rules"));
+            assert!(result.contains("rule messages"));
+            assert!(result.contains("profiles"));
+            assert_eq!(count_messages(), messages_before);
+        }
+
+        let unsupported = "metadata:\n  export_timestamp: now\n  format_version: \"9.0\"\n";
+        assert!(bundle::parse_bundle(unsupported)
+            .unwrap_err()
+            .contains("Unsupported format_version 9.0"));
+        assert!(BundleFormat::parse("xml").is_err());
+
+        fixtures::cleanup_test_rule("TEST_IMPORT_1");
+        fixtures::cleanup_test_rule("TEST_IMPORT_2");
+    }
+
+    #[pg_test]
+    fn test_file_directory() {
+        use crate::bundle;
+
+        let directory = "/tmp/pglinter_file_directory_test";
+        let _ = std::fs::remove_dir_all(directory);
+        std::fs::create_dir_all(format!("{}/sub", directory)).unwrap();
+
+        // File access is disabled until a directory is set
+        let result = manage_rules::export_rules_to_file("/tmp/pglinter_disabled.yaml");
+        assert!(result
+            .unwrap_err()
+            .contains("pglinter.file_directory is not set"));
+
+        Spi::run(&format!("SET pglinter.file_directory = '{}'", directory)).unwrap();
+
+        // Relative paths are taken from the directory
+        let result = manage_rules::export_rules_to_file("sub/rules.yaml");
+        assert!(result.is_ok());
+        assert!(std::path::Path::new(&format!("{}/sub/rules.yaml", directory)).exists());
+        let result = manage_rules::import_rules_from_file(
+            &format!("{}/sub/rules.yaml", directory),
+            manage_rules::ImportMode::DryRun,
+        );
+        assert!(result.is_ok());
+
+        // Paths outside of the directory are rejected
+        let result = manage_rules::export_rules_to_file("../escaped.yaml");
+        assert!(result.unwrap_err().contains("'..' is not allowed"));
+        let result = manage_rules::export_rules_to_file("sub/../../escaped.yaml");
+        assert!(result.unwrap_err().contains("'..' is not allowed"));
+        let result =
+            manage_rules::import_rules_from_file("/etc/passwd", manage_rules::ImportMode::Merge);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("outside of pglinter.file_directory"));
+
+        // Symbolic links do not allow leaving the directory
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", format!("{}/etc", directory)).unwrap();
+            let result =
+                bundle::import_config_from_file("etc/passwd", manage_rules::ImportMode::Merge);
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains("outside of pglinter.file_directory"));
+        }
+
+        let _ = std::fs::remove_dir_all(directory);
+    }
+
+    #[pg_test]
+    fn test_pglinter_roles() {
+        use crate::privileges::{caller_has_role, ADMIN_ROLE};
+
+        Spi::run("CREATE ROLE pglinter_test_user").unwrap();
+        Spi::run("GRANT pglinter_reader TO pglinter_test_user").unwrap();
+
+        // Readers run the linter, but cannot manage it
+        Spi::run("SET ROLE pglinter_test_user").unwrap();
+        assert!(!caller_has_role(ADMIN_ROLE));
+        let score = Spi::get_one::<f64>("SELECT pglinter.get_database_score()");
+        assert!(score.is_ok());
+        Spi::run("RESET ROLE").unwrap();
+
+        Spi::run("GRANT pglinter_admin TO pglinter_test_user").unwrap();
+        Spi::run("SET ROLE pglinter_test_user").unwrap();
+        assert!(caller_has_role(ADMIN_ROLE));
+        Spi::run("RESET ROLE").unwrap();
+    }
+
+    #[pg_test(error = "permission denied for function disable_rule")]
+    fn test_reader_cannot_manage_rules() {
+        Spi::run("CREATE ROLE pglinter_test_user").unwrap();
+        Spi::run("GRANT pglinter_reader TO pglinter_test_user").unwrap();
+        Spi::run("SET ROLE pglinter_test_user").unwrap();
+        Spi::run("SELECT pglinter.disable_rule('B001')").unwrap();
+    }
+
+    #[pg_test]
+    fn test_config_audit() {
+        Spi::run("UPDATE pglinter.rules SET enable = TRUE WHERE code = 'B001'").unwrap();
+        Spi::run("DELETE FROM pglinter.config_audit").unwrap();
+
+        Spi::run("SELECT pglinter.disable_rule('B001')").unwrap();
+        Spi::run("UPDATE pglinter.rules SET warning_level = 42 WHERE code = 'B001'").unwrap();
+        // An update that changes nothing is not recorded
+        Spi::run("SELECT pglinter.disable_rule('B001')").unwrap();
+
+        let entry = Spi::get_three::<String, String, String>(
+            "SELECT changed_by, old_value::TEXT, new_value::TEXT
+             FROM pglinter.config_audit
+             WHERE object_name = 'B001' AND source = 'disable_rule'",
+        )
+        .unwrap();
+        let session_user = Spi::get_one::<String>("SELECT session_user::TEXT").unwrap();
+        assert_eq!(entry.0, session_user);
+        assert_eq!(entry.1.as_deref(), Some(r#"{"enable": true}"#));
+        assert_eq!(entry.2.as_deref(), Some(r#"{"enable": false}"#));
+
+        // Changes made with plain SQL are recorded too
+        let sources = Spi::get_one::<Vec<String>>(
+            "SELECT array_agg(source ORDER BY id) FROM pglinter.config_audit",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(sources, vec!["disable_rule", "SQL"]);
+
+        // The context does not outlive the function call
+        let source =
+            Spi::get_one::<String>("SELECT current_setting('pglinter.audit_source', TRUE)")
+                .unwrap();
+        assert_eq!(source.as_deref(), Some(""));
+
+        let export = Spi::get_one::<String>("SELECT pglinter.export_config_audit_to_yaml()")
+            .unwrap()
+            .unwrap();
+        assert!(export.contains("total_entries: 2"));
+
+        // The recorded role cannot be chosen with a setting
+        Spi::run("SET LOCAL pglinter.audit_role = 'mallory'").unwrap();
+        Spi::run("SELECT pglinter.enable_rule('B001')").unwrap();
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT changed_by FROM pglinter.config_audit WHERE source = 'enable_rule'"
+            ),
+            Ok(session_user)
+        );
+
+        // The source cannot be chosen with a setting either
+        Spi::run("CREATE ROLE audit_forger").unwrap();
+        Spi::run(
+            "GRANT USAGE ON SCHEMA pglinter TO audit_forger;
+             GRANT SELECT, UPDATE ON pglinter.rules TO audit_forger;
+             GRANT INSERT ON pglinter.config_audit TO audit_forger;
+             GRANT USAGE ON SEQUENCE pglinter.config_audit_id_seq TO audit_forger",
+        )
+        .unwrap();
+        Spi::run("SET LOCAL pglinter.audit_source = 'enable_rule'").unwrap();
+        Spi::run("SET LOCAL ROLE audit_forger").unwrap();
+        Spi::run("UPDATE pglinter.rules SET warning_level = 43 WHERE code = 'B001'").unwrap();
+        Spi::run("RESET ROLE").unwrap();
+        Spi::run("RESET pglinter.audit_source").unwrap();
+        assert_eq!(
+            Spi::get_one::<String>(
+                "SELECT source FROM pglinter.config_audit ORDER BY id DESC LIMIT 1"
+            ),
+            Ok(Some("SQL".to_string()))
+        );
+
+        // Profile changes are recorded with their function
+        Spi::run("SELECT pglinter.create_profile('audit_profile', ARRAY['B001'])").unwrap();
+        Spi::run("SELECT pglinter.set_profile_rule('audit_profile', 'B001', 5)").unwrap();
+        Spi::run("SELECT pglinter.drop_profile('audit_profile')").unwrap();
+        let mut profile_changes = Spi::get_one::<Vec<String>>(
+            "SELECT array_agg(source || ':' || object_name || ':' || action)
+             FROM pglinter.config_audit WHERE object_type LIKE 'profile%'",
+        )
+        .unwrap()
+        .unwrap();
+        profile_changes.sort();
+        assert_eq!(
+            profile_changes,
+            vec![
+                "create_profile:audit_profile/B001:INSERT",
+                "create_profile:audit_profile:INSERT",
+                "drop_profile:audit_profile/B001:DELETE",
+                "drop_profile:audit_profile:DELETE",
+                "set_profile_rule:audit_profile/B001:UPDATE",
+            ]
+        );
+        assert!(export.contains("source: disable_rule"));
+        let export = Spi::get_one::<String>(
+            "SELECT pglinter.export_config_audit_to_yaml(now() + interval '1 day')",
+        )
+        .unwrap()
+        .unwrap();
+        assert!(export.contains("total_entries: 0"));
+    }
+
+    #[pg_test]
+    fn test_rules_catalog() {
+        fixtures::setup_test_rule("CATALOG_TEST", 9982, "Catalog Test Rule", false);
+        Spi::run(
+            "UPDATE pglinter.rules
+             SET user_defined = TRUE, tags = '{custom}', warning_level = 5
+             WHERE code = 'CATALOG_TEST'",
+        )
+        .unwrap();
+
+        let rules_count = Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rules").unwrap();
+        let catalog_count =
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rules_catalog()").unwrap();
+        assert_eq!(rules_count, catalog_count);
+
+        let (name, severity, docs_url) = Spi::get_three::<String, String, String>(
+            "SELECT name, severity, docs_url FROM pglinter.rule_info('B001')",
+        )
+        .unwrap();
+        assert_eq!(name.as_deref(), Some("HowManyTableWithoutPrimaryKey"));
+        assert!(severity.is_some());
+        assert_eq!(docs_url.as_deref(), Some(manage_rules::RULES_DOCS_URL));
+
+        let (enabled, tags, params) = Spi::get_three::<bool, Vec<String>, pgrx::JsonB>(
+            "SELECT enabled, tags, params FROM pglinter.rule_info('CATALOG_TEST')",
+        )
+        .unwrap();
+        assert_eq!(enabled, Some(false));
+        assert_eq!(tags, Some(vec!["custom".to_string()]));
+        assert_eq!(params.unwrap().0["warning_level"], 5);
+        let docs_url =
+            Spi::get_one::<String>("SELECT docs_url FROM pglinter.rule_info('CATALOG_TEST')")
+                .unwrap();
+        assert!(docs_url.is_none());
+
+        let missing =
+            Spi::get_one::<i64>("SELECT count(*) FROM pglinter.rule_info('NONEXISTENT')").unwrap();
+        assert_eq!(missing, Some(0));
+
+        fixtures::cleanup_test_rule("CATALOG_TEST");
+    }
+
+    #[pg_test]
+    fn test_message_templates() {
+        let _ = Spi::run("CREATE SCHEMA \"Tmpl\"");
+        let _ = Spi::run("CREATE TABLE \"Tmpl\".\"Orders\" (\"CustId\" INT)");
+        fixtures::setup_test_rule_with_q4(
+            "TMPL_TEST",
+            9979,
+            "Template Rule",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, '\"Tmpl\".\"Orders\"'::regclass::oid, 1",
+        );
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('TMPL_TEST', '{\"severity\": \"WARNING\",
+                 \"message\": \"{type} {object} in {schema}, owned by {owner}\",
+                 \"infos\": [\"{rule_code}: {identity} / {name}.{column} {{literal}} {unknown}\"]}')",
+        );
+        let owner = Spi::get_one::<String>("SELECT current_user::TEXT")
+            .unwrap()
+            .unwrap();
+        let (classid, objid) = Spi::get_two::<i32, i32>(
+            "SELECT 'pg_class'::regclass::oid::INT, '\"Tmpl\".\"Orders\"'::regclass::oid::INT",
+        )
+        .unwrap();
+        let (classid, objid) = (classid.unwrap(), objid.unwrap());
+
+        let rendered =
+            crate::execute_rules::get_sanitized_message("TMPL_TEST", classid, objid, 1, None);
+        let rendered: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(
+            rendered["rule_msg"]["message"],
+            format!("table column \"Tmpl\".\"Orders\".\"CustId\" in Tmpl, owned by {owner}")
+        );
+        assert_eq!(
+            rendered["rule_msg"]["infos"][0],
+            "TMPL_TEST: Tmpl.Orders.CustId / Orders.CustId {literal} {unknown}"
+        );
+
+        // Without a rule_msg the message of the rule is rendered
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'TMPL_TEST'");
+        let _ = Spi::run(
+            "UPDATE pglinter.rules SET message = 'ALTER TABLE {object} OWNER TO {owner};'
+             WHERE code = 'TMPL_TEST'",
+        );
+        let rendered =
+            crate::execute_rules::get_sanitized_message("TMPL_TEST", classid, objid, 0, None);
+        assert_eq!(
+            rendered,
+            format!("ALTER TABLE \"Tmpl\".\"Orders\" OWNER TO {owner};")
+        );
+
+        // An object that cannot be resolved shows its location
+        let rendered =
+            crate::execute_rules::get_sanitized_message("TMPL_TEST", classid, 0, 0, None);
+        assert_eq!(
+            rendered,
+            format!("ALTER TABLE classid={classid}, objid=0, objsubid=0 OWNER TO ;")
+        );
+
+        fixtures::cleanup_test_rule("TMPL_TEST");
+        let _ = Spi::run("DROP SCHEMA \"Tmpl\" CASCADE");
+    }
+
+    #[pg_test]
+    fn test_q4_details() {
+        let _ = Spi::run("CREATE TABLE details_parent (id INT PRIMARY KEY)");
+        let _ = Spi::run(
+            "CREATE TABLE details_child (id INT, parent_id INT REFERENCES details_parent (id))",
+        );
+
+        // B003 reports the table and the columns of the foreign key
+        let violations = crate::execute_rules::get_violations_for_rule("B003").unwrap();
+        let violation = violations
+            .iter()
+            .find(|v| {
+                v.details
+                    .as_ref()
+                    .map(|d| d["table"] == "public.details_child")
+                    == Some(true)
+            })
+            .expect("B003 should report details_child");
+        assert_eq!(
+            violation.details.as_ref().unwrap()["columns"],
+            serde_json::json!(["parent_id"])
+        );
+        let (classid, objid, objsubid) = violation.location();
+        let rendered: serde_json::Value =
+            serde_json::from_str(&crate::execute_rules::get_sanitized_message(
+                "B003",
+                classid,
+                objid,
+                objsubid,
+                violation.details.as_ref(),
+            ))
+            .unwrap();
+        assert_eq!(
+            rendered["rule_msg"]["infos"][0],
+            "How to fix: CREATE INDEX ON public.details_child (parent_id);"
+        );
+
+        // Nested fields, lists and missing details
+        let details = serde_json::json!({"columns": ["a", "b"], "ref": {"type": "int"}, "n": 3});
+        let context = crate::templates::TemplateContext::default().with_details(Some(&details));
+        assert_eq!(
+            context.render("{details.columns} {details.ref.type} {details.n} {details.missing}"),
+            "a, b int 3 {details.missing}"
+        );
+
+        // The details column is optional and must be jsonb
+        let q4 = "SELECT 'pg_class'::regclass::oid, 'details_child'::regclass::oid, 0";
+        assert!(crate::execute_rules::validate_q4(q4).is_ok());
+        assert!(crate::execute_rules::validate_q4(&format!("{q4}, '{{}}'::jsonb")).is_ok());
+        let err = crate::execute_rules::validate_q4(&format!("{q4}, 'x'::text")).unwrap_err();
+        assert!(err.contains("details must be of type jsonb"));
+        let err =
+            crate::execute_rules::validate_q4(&format!("{q4}, '{{}}'::jsonb, 1")).unwrap_err();
+        assert!(err.contains("optional details column"));
+
+        let _ = Spi::run("DROP TABLE details_child, details_parent");
+    }
+
+    #[pg_test]
+    fn test_generate_fix_script() {
+        let _ = Spi::run("CREATE SCHEMA fix_schema");
+        let _ = Spi::run("CREATE TABLE fix_schema.parent (id INT PRIMARY KEY)");
+        let _ = Spi::run(
+            "CREATE TABLE fix_schema.\"Child\" (\"ParentId\" INT REFERENCES fix_schema.parent (id))",
+        );
+        let _ = Spi::run("GRANT CREATE ON SCHEMA fix_schema TO PUBLIC");
+
+        let script = crate::fixes::generate_fix_script(Some("b003")).unwrap();
+        assert!(script.starts_with("-- pglinter fix script"));
+        assert!(script.contains("CONCURRENTLY statements cannot run inside a transaction block"));
+        assert!(script.contains("(risk: low)\n"));
+        assert!(
+            script.contains("CREATE INDEX CONCURRENTLY ON fix_schema.\"Child\" (\"ParentId\");")
+        );
+
+        let fixes = crate::fixes::generate_fixes(Some("S003")).unwrap();
+        let fix = fixes
+            .iter()
+            .find(|fix| fix.statement == "REVOKE CREATE ON SCHEMA fix_schema FROM PUBLIC;")
+            .expect("S003 should revoke CREATE on fix_schema");
+        assert_eq!(fix.risk, crate::fixes::Risk::Medium);
+        assert!(!fix.destructive);
+        assert!(!fix.is_concurrent());
+
+        let err = crate::fixes::generate_fix_script(Some("B001")).unwrap_err();
+        assert!(err.contains("No automated fix is available for rule B001"));
+        let err = crate::fixes::generate_fix_script(Some("NOPE")).unwrap_err();
+        assert!(err.contains("not found"));
+
+        let _ = Spi::run("DROP SCHEMA fix_schema CASCADE");
+    }
+
+    #[pg_test]
+    fn test_apply_fixes() {
+        use crate::fixes::{apply_fixes, FixStatus};
+        let _ = Spi::run("CREATE SCHEMA apply_schema");
+        let _ = Spi::run("CREATE TABLE apply_schema.parent (id INT PRIMARY KEY)");
+        let _ = Spi::run(
+            "CREATE TABLE apply_schema.child (id INT, parent_id INT REFERENCES apply_schema.parent (id))",
+        );
+        let _ = Spi::run("CREATE INDEX child_id_idx ON apply_schema.child (id)");
+        let _ = Spi::run("CREATE INDEX child_id_parent_idx ON apply_schema.child (id, parent_id)");
+        let index_exists = |name: &str| {
+            Spi::get_one::<bool>(&format!(
+                "SELECT to_regclass('apply_schema.{name}') IS NOT NULL"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+        let in_schema = |outcomes: Vec<crate::fixes::FixOutcome>| {
+            outcomes
+                .into_iter()
+                .filter(|outcome| outcome.statement.contains("apply_schema."))
+                .collect::<Vec<_>>()
+        };
+
+        // Dry run: nothing is executed, statements run without CONCURRENTLY
+        let outcomes =
+            in_schema(apply_fixes(Some(vec!["B003".into()]), true, false, "5s").unwrap());
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].status, FixStatus::DryRun);
+        assert_eq!(
+            outcomes[0].statement,
+            "CREATE INDEX ON apply_schema.child (parent_id);"
+        );
+        // Without CONCURRENTLY, the index blocks writes until the transaction commits
+        assert_eq!(outcomes[0].fix.risk, crate::fixes::Risk::Medium);
+        assert_eq!(
+            outcomes[0].detail.as_deref(),
+            Some("run without CONCURRENTLY, SHARE lock held until the transaction commits")
+        );
+        let indexes = || {
+            Spi::get_one::<i64>("SELECT count(*) FROM pg_indexes WHERE schemaname = 'apply_schema'")
+                .unwrap()
+                .unwrap()
+        };
+        let before = indexes();
+
+        let outcomes =
+            in_schema(apply_fixes(Some(vec!["B003".into()]), false, false, "5s").unwrap());
+        assert_eq!(outcomes[0].status, FixStatus::Applied);
+        assert_eq!(indexes(), before + 1);
+
+        // A statement reported by several rules is listed once
+        let outcomes = in_schema(
+            apply_fixes(Some(vec!["B002".into(), "B004".into()]), true, true, "5s").unwrap(),
+        );
+        let mut statements: Vec<&str> = outcomes.iter().map(|o| o.statement.as_str()).collect();
+        assert_eq!(
+            statements
+                .iter()
+                .filter(|s| s.contains("child_id_idx"))
+                .count(),
+            1
+        );
+        statements.sort();
+        statements.dedup();
+        assert_eq!(statements.len(), outcomes.len());
+
+        // Destructive fixes need allow_destructive
+        let outcomes =
+            in_schema(apply_fixes(Some(vec!["B002".into()]), false, false, "5s").unwrap());
+        assert!(!outcomes.is_empty());
+        assert!(outcomes.iter().all(|o| o.status == FixStatus::Skipped));
+        assert!(index_exists("child_id_idx"));
+        let outcomes =
+            in_schema(apply_fixes(Some(vec!["B002".into()]), false, true, "5s").unwrap());
+        assert!(outcomes
+            .iter()
+            .any(|o| o.status == FixStatus::Applied && o.statement.contains("child_id_idx")));
+        assert!(!index_exists("child_id_idx"));
+
+        // lock_timeout is checked and restored
+        let timeout = Spi::get_one::<String>("SELECT current_setting('lock_timeout')").unwrap();
+        assert!(apply_fixes(Some(vec!["B003".into()]), false, false, "soon").is_err());
+        assert!(apply_fixes(Some(vec!["NOPE".into()]), true, false, "5s").is_err());
+        let _ = apply_fixes(Some(vec!["B003".into()]), false, false, "1s").unwrap();
+        assert_eq!(
+            Spi::get_one::<String>("SELECT current_setting('lock_timeout')").unwrap(),
+            timeout
+        );
+
+        let _ = Spi::run("DROP SCHEMA apply_schema CASCADE");
+    }
+
+    #[pg_test]
+    fn test_localized_messages() {
+        fixtures::setup_test_rule_with_q4(
+            "L10N_TEST",
+            9978,
+            "Localized Rule",
+            "BASE",
+            "SELECT 'pg_namespace'::regclass::oid, 'public'::regnamespace::oid, 0",
+        );
+        let _ = Spi::run(
+            "INSERT INTO pglinter.rule_messages (code, rule_msg)
+             VALUES ('L10N_TEST', '{\"severity\": \"WARNING\",
+                 \"message\": \"Schema {object} is checked.\", \"advices\": \"Check it.\",
+                 \"locales\": {
+                     \"fr\": {\"message\": \"Le schéma {object} est vérifié.\"},
+                     \"de-DE\": {\"message\": \"Schema {object} wird geprüft.\", \"advices\": \"Prüfen.\"}
+                 }}')",
+        );
+        let (classid, objid) = Spi::get_two::<i32, i32>(
+            "SELECT 'pg_namespace'::regclass::oid::INT, 'public'::regnamespace::oid::INT",
+        )
+        .unwrap();
+        let message = |language: &str| {
+            Spi::run(&format!("SET pglinter.language = '{language}'")).unwrap();
+            let rendered = crate::execute_rules::get_sanitized_message(
+                "L10N_TEST",
+                classid.unwrap(),
+                objid.unwrap(),
+                0,
+                None,
+            );
+            let rule_msg: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+            rule_msg["rule_msg"].clone()
+        };
+
+        // Missing fields and languages fall back to English
+        let fr = message("fr_FR.UTF-8");
+        assert_eq!(fr["message"], "Le schéma public est vérifié.");
+        assert_eq!(fr["advices"], "Check it.");
+        assert!(fr.get("locales").is_none());
+        let de = message("de_DE");
+        assert_eq!(de["message"], "Schema public wird geprüft.");
+        assert_eq!(de["advices"], "Prüfen.");
+        assert_eq!(message("es")["message"], "Schema public is checked.");
+        assert_eq!(message("C")["message"], "Schema public is checked.");
+
+        assert_eq!(
+            crate::templates::language_candidates("pt_BR.UTF-8@euro"),
+            vec!["pt_BR".to_string(), "pt".to_string()]
+        );
+        assert!(crate::templates::language_candidates("POSIX").is_empty());
+
+        // The translations are exported with the message
+        let yaml = manage_rules::export_rule_messages_to_yaml().unwrap();
+        assert!(yaml.contains("Le schéma {object} est vérifié."));
+
+        let _ = Spi::run("RESET pglinter.language");
+        fixtures::cleanup_test_rule("L10N_TEST");
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'L10N_TEST'");
+    }
+
+    #[pg_test]
+    fn test_rule_msg_validation() {
+        use crate::execute_rules::in_subtransaction;
+        use manage_rules::validate_rule_msg;
+        use serde_json::json;
+
+        assert!(validate_rule_msg(&json!({
+            "severity": "warning", "message": "m", "advices": null, "infos": ["i"],
+            "doc_url": "https://example.com",
+            "locales": {"fr": {"message": "fr", "infos": []}}
+        }))
+        .is_ok());
+        let error = |rule_msg: serde_json::Value| validate_rule_msg(&rule_msg).unwrap_err();
+        assert!(error(json!({"message": "m", "advice": "a"})).contains("unknown field 'advice'"));
+        assert!(error(json!({"advices": "a"})).contains("'message' is required"));
+        assert!(error(json!({"message": "m", "severity": "LOW"})).contains("'severity' must be"));
+        assert!(error(json!({"message": "m", "infos": "i"})).contains("array of strings"));
+        assert!(
+            error(json!({"message": "m", "locales": {"fr": {"severity": "INFO"}}}))
+                .contains("unknown field 'locales.fr.severity'")
+        );
+        assert!(error(json!(["m"])).contains("JSON object"));
+
+        fixtures::setup_test_rule("MSG_CHECK", 9977, "Message Check Rule", true);
+        let insert = |rule_msg: &'static str| {
+            in_subtransaction(move || {
+                Spi::run_with_args(
+                    "INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES ('MSG_CHECK', $1::jsonb)",
+                    &[rule_msg.into()],
+                )
+                .map_err(|e| e.to_string())
+            })
+        };
+        let err = insert(r#"{"message": "m", "advice": "a"}"#).unwrap_err();
+        assert!(err.contains("invalid rule_msg: unknown field 'advice'"));
+        assert!(insert(r#"{"message": "m"}"#).is_ok());
+        // One message per code
+        assert!(insert(r#"{"message": "again"}"#).is_err());
+
+        let errors = manage_rules::check_import(|| {
+            manage_rules::import_rule_messages(
+                &[(
+                    "MSG_CHECK".to_string(),
+                    json!({"message": "m", "infos": [1]}),
+                )]
+                .into_iter()
+                .collect(),
+                false,
+            )
+        });
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].item, "MSG_CHECK");
+        assert!(errors[0]
+            .message
+            .contains("'infos' must be an array of strings"));
+
+        let _ = Spi::run("DELETE FROM pglinter.rule_messages WHERE code = 'MSG_CHECK'");
+        fixtures::cleanup_test_rule("MSG_CHECK");
+    }
+
+    #[pg_test]
+    fn test_stream_violations() {
+        // More rows than one fetch, a rule failing in its second batch, then a small rule
+        fixtures::setup_test_rule_with_q4(
+            "STREAM_A",
+            9976,
+            "Stream Test A",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, g, jsonb_build_object('n', g) FROM generate_series(1, 2500) g WHERE nextval('stream_test_seq') > 0",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "STREAM_B",
+            9975,
+            "Stream Test B",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, (1 / (g - 1500))::int FROM generate_series(1, 2000) g",
+        );
+        fixtures::setup_test_rule_with_q4(
+            "STREAM_C",
+            9974,
+            "Stream Test C",
+            "BASE",
+            "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, 0",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rules SET message = 'Object {object} of {owner}' WHERE code = 'STREAM_C'",
+        );
+        let _ = Spi::run(
+            "UPDATE pglinter.rules SET tags = '{stream_test}' WHERE code LIKE 'STREAM\\_%'",
+        );
+        Spi::run("CREATE SEQUENCE stream_test_seq").unwrap();
+
+        // Rows are fetched one batch at a time, when the previous batch is consumed
+        let mut stream =
+            crate::execute_rules::stream_violations(None, Some("stream_test")).unwrap();
+        assert_eq!(stream.next().unwrap().0, "STREAM_A");
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT last_value FROM stream_test_seq"),
+            Ok(Some(1000))
+        );
+        assert_eq!(stream.nth(1000).unwrap().1.objsubid, 1002);
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT last_value FROM stream_test_seq"),
+            Ok(Some(2000))
+        );
+        drop(stream);
+
+        let streamed: Vec<(String, crate::execute_rules::Violation, String)> =
+            crate::execute_rules::stream_violations(None, None)
+                .unwrap()
+                .filter(|(code, _, _)| code.starts_with("STREAM_"))
+                .collect();
+        let rows_of = |code: &str| streamed.iter().filter(|(c, _, _)| c == code).count();
+        assert_eq!(rows_of("STREAM_A"), 2500);
+        assert_eq!(rows_of("STREAM_B"), 1000);
+        assert_eq!(rows_of("STREAM_C"), 1);
+
+        // Rows keep the order of the rules and of each q4
+        let stream_a: Vec<&crate::execute_rules::Violation> = streamed
+            .iter()
+            .filter(|(c, _, _)| c == "STREAM_A")
+            .map(|(_, v, _)| v)
+            .collect();
+        assert!(stream_a
+            .iter()
+            .enumerate()
+            .all(|(i, v)| v.objsubid == i as i32 + 1));
+        assert_eq!(stream_a[2499].details, Some(serde_json::json!({"n": 2500})));
+        assert_eq!(streamed.last().unwrap().0, "STREAM_C");
+
+        // Objects are described by the cursor, messages rendered as for one violation
+        let owner = Spi::get_one::<String>(
+            "SELECT pg_get_userbyid(relowner)::TEXT FROM pg_class WHERE oid = 'pg_class'::regclass",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            streamed.last().unwrap().2,
+            format!("Object pg_catalog.pg_class of {owner}")
+        );
+        assert_eq!(
+            streamed.last().unwrap().2,
+            crate::execute_rules::get_sanitized_message("STREAM_C", 1259, 1259, 0, None)
+        );
+
+        // The SQL function streams the same rows
+        let count = Spi::get_one::<i64>(
+            "SELECT count(*) FROM pglinter.get_violations() WHERE rule_code LIKE 'STREAM\\_%'",
+        )
+        .unwrap();
+        assert_eq!(count, Some(3501));
+
+        fixtures::cleanup_test_rule("STREAM_A");
+        fixtures::cleanup_test_rule("STREAM_B");
+        fixtures::cleanup_test_rule("STREAM_C");
+        let _ = Spi::run("DROP SEQUENCE stream_test_seq");
+    }
+
+    #[pg_test]
+    fn test_parallel_rules() {
+        use crate::parallel::{ParallelRun, TaskMessage};
+        type Rows = Vec<(i32, i32, i32, Option<serde_json::Value>, serde_json::Value)>;
+        let read = |rows: Vec<crate::execute_rules::DescribedViolation>| -> Rows {
+            rows.into_iter()
+                .map(|(v, description)| {
+                    let description = serde_json::to_value(description.ok()).unwrap();
+                    (v.classid, v.objid, v.objsubid, v.details, description)
+                })
+                .collect()
+        };
+        // Nothing is written before the workers start: they only run in a transaction
+        // whose snapshot they share
+        let tasks: Vec<(String, String)> = [
+            (
+                "PARALLEL_A",
+                "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, g, jsonb_build_object('n', g) FROM generate_series(1, 2500) g",
+            ),
+            ("PARALLEL_B", "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, 1 / 0"),
+            (
+                "PARALLEL_C",
+                "SELECT 'pg_namespace'::regclass::oid, oid, 0 FROM pg_namespace ORDER BY nspname",
+            ),
+        ]
+        .into_iter()
+        .map(|(code, q4)| (code.to_string(), crate::templates::described_query(q4)))
+        .collect();
+
+        // The same queries run in the session
+        let serial: Vec<Result<Rows, String>> = tasks
+            .iter()
+            .map(|(_, sql)| {
+                crate::execute_rules::in_subtransaction(|| {
+                    Spi::connect(|client| {
+                        let table = client.select(sql, None, &[])?;
+                        let columns = table.columns()?;
+                        let mut rows = Vec::new();
+                        for row in table {
+                            let description = crate::templates::read_description(
+                                &row,
+                                columns - crate::templates::DESCRIPTION_COLUMNS + 1,
+                            )?;
+                            let violation = crate::execute_rules::read_violation(
+                                &row,
+                                columns > 3 + crate::templates::DESCRIPTION_COLUMNS,
+                            )?;
+                            rows.push((violation, description));
+                        }
+                        Ok(read(rows))
+                    })
+                    .map_err(|e: pgrx::spi::SpiError| e.to_string())
+                })
+            })
+            .collect();
+
+        Spi::run("SET LOCAL pglinter.max_parallel_workers = 2").unwrap();
+        let mut run = ParallelRun::start(&tasks, true).expect("workers should start");
+        let mut parallel: Vec<Result<Rows, String>> = Vec::new();
+        let mut batches = Vec::new();
+        for task in 0..tasks.len() {
+            let mut rows = Vec::new();
+            let result = loop {
+                match run.receive(task) {
+                    TaskMessage::Rows(batch) => {
+                        batches.push(batch.len());
+                        rows.extend(read(batch));
+                    }
+                    TaskMessage::Done(_) => break Ok(rows),
+                    TaskMessage::Failed(e, _) => break Err(e),
+                    TaskMessage::Lost => panic!("task {task} was not completed"),
+                }
+            };
+            parallel.push(result);
+        }
+        drop(run);
+
+        // Same rows in the same order, objects described the same way
+        assert_eq!(serial[0], parallel[0]);
+        assert_eq!(serial[2], parallel[2]);
+        assert_eq!(parallel[0].as_ref().unwrap().len(), 2500);
+        assert_eq!(
+            parallel[0].as_ref().unwrap()[2499].3,
+            Some(serde_json::json!({"n": 2500}))
+        );
+        assert!(parallel[2]
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|row| row.4["quoted_identity"] == "public"));
+        assert!(serial[1].as_ref().unwrap_err().contains("division by zero"));
+        assert!(parallel[1]
+            .as_ref()
+            .unwrap_err()
+            .contains("division by zero"));
+        // Rows arrive in batches, never a whole result at once
+        assert_eq!(&batches[..3], &[1000, 1000, 500]);
+        assert!(batches.iter().all(|&len| len <= 1000));
+
+        // Once the transaction has written data, the rules run in the session
+        Spi::run("CREATE TEMP TABLE parallel_written (id INT)").unwrap();
+        assert!(ParallelRun::start(&tasks, true).is_none());
+        assert!(crate::parallel::run_tasks(&tasks, true)
+            .iter()
+            .all(Option::is_none));
+        Spi::run("RESET pglinter.max_parallel_workers").unwrap();
+    }
+
+    #[pg_test(error = "pglinter worker running rule B001 exited before sending all its violations")]
+    fn test_parallel_worker_killed() {
+        use crate::execute_rules::ViolationStream;
+        use crate::parallel::ParallelRun;
+        let tasks: Vec<(String, String)> = [
+            (
+                "B001",
+                "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, 0 FROM generate_series(1, 2500)",
+            ),
+            ("B002", "SELECT 'pg_class'::regclass::oid, 'pg_class'::regclass::oid, 0"),
+        ]
+        .into_iter()
+        .map(|(code, q4)| (code.to_string(), crate::templates::described_query(q4)))
+        .collect();
+
+        // One worker runs both rules
+        Spi::run("SET LOCAL pglinter.max_parallel_workers = 1").unwrap();
+        let run = ParallelRun::start(&tasks, true).expect("workers should start");
+        let mut stream = ViolationStream::new(
+            vec![("B001".to_string(), Some(0)), ("B002".to_string(), Some(1))],
+            Some(run),
+        );
+        assert_eq!(stream.by_ref().take(1000).count(), 1000);
+
+        // The worker stops after its current batch, in the middle of B001
+        Spi::run(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE backend_type = 'pglinter worker'",
+        )
+        .unwrap();
+        let _ = stream.count();
+    }
+
+    #[pg_test]
+    fn test_upgrade_renumbers_user_rules() {
+        // A rule created with pglinter 2.0.0, which had no user_defined flag
+        fixtures::setup_test_rule("UPGRADE_RULE", 900, "Upgrade Rule", true);
+        Spi::run(
+            "UPDATE pglinter.rules SET user_defined = FALSE WHERE code = 'UPGRADE_RULE';
+             INSERT INTO pglinter.rule_messages (id, code, rule_msg)
+             SELECT 900, 'UPGRADE_RULE', rule_msg FROM pglinter.rule_messages
+             WHERE code = 'B001'",
+        )
+        .unwrap();
+
+        // Rules table section of the upgrade script
+        let script = include_str!("../sql/pglinter--2.0.0--2.1.0.sql");
+        let start = script.find("ALTER TABLE pglinter.rules").unwrap();
+        let end = script.find("-- Ratio thresholds").unwrap();
+        Spi::run(&script[start..end]).unwrap();
+
+        assert_eq!(
+            fixtures::get_rule_bool_property("UPGRADE_RULE", "user_defined"),
+            Some(true)
+        );
+        let rule_id =
+            Spi::get_one::<i32>("SELECT id FROM pglinter.rules WHERE code = 'UPGRADE_RULE'");
+        assert!(rule_id.unwrap().unwrap() >= 1000);
+        let message_id = Spi::get_one::<i32>(
+            "SELECT id FROM pglinter.rule_messages WHERE code = 'UPGRADE_RULE'",
+        );
+        assert!(message_id.unwrap().unwrap() >= 1000);
+        assert_eq!(
+            Spi::get_one::<i64>(
+                "SELECT count(*) FROM pglinter.rules WHERE id < 1000 AND user_defined"
+            ),
+            Ok(Some(0))
+        );
+
+        fixtures::cleanup_test_rule("UPGRADE_RULE");
+    }
 }
 
 /// This module is required by `cargo pgrx test` invocations.
@@ -1,8 +1,12 @@
+use crate::execute_rules::in_subtransaction;
+use crate::files::{resolve_path, FileAccess};
 use pgrx::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Rule {
+    #[serde(default)]
     pub id: i32,
     pub name: String,
     pub code: String,
@@ -11,6 +15,16 @@ pub struct Rule {
     pub message: String,
     pub fixes: Vec<String>,
     pub q4: Option<String>,
+    #[serde(default)]
+    pub q1: Option<String>,
+    #[serde(default)]
+    pub warning_level: Option<i32>,
+    #[serde(default)]
+    pub error_level: Option<i32>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub user_defined: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -125,27 +139,72 @@ pub fn is_rule_enabled(rule_code: &str) -> Result<bool, String> {
     }
 }
 
-pub fn list_rules() -> Result<Vec<(String, String, bool)>, String> {
-    let list_query = "
-        SELECT code, name, enable
-        FROM pglinter.rules
-        ORDER BY code";
+/// Documentation of the built-in rules
+pub const RULES_DOCS_URL: &str = "https://pglinter.readthedocs.io/en/latest/rules/";
 
-    let result: Result<Vec<(String, String, bool)>, spi::SpiError> = Spi::connect(|client| {
+/// A rule as described by rules_catalog() and rule_info()
+#[derive(Debug, Clone)]
+pub struct RuleInfo {
+    pub code: String,
+    pub name: String,
+    pub scope: String,
+    pub enabled: bool,
+    pub severity: Option<String>,
+    pub tags: Vec<String>,
+    pub message: Option<String>,
+    pub fixes: Vec<Option<String>>,
+    /// Configurable thresholds of the rule
+    pub params: serde_json::Value,
+    /// Only built-in rules are documented
+    pub docs_url: Option<String>,
+}
+
+/// Describe every rule, or the rule `rule_code` only
+pub fn rules_catalog(rule_code: Option<&str>) -> Result<Vec<RuleInfo>, String> {
+    let catalog_query = "
+        SELECT r.code, r.name, r.scope, r.enable, m.rule_msg ->> 'severity',
+               r.tags, r.message, r.fixes,
+               jsonb_build_object(
+                   'warning_level', r.warning_level,
+                   'error_level', r.error_level
+               )::TEXT,
+               r.user_defined
+        FROM pglinter.rules r
+        LEFT JOIN pglinter.rule_messages m ON m.code = r.code
+        WHERE $1::TEXT IS NULL OR r.code = $1
+        ORDER BY r.code";
+
+    let result: Result<Vec<RuleInfo>, spi::SpiError> = Spi::connect(|client| {
         let mut rules = Vec::new();
-        for row in client.select(list_query, None, &[])? {
-            let code: String = row.get(1)?.unwrap_or_default();
-            let name: String = row.get(2)?.unwrap_or_default();
-            let enabled: bool = row.get(3)?.unwrap_or(false);
-            rules.push((code, name, enabled));
+        for row in client.select(catalog_query, None, &[rule_code.into()])? {
+            let params: Option<String> = row.get(9)?;
+            let user_defined: bool = row.get(10)?.unwrap_or(false);
+            rules.push(RuleInfo {
+                code: row.get(1)?.unwrap_or_default(),
+                name: row.get(2)?.unwrap_or_default(),
+                scope: row.get(3)?.unwrap_or_default(),
+                enabled: row.get(4)?.unwrap_or(false),
+                severity: row.get(5)?,
+                tags: row.get(6)?.unwrap_or_default(),
+                message: row.get(7)?,
+                fixes: row.get(8)?.unwrap_or_default(),
+                params: params
+                    .and_then(|p| serde_json::from_str(&p).ok())
+                    .unwrap_or(serde_json::Value::Null),
+                docs_url: (!user_defined).then(|| RULES_DOCS_URL.to_string()),
+            });
         }
         Ok(rules)
     });
 
-    match result {
-        Ok(rules) => Ok(rules),
-        Err(e) => Err(format!("Database error: {e}")),
-    }
+    result.map_err(|e| format!("Database error: {e}"))
+}
+
+pub fn list_rules() -> Result<Vec<(String, String, bool)>, String> {
+    Ok(rules_catalog(None)?
+        .into_iter()
+        .map(|rule| (rule.code, rule.name, rule.enabled))
+        .collect())
 }
 
 pub fn show_rule_status() -> Result<bool, String> {
@@ -185,57 +244,34 @@ pub fn show_rule_status() -> Result<bool, String> {
 }
 
 pub fn explain_rule(rule_code: &str) -> Result<String, String> {
-    let explain_query = "
-        SELECT code, name, scope, message, fixes
-        FROM pglinter.rules
-        WHERE code = $1";
-
-    type RuleExplainRow = (String, String, String, String, Vec<Option<String>>);
+    let Some(rule) = rules_catalog(Some(rule_code))?.into_iter().next() else {
+        return Err(format!("Rule '{rule_code}' not found"));
+    };
 
-    let result: Result<Option<RuleExplainRow>, spi::SpiError> = Spi::connect(|client| {
-        let mut rows = client.select(explain_query, None, &[rule_code.into()])?;
-        if let Some(row) = rows.next() {
-            let code: String = row.get(1)?.unwrap_or_default();
-            let name: String = row.get(2)?.unwrap_or_default();
-            let scope: String = row.get(3)?.unwrap_or_default();
-            let message: String = row.get(4)?.unwrap_or_default();
-            let fixes: Vec<Option<String>> = row.get(5)?.unwrap_or_default();
-            Ok(Some((code, name, scope, message, fixes)))
-        } else {
-            Ok(None)
+    // Format the fixes section
+    let fixes_section = if rule.fixes.is_empty() {
+        "No specific fixes available.".to_string()
+    } else {
+        let mut fix_list = String::new();
+        for (i, fix) in rule.fixes.iter().enumerate() {
+            if let Some(fix_text) = fix {
+                fix_list.push_str(&format!("   {}. {}\n", i + 1, fix_text));
+            }
         }
-    });
-
-    match result {
-        Ok(Some((code, name, scope, message, fixes))) => {
-            // Format the fixes section
-            let fixes_section = if fixes.is_empty() {
-                "No specific fixes available.".to_string()
-            } else {
-                let mut fix_list = String::new();
-                for (i, fix) in fixes.iter().enumerate() {
-                    if let Some(fix_text) = fix {
-                        fix_list.push_str(&format!("   {}. {}\n", i + 1, fix_text));
-                    }
-                }
-                fix_list.trim_end().to_string()
-            };
+        fix_list.trim_end().to_string()
+    };
 
-            let explanation = format!(
-                "📖 Rule Explanation for {}\n{}\n\n🎯 Rule Name: {}\n📋 Scope: {}\n\n📝 Message:\n{}\n\n🔧 How to Fix:\n{}\n{}",
-                code,
-                "=".repeat(60),
-                name,
-                scope,
-                message,
-                fixes_section,
-                "=".repeat(60)
-            );
-            Ok(explanation)
-        }
-        Ok(None) => Err(format!("Rule '{rule_code}' not found")),
-        Err(e) => Err(format!("Database error: {e}")),
-    }
+    let explanation = format!(
+        "📖 Rule Explanation for {}\n{}\n\n🎯 Rule Name: {}\n📋 Scope: {}\n\n📝 Message:\n{}\n\n🔧 How to Fix:\n{}\n{}",
+        rule.code,
+        "=".repeat(60),
+        rule.name,
+        rule.scope,
+        rule.message.unwrap_or_default(),
+        fixes_section,
+        "=".repeat(60)
+    );
+    Ok(explanation)
 }
 
 pub fn enable_all_rules() -> Result<usize, String> {
@@ -286,6 +322,472 @@ pub fn disable_all_rules() -> Result<usize, String> {
     }
 }
 
+/// Severities accepted in the rule_msg of a rule.
+const SEVERITIES: [&str; 6] = ["INFO", "NOTICE", "WARNING", "ERROR", "CRITICAL", "BLOCKER"];
+
+/// Fields of a rule_msg, a translation in `locales` may hold all of them but severity.
+const RULE_MSG_FIELDS: [&str; 5] = ["severity", "message", "advices", "infos", "doc_url"];
+
+/// Checks that a rule_msg has the expected structure: a required message, an
+/// optional severity, advices, infos and doc_url, and optional translations
+/// of these fields in `locales`. Unknown fields are rejected, so that a typo
+/// such as "advice" is not silently ignored.
+pub fn validate_rule_msg(rule_msg: &serde_json::Value) -> Result<(), String> {
+    use serde_json::Value;
+
+    fn check_fields(
+        fields: &serde_json::Map<String, Value>,
+        allowed: &[&str],
+        prefix: &str,
+    ) -> Result<(), String> {
+        for (field, value) in fields {
+            if !allowed.contains(&field.as_str()) {
+                return Err(format!(
+                    "unknown field '{prefix}{field}', expected one of {}",
+                    allowed.join(", ")
+                ));
+            }
+            let valid = match field.as_str() {
+                "severity" => value
+                    .as_str()
+                    .is_some_and(|s| SEVERITIES.contains(&s.to_uppercase().as_str())),
+                "message" => value.is_string(),
+                "advices" | "doc_url" => value.is_string() || value.is_null(),
+                "infos" => {
+                    value.is_null()
+                        || value
+                            .as_array()
+                            .is_some_and(|infos| infos.iter().all(Value::is_string))
+                }
+                _ => true,
+            };
+            if !valid {
+                let expected = match field.as_str() {
+                    "severity" => format!("one of {}", SEVERITIES.join(", ")),
+                    "message" => "a string".to_string(),
+                    "infos" => "an array of strings".to_string(),
+                    _ => "a string or null".to_string(),
+                };
+                return Err(format!("field '{prefix}{field}' must be {expected}"));
+            }
+        }
+        Ok(())
+    }
+
+    let Some(fields) = rule_msg.as_object() else {
+        return Err("rule_msg must be a JSON object".to_string());
+    };
+    let mut allowed = RULE_MSG_FIELDS.to_vec();
+    allowed.push("locales");
+    check_fields(fields, &allowed, "")?;
+    if !fields.contains_key("message") {
+        return Err("field 'message' is required".to_string());
+    }
+
+    match fields.get("locales") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(locales)) => {
+            for (locale, translation) in locales {
+                let Some(translation) = translation.as_object() else {
+                    return Err(format!("locale '{locale}' must be a JSON object"));
+                };
+                check_fields(
+                    translation,
+                    &RULE_MSG_FIELDS[1..],
+                    &format!("locales.{locale}."),
+                )?;
+            }
+        }
+        Some(_) => return Err("field 'locales' must be a JSON object".to_string()),
+    }
+    Ok(())
+}
+
+/// Definition of a user-defined rule, a field left to `None` is not changed by update_rule.
+#[derive(Debug, Default)]
+pub struct RuleDefinition<'a> {
+    pub code: &'a str,
+    pub name: Option<&'a str>,
+    pub scope: Option<&'a str>,
+    pub q4: Option<&'a str>,
+    pub message: Option<&'a str>,
+    pub severity: Option<&'a str>,
+    pub fixes: Option<Vec<String>>,
+    pub description: Option<&'a str>,
+    pub tags: Option<Vec<String>>,
+}
+
+fn normalize_severity(severity: Option<&str>) -> Result<Option<String>, String> {
+    match severity.map(|s| s.trim().to_uppercase()) {
+        Some(s) if !SEVERITIES.contains(&s.as_str()) => Err(format!(
+            "Invalid severity '{s}', expected one of {}",
+            SEVERITIES.join(", ")
+        )),
+        other => Ok(other),
+    }
+}
+
+/// Returns whether a rule is user-defined, or None when no rule has this code.
+fn rule_is_user_defined(rule_code: &str) -> Result<Option<bool>, String> {
+    Spi::get_one_with_args::<bool>(
+        "SELECT bool_and(user_defined) FROM pglinter.rules WHERE code = $1",
+        &[rule_code.into()],
+    )
+    .map_err(|e| format!("Database error: {e}"))
+}
+
+/// Create a user-defined rule and its rule_messages entry.
+pub fn create_rule(rule: RuleDefinition) -> Result<bool, String> {
+    let code = rule.code.trim();
+    if code.is_empty() || code.contains(char::is_whitespace) {
+        return Err(format!("Invalid rule code '{}'", rule.code));
+    }
+    let required = |field: Option<&str>, name: &str| match field {
+        Some(value) if !value.trim().is_empty() => Ok(value.to_string()),
+        _ => Err(format!("{name} is required to create rule {code}")),
+    };
+    let name = required(rule.name, "name")?;
+    let scope = required(rule.scope, "scope")?.to_uppercase();
+    let q4 = required(rule.q4, "q4")?;
+    let message = required(rule.message, "message")?;
+    let severity = normalize_severity(rule.severity)?.unwrap_or_else(|| "WARNING".to_string());
+
+    match rule_is_user_defined(code)? {
+        Some(false) => {
+            return Err(format!(
+                "Rule code '{code}' is used by a built-in rule, choose another code"
+            ))
+        }
+        Some(true) => {
+            pgrx::warning!("⚠️  Rule {} already exists, use update_rule()", code);
+            return Ok(false);
+        }
+        None => {}
+    }
+    crate::execute_rules::validate_q4(&q4)?;
+
+    let fixes = rule.fixes.unwrap_or_default();
+    let insert_rule_query = "
+        INSERT INTO pglinter.rules (name, code, enable, scope, message, fixes, q4, tags, user_defined)
+        VALUES ($1, $2, true, $3, $4, $5, $6, COALESCE($7, '{}'), true)";
+    let insert_message_query = "
+        INSERT INTO pglinter.rule_messages (code, rule_msg)
+        VALUES ($1, jsonb_build_object(
+            'severity', $2::TEXT,
+            'message', $3::TEXT,
+            'advices', $4::TEXT,
+            'infos', to_jsonb($5::TEXT[])
+        ))";
+
+    let result: Result<(), spi::SpiError> = Spi::connect_mut(|client| {
+        client.update(
+            insert_rule_query,
+            None,
+            &[
+                name.into(),
+                code.into(),
+                scope.into(),
+                rule.description.unwrap_or(message.as_str()).into(),
+                fixes.clone().into(),
+                q4.into(),
+                rule.tags.into(),
+            ],
+        )?;
+        client.update(
+            insert_message_query,
+            None,
+            &[
+                code.into(),
+                severity.into(),
+                message.as_str().into(),
+                rule.description.unwrap_or_default().into(),
+                fixes.into(),
+            ],
+        )?;
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            pgrx::notice!("✅ Rule {} has been created", code);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Update a user-defined rule and its rule_messages entry, built-in rules are rejected.
+pub fn update_rule(rule: RuleDefinition) -> Result<bool, String> {
+    match rule_is_user_defined(rule.code)? {
+        None => {
+            pgrx::warning!("⚠️  Rule {} not found", rule.code);
+            return Ok(false);
+        }
+        Some(false) => {
+            return Err(format!(
+                "Rule '{}' is a built-in rule and cannot be modified",
+                rule.code
+            ))
+        }
+        Some(true) => {}
+    }
+    let severity = normalize_severity(rule.severity)?;
+    if let Some(q4) = rule.q4 {
+        crate::execute_rules::validate_q4(q4)?;
+    }
+
+    let update_rule_query = "
+        UPDATE pglinter.rules
+        SET name = COALESCE($2, name),
+            scope = COALESCE(upper($3), scope),
+            q4 = COALESCE($4, q4),
+            message = COALESCE($5, message),
+            fixes = COALESCE($6, fixes),
+            tags = COALESCE($7, tags)
+        WHERE code = $1 AND user_defined";
+    let update_message_query = "
+        UPDATE pglinter.rule_messages
+        SET rule_msg = COALESCE(rule_msg, '{}') || jsonb_strip_nulls(jsonb_build_object(
+            'severity', $2::TEXT,
+            'message', $3::TEXT,
+            'advices', $4::TEXT,
+            'infos', to_jsonb($5::TEXT[])
+        ))
+        WHERE code = $1";
+
+    let result: Result<(), spi::SpiError> = Spi::connect_mut(|client| {
+        client.update(
+            update_rule_query,
+            None,
+            &[
+                rule.code.into(),
+                rule.name.into(),
+                rule.scope.into(),
+                rule.q4.into(),
+                rule.description.into(),
+                rule.fixes.clone().into(),
+                rule.tags.into(),
+            ],
+        )?;
+        client.update(
+            update_message_query,
+            None,
+            &[
+                rule.code.into(),
+                severity.into(),
+                rule.message.into(),
+                rule.description.into(),
+                rule.fixes.into(),
+            ],
+        )?;
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            pgrx::notice!("✅ Rule {} has been updated", rule.code);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Delete a user-defined rule, its rule_messages entry and its profile entries.
+pub fn delete_rule(rule_code: &str) -> Result<bool, String> {
+    match rule_is_user_defined(rule_code)? {
+        None => {
+            pgrx::warning!("⚠️  Rule {} not found", rule_code);
+            return Ok(false);
+        }
+        Some(false) => {
+            return Err(format!(
+                "Rule '{rule_code}' is a built-in rule and cannot be deleted, use disable_rule()"
+            ))
+        }
+        Some(true) => {}
+    }
+
+    let result: Result<(), spi::SpiError> = Spi::connect_mut(|client| {
+        for query in [
+            "DELETE FROM pglinter.rules WHERE code = $1 AND user_defined",
+            "DELETE FROM pglinter.rule_messages WHERE code = $1",
+            "DELETE FROM pglinter.profile_rules WHERE rule_code = $1",
+        ] {
+            client.update(query, None, &[rule_code.into()])?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            pgrx::notice!("🗑️  Rule {} has been deleted", rule_code);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Enable or disable the rules matching `condition`, in which `$1` is the given value.
+fn set_rules_enabled_where(condition: &str, value: &str, enable: bool) -> Result<usize, String> {
+    let match_query = format!("SELECT count(*) FROM pglinter.rules WHERE {condition}");
+    let update_query = format!(
+        "UPDATE pglinter.rules SET enable = $2 WHERE enable IS DISTINCT FROM $2 AND {condition}"
+    );
+
+    let result: Result<(i64, usize), spi::SpiError> = Spi::connect_mut(|client| {
+        let matching = client
+            .select(&match_query, None, &[value.into()])?
+            .first()
+            .get_one::<i64>()?
+            .unwrap_or(0);
+        let table = client.update(&update_query, None, &[value.into(), enable.into()])?;
+        Ok((matching, table.len()))
+    });
+
+    match result {
+        Ok((0, _)) => {
+            pgrx::warning!("⚠️  No rule matches '{}'", value);
+            Ok(0)
+        }
+        Ok((_, count)) => {
+            let (icon, action) = if enable {
+                ("✅", "Enabled")
+            } else {
+                ("🔴", "Disabled")
+            };
+            if count > 0 {
+                pgrx::notice!("{} {} {} rule(s) matching '{}'", icon, action, count, value);
+            } else {
+                pgrx::notice!(
+                    "ℹ️  All rules matching '{}' were already {}",
+                    value,
+                    action.to_lowercase()
+                );
+            }
+            Ok(count)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+pub fn enable_rules_by_tag(tag: &str) -> Result<usize, String> {
+    set_rules_enabled_where("lower($1) = ANY(tags)", tag, true)
+}
+
+pub fn disable_rules_by_tag(tag: &str) -> Result<usize, String> {
+    set_rules_enabled_where("lower($1) = ANY(tags)", tag, false)
+}
+
+pub fn enable_rules_by_scope(scope: &str) -> Result<usize, String> {
+    set_rules_enabled_where("upper(scope) = upper($1)", scope, true)
+}
+
+pub fn disable_rules_by_scope(scope: &str) -> Result<usize, String> {
+    set_rules_enabled_where("upper(scope) = upper($1)", scope, false)
+}
+
+/// Check that thresholds are percentages and that the warning level is not above the error level.
+pub fn validate_levels(warning_level: Option<i32>, error_level: Option<i32>) -> Result<(), String> {
+    for level in [warning_level, error_level].into_iter().flatten() {
+        if !(0..=100).contains(&level) {
+            return Err(format!(
+                "Invalid level {level}: thresholds are percentages between 0 and 100"
+            ));
+        }
+    }
+    if let (Some(warning), Some(error)) = (warning_level, error_level) {
+        if warning > error {
+            return Err(format!(
+                "Warning level ({warning}%) must not be greater than error level ({error}%)"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Update the warning and error thresholds of a rule, NULL keeps the current value.
+pub fn update_rule_levels(
+    rule_code: &str,
+    warning_level: Option<i32>,
+    error_level: Option<i32>,
+) -> Result<bool, String> {
+    validate_levels(warning_level, error_level)?;
+
+    let current_query = "SELECT warning_level, error_level FROM pglinter.rules WHERE code = $1";
+    let update_query = "
+        UPDATE pglinter.rules
+        SET warning_level = $2, error_level = $3
+        WHERE code = $1";
+
+    let result: Result<Result<Option<RuleLevels>, String>, spi::SpiError> =
+        Spi::connect_mut(|client| {
+            let mut current = client.select(current_query, None, &[rule_code.into()])?;
+            let Some(row) = current.next() else {
+                return Ok(Ok(None)); // Rule not found
+            };
+            let warning_level = warning_level.or(row.get::<i32>(1)?);
+            let error_level = error_level.or(row.get::<i32>(2)?);
+
+            if let Err(e) = validate_levels(warning_level, error_level) {
+                return Ok(Err(e));
+            }
+
+            client.update(
+                update_query,
+                None,
+                &[rule_code.into(), warning_level.into(), error_level.into()],
+            )?;
+            Ok(Ok(Some((warning_level, error_level))))
+        });
+
+    match result {
+        Ok(Ok(Some((warning_level, error_level)))) => {
+            pgrx::notice!(
+                "✅ Updated rule {} levels: warning={}, error={}",
+                rule_code,
+                format_level(warning_level),
+                format_level(error_level)
+            );
+            Ok(true)
+        }
+        Ok(Ok(None)) => {
+            pgrx::warning!("⚠️  Rule {} not found", rule_code);
+            Ok(false)
+        }
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+type RuleLevels = (Option<i32>, Option<i32>);
+
+fn format_level(level: Option<i32>) -> String {
+    level.map_or_else(|| "NULL".to_string(), |value| value.to_string())
+}
+
+/// Get the warning and error thresholds of a rule
+pub fn get_rule_levels(rule_code: &str) -> Result<String, String> {
+    let query = "SELECT warning_level, error_level FROM pglinter.rules WHERE code = $1";
+
+    let result: Result<Option<RuleLevels>, spi::SpiError> = Spi::connect(|client| {
+        let mut rows = client.select(query, None, &[rule_code.into()])?;
+        match rows.next() {
+            Some(row) => Ok(Some((row.get(1)?, row.get(2)?))),
+            None => Ok(None),
+        }
+    });
+
+    match result {
+        Ok(Some((warning_level, error_level))) => Ok(format!(
+            "warning_level={}, error_level={}",
+            format_level(warning_level),
+            format_level(error_level)
+        )),
+        Ok(None) => Err(format!("Rule '{rule_code}' not found")),
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
 /// Show current q4 rule query for debugging
 pub fn show_rule_queries(rule_code: &str) -> Result<bool, String> {
     let query = "SELECT code, name, q4 FROM pglinter.rules WHERE code = $1";
@@ -322,15 +824,16 @@ pub fn show_rule_queries(rule_code: &str) -> Result<bool, String> {
     }
 }
 
-/// Export all rules to YAML format
-pub fn export_rules_to_yaml() -> Result<String, String> {
+/// Load all rules, ordered by code
+pub fn load_rules() -> Result<Vec<Rule>, spi::SpiError> {
     let query = "
         SELECT id, name, code, enable,
-               scope, message, fixes, q4
+               scope, message, fixes, q4,
+               q1, warning_level, error_level, tags, user_defined
         FROM pglinter.rules
         ORDER BY code";
 
-    let result: Result<Vec<Rule>, spi::SpiError> = Spi::connect(|client| {
+    Spi::connect(|client| {
         let rows = client.select(query, None, &[])?;
         let mut rules = Vec::new();
 
@@ -347,12 +850,24 @@ pub fn export_rules_to_yaml() -> Result<String, String> {
                 message: row.get(6)?.unwrap_or_default(),
                 fixes,
                 q4: row.get(8)?,
+                q1: row.get(9)?,
+                warning_level: row.get(10)?,
+                error_level: row.get(11)?,
+                tags: row
+                    .get::<Vec<Option<String>>>(12)?
+                    .map(|tags| tags.into_iter().flatten().collect()),
+                user_defined: row.get(13)?.unwrap_or(false),
             };
             rules.push(rule);
         }
 
         Ok(rules)
-    });
+    })
+}
+
+/// Export all rules to YAML format
+pub fn export_rules_to_yaml() -> Result<String, String> {
+    let result = load_rules();
 
     match result {
         Ok(rules) => {
@@ -374,17 +889,16 @@ pub fn export_rules_to_yaml() -> Result<String, String> {
     }
 }
 
-/// Export all rule messages to YAML format
-pub fn export_rule_messages_to_yaml() -> Result<String, String> {
+/// Load all rule messages, keyed by rule code
+pub fn load_rule_messages() -> Result<BTreeMap<String, serde_json::Value>, spi::SpiError> {
     use serde_json::Value;
-    use std::collections::BTreeMap;
 
     let query = "
         SELECT code, rule_msg::TEXT
         FROM pglinter.rule_messages
         ORDER BY code";
 
-    let result: Result<BTreeMap<String, Value>, spi::SpiError> = Spi::connect(|client| {
+    Spi::connect(|client| {
         let rows = client.select(query, None, &[])?;
         let mut messages = BTreeMap::new();
         for row in rows {
@@ -397,9 +911,12 @@ pub fn export_rule_messages_to_yaml() -> Result<String, String> {
             messages.insert(code, json_val);
         }
         Ok(messages)
-    });
+    })
+}
 
-    match result {
+/// Export all rule messages to YAML format
+pub fn export_rule_messages_to_yaml() -> Result<String, String> {
+    match load_rule_messages() {
         Ok(messages) => match serde_yaml::to_string(&messages) {
             Ok(yaml) => Ok(yaml),
             Err(e) => Err(format!("YAML serialization error: {}", e)),
@@ -410,85 +927,264 @@ pub fn export_rule_messages_to_yaml() -> Result<String, String> {
 
 /// Export rules to YAML file
 pub fn export_rules_to_file(file_path: &str) -> Result<String, String> {
+    let path = resolve_path(file_path, FileAccess::Write)?;
     let yaml_content = export_rules_to_yaml()?;
 
-    match std::fs::write(file_path, &yaml_content) {
+    match std::fs::write(path, &yaml_content) {
         Ok(_) => Ok(format!("✅ Rules exported successfully to: {}", file_path)),
         Err(e) => Err(format!("File write error: {}", e)),
     }
 }
 
-/// Import rules from YAML format
-pub fn import_rules_from_yaml(yaml_content: &str) -> Result<String, String> {
-    let import_data: RulesExport = match serde_yaml::from_str(yaml_content) {
-        Ok(data) => data,
-        Err(e) => return Err(format!("YAML parsing error: {}", e)),
-    };
+/// An error found by an import. `item` is the rule, message or profile
+/// concerned, it is empty for errors about the whole document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub item: String,
+    pub message: String,
+}
 
-    pgrx::notice!(
-        "📥 Importing {} rules from YAML (format v{})",
-        import_data.metadata.total_rules,
-        import_data.metadata.format_version
+/// All the errors found by an import. When there is one, nothing is imported.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportErrors(pub Vec<ImportError>);
+
+impl ImportErrors {
+    pub fn push(&mut self, item: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ImportError {
+            item: item.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// One line per error, used as the detail of the error raised by the wrappers
+    pub fn detail(&self) -> String {
+        self.0
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.item.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.item, self.message)
+        }
+    }
+}
+
+impl std::fmt::Display for ImportErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.as_slice() {
+            [error] => write!(f, "{}", error),
+            errors => write!(f, "{} errors, nothing was imported", errors.len()),
+        }
+    }
+}
+
+impl From<String> for ImportErrors {
+    fn from(message: String) -> Self {
+        let mut errors = ImportErrors::default();
+        errors.push("", message);
+        errors
+    }
+}
+
+/// How import_rules_from_yaml() applies the rules of a YAML document.
+/// Rules are matched on their code, the ids of the document are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Add the new rules and update the existing ones, other rules are kept
+    Merge,
+    /// Merge, then delete the rules missing from the document
+    Replace,
+    /// Report the changes a replace would make, without writing anything
+    DryRun,
+}
+
+impl ImportMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode.trim().to_lowercase().replace('_', "-").as_str() {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            "dry-run" => Ok(ImportMode::DryRun),
+            _ => Err(format!(
+                "Invalid import mode '{}': expected merge, replace or dry-run",
+                mode
+            )),
+        }
+    }
+}
+
+/// Names of the fields that differ between a rule and its imported version
+fn rule_changes(current: &Rule, imported: &Rule) -> Vec<&'static str> {
+    let mut changes = Vec::new();
+    if current.name != imported.name {
+        changes.push("name");
+    }
+    if current.enable != imported.enable {
+        changes.push("enable");
+    }
+    if current.scope != imported.scope {
+        changes.push("scope");
+    }
+    if current.message != imported.message {
+        changes.push("message");
+    }
+    if current.fixes != imported.fixes {
+        changes.push("fixes");
+    }
+    if current.q4 != imported.q4 {
+        changes.push("q4");
+    }
+    if current.q1 != imported.q1 {
+        changes.push("q1");
+    }
+    if current.warning_level != imported.warning_level {
+        changes.push("warning_level");
+    }
+    if current.error_level != imported.error_level {
+        changes.push("error_level");
+    }
+    // Documents without tags keep the current ones
+    if imported.tags.is_some() && current.tags != imported.tags {
+        changes.push("tags");
+    }
+    changes
+}
+
+/// Per-rule diff between the rules table and an imported document
+fn import_preview(current: &BTreeMap<String, Rule>, imported: &[Rule]) -> String {
+    let imported_codes: BTreeSet<&str> = imported.iter().map(|r| r.code.as_str()).collect();
+    let mut lines = Vec::new();
+    let (mut added, mut changed, mut unchanged, mut removed) = (0, 0, 0, 0);
+
+    for rule in imported {
+        match current.get(&rule.code) {
+            None => {
+                added += 1;
+                lines.push(format!("  + {} added", rule.code));
+            }
+            Some(existing) => {
+                let changes = rule_changes(existing, rule);
+                if changes.is_empty() {
+                    unchanged += 1;
+                    lines.push(format!("  = {} unchanged", rule.code));
+                } else {
+                    changed += 1;
+                    lines.push(format!(
+                        "  ~ {} changed ({})",
+                        rule.code,
+                        changes.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    for (code, rule) in current {
+        if rule.user_defined && !imported_codes.contains(code.as_str()) {
+            removed += 1;
+            lines.push(format!("  - {} removed (replace mode only)", code));
+        }
+    }
+
+    let mut preview = format!(
+        "🔍 Dry run: {} added, {} changed, {} unchanged, {} removed rules",
+        added, changed, unchanged, removed
     );
+    for line in lines {
+        preview.push('\n');
+        preview.push_str(&line);
+    }
+    preview
+}
 
+/// Checks the rules of a YAML document before anything is written
+fn validate_imported_rules(rules: &[Rule]) -> ImportErrors {
+    let mut errors = ImportErrors::default();
+    let mut codes = BTreeSet::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if rule.code.trim().is_empty() {
+            errors.push(format!("rule #{}", index + 1), "code is empty");
+            continue;
+        }
+        if !codes.insert(rule.code.as_str()) {
+            errors.push(&rule.code, "code appears more than once in the document");
+        }
+        if let Err(e) = validate_levels(rule.warning_level, rule.error_level) {
+            errors.push(&rule.code, e);
+        }
+    }
+    errors
+}
+
+/// Writes the imported rules, one subtransaction per rule so that every failing
+/// rule is reported. The caller rolls everything back when an error is returned.
+fn write_imported_rules(
+    current: &BTreeMap<String, Rule>,
+    rules: &[Rule],
+    mode: ImportMode,
+) -> Result<String, ImportErrors> {
     let mut imported_count = 0;
     let mut updated_count = 0;
-    let mut errors = Vec::new();
-
-    for rule in import_data.rules {
-        let fixes_array: Vec<Option<String>> = rule.fixes.into_iter().map(Some).collect();
-        let rule_code_for_error = rule.code.clone();
-
-        let upsert_query = "
-            INSERT INTO pglinter.rules (id, name, code, enable,
-                                       scope, message, fixes, q4)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-            ON CONFLICT (id)
-            DO UPDATE SET
-                name = EXCLUDED.name,
-                code = EXCLUDED.code,
-                enable = EXCLUDED.enable,
-                scope = EXCLUDED.scope,
-                message = EXCLUDED.message,
-                fixes = EXCLUDED.fixes,
-                q4 = EXCLUDED.q4
-            RETURNING (xmax = 0) as is_new";
-
-        let result: Result<bool, spi::SpiError> = Spi::connect_mut(|client| {
-            let mut rows = client.update(
-                upsert_query,
-                None,
+    let mut errors = ImportErrors::default();
+
+    // Rules missing from the rules table are not built-in rules
+    let insert_query = "
+        INSERT INTO pglinter.rules (name, code, enable,
+                                   scope, message, fixes, q4,
+                                   q1, warning_level, error_level, tags, user_defined)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE($11, '{}'), TRUE)";
+    let update_query = "
+        UPDATE pglinter.rules
+        SET name = $1,
+            enable = $3,
+            scope = $4,
+            message = $5,
+            fixes = $6,
+            q4 = $7,
+            q1 = $8,
+            warning_level = $9,
+            error_level = $10,
+            tags = COALESCE($11, tags)
+        WHERE code = $2";
+
+    for rule in rules {
+        let fixes_array: Vec<Option<String>> = rule.fixes.iter().cloned().map(Some).collect();
+        let is_new = !current.contains_key(&rule.code);
+        let query = if is_new { insert_query } else { update_query };
+
+        let result = in_subtransaction(|| {
+            Spi::run_with_args(
+                query,
                 &[
-                    rule.id.into(),
-                    rule.name.into(),
-                    rule.code.into(),
+                    rule.name.clone().into(),
+                    rule.code.clone().into(),
                     rule.enable.into(),
-                    rule.scope.into(),
-                    rule.message.into(),
+                    rule.scope.clone().into(),
+                    rule.message.clone().into(),
                     fixes_array.into(),
-                    rule.q4.into(),
+                    rule.q4.clone().into(),
+                    rule.q1.clone().into(),
+                    rule.warning_level.into(),
+                    rule.error_level.into(),
+                    rule.tags.clone().into(),
                 ],
-            )?;
-
-            if let Some(row) = rows.next() {
-                let is_new: bool = row.get(1)?.unwrap_or(false);
-                Ok(is_new)
-            } else {
-                Ok(false)
-            }
+            )
+            .map_err(|e| e.to_string())
         });
 
         match result {
-            Ok(is_new) => {
-                if is_new {
-                    imported_count += 1;
-                } else {
-                    updated_count += 1;
-                }
-            }
-            Err(e) => {
-                errors.push(format!("Rule {}: {}", rule_code_for_error, e));
-            }
+            Ok(()) if is_new => imported_count += 1,
+            Ok(()) => updated_count += 1,
+            Err(e) => errors.push(&rule.code, e),
         }
     }
 
@@ -497,76 +1193,184 @@ pub fn import_rules_from_yaml(yaml_content: &str) -> Result<String, String> {
         imported_count, updated_count
     );
 
-    if !errors.is_empty() {
-        result_msg.push_str(&format!("\n⚠️  {} errors encountered:", errors.len()));
-        for error in errors.iter().take(5) {
-            result_msg.push_str(&format!("\n  - {}", error));
-        }
-        if errors.len() > 5 {
-            result_msg.push_str(&format!("\n  ... and {} more errors", errors.len() - 5));
+    if mode == ImportMode::Replace {
+        let imported_codes: Vec<String> = rules.iter().map(|r| r.code.clone()).collect();
+        let result: Result<usize, spi::SpiError> = Spi::connect_mut(|client| {
+            // Built-in rules are kept, the messages, profile entries and
+            // overrides of the deleted rules go with them
+            let deleted = client.update(
+                "WITH removed AS (
+                    DELETE FROM pglinter.rules
+                    WHERE user_defined AND code <> ALL($1)
+                    RETURNING code
+                ),
+                removed_messages AS (
+                    DELETE FROM pglinter.rule_messages
+                    WHERE code IN (SELECT code FROM removed)
+                ),
+                removed_profile_rules AS (
+                    DELETE FROM pglinter.profile_rules
+                    WHERE rule_code IN (SELECT code FROM removed)
+                ),
+                removed_overrides AS (
+                    DELETE FROM pglinter.rule_overrides
+                    WHERE code IN (SELECT code FROM removed)
+                )
+                SELECT code FROM removed",
+                None,
+                &[imported_codes.into()],
+            )?;
+            Ok(deleted.len())
+        });
+        match result {
+            Ok(removed_count) => result_msg.push_str(&format!(", {} removed rules", removed_count)),
+            Err(e) => errors.push("", format!("Removing rules: {}", e)),
         }
     }
 
-    Ok(result_msg)
+    if errors.is_empty() {
+        Ok(result_msg)
+    } else {
+        Err(errors)
+    }
 }
 
-/// Import rules from YAML file
-pub fn import_rules_from_file(file_path: &str) -> Result<String, String> {
-    let yaml_content = match std::fs::read_to_string(file_path) {
-        Ok(content) => content,
-        Err(e) => return Err(format!("File read error: {}", e)),
+/// Runs an import and rolls it back, returning the errors it found
+pub fn check_import(
+    import: impl FnOnce() -> Result<String, ImportErrors> + std::panic::UnwindSafe,
+) -> Vec<ImportError> {
+    let result: Result<(), ImportErrors> = in_subtransaction(|| match import() {
+        Ok(_) => Err(ImportErrors::default()),
+        Err(errors) => Err(errors),
+    });
+    result.err().unwrap_or_default().0
+}
+
+/// Import rules, matched on their code. Either every rule is imported, or nothing
+/// is written and all the errors found are returned.
+pub fn import_rules(rules: &[Rule], mode: ImportMode) -> Result<String, ImportErrors> {
+    let errors = validate_imported_rules(rules);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let current: BTreeMap<String, Rule> = match load_rules() {
+        Ok(rules) => rules.into_iter().map(|r| (r.code.clone(), r)).collect(),
+        Err(e) => return Err(format!("Database error: {}", e).into()),
     };
 
-    pgrx::notice!("📂 Reading rules from: {}", file_path);
-    import_rules_from_yaml(&yaml_content)
-}
+    if mode == ImportMode::DryRun {
+        return Ok(import_preview(&current, rules));
+    }
 
-/// Import rule messages from YAML format and replace all entries in pglinter.rule_messages
-pub fn import_rule_messages_from_yaml(yaml_content: &str) -> Result<String, String> {
-    use serde_json::Value;
-    use std::collections::BTreeMap;
+    in_subtransaction(|| write_imported_rules(&current, rules, mode))
+}
 
-    // Parse YAML into BTreeMap<String, Value>
-    let messages: BTreeMap<String, Value> = match serde_yaml::from_str(yaml_content) {
+/// Import rules from YAML format
+pub fn import_rules_from_yaml(
+    yaml_content: &str,
+    mode: ImportMode,
+) -> Result<String, ImportErrors> {
+    let import_data: RulesExport = match serde_yaml::from_str(yaml_content) {
         Ok(data) => data,
-        Err(e) => return Err(format!("YAML parsing error: {}", e)),
+        Err(e) => return Err(format!("YAML parsing error: {}", e).into()),
     };
 
-    let mut errors = Vec::new();
-    let mut inserted = 0;
+    pgrx::notice!(
+        "📥 Importing {} rules from YAML (format v{})",
+        import_data.metadata.total_rules,
+        import_data.metadata.format_version
+    );
 
-    let result: Result<(), spi::SpiError> = Spi::connect_mut(|client| {
-        // Remove all existing entries
-        client.update("DELETE FROM pglinter.rule_messages", None, &[])?;
+    import_rules(&import_data.rules, mode)
+}
 
-        // Insert each rule message
-        for (code, rule_msg) in &messages {
-            let insert_query =
-                "INSERT INTO pglinter.rule_messages (code, rule_msg) VALUES ($1, $2)";
+/// Import rules from YAML file
+pub fn import_rules_from_file(file_path: &str, mode: ImportMode) -> Result<String, ImportErrors> {
+    let path = resolve_path(file_path, FileAccess::Read)?;
+    let yaml_content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("File read error: {}", e).into()),
+    };
+
+    pgrx::notice!("📂 Reading rules from: {}", file_path);
+    import_rules_from_yaml(&yaml_content, mode)
+}
+
+/// Import rule messages. With `replace` the messages missing from the list are
+/// deleted. Either every message is imported, or the current messages are kept.
+pub fn import_rule_messages(
+    messages: &BTreeMap<String, serde_json::Value>,
+    replace: bool,
+) -> Result<String, ImportErrors> {
+    in_subtransaction(|| {
+        let mut errors = ImportErrors::default();
+        let mut inserted = 0;
+
+        if replace {
+            let codes: Vec<String> = messages.keys().cloned().collect();
+            Spi::run_with_args(
+                "DELETE FROM pglinter.rule_messages WHERE code <> ALL($1)",
+                &[codes.into()],
+            )
+            .map_err(|e| format!("Database error: {}", e))?;
+        }
+
+        // Existing messages are updated in place, so that changes to the
+        // messages of built-in rules are recorded as overrides
+        let upsert_query = "
+            WITH updated AS (
+                UPDATE pglinter.rule_messages SET rule_msg = $2::jsonb
+                WHERE code = $1
+                RETURNING 1
+            )
+            INSERT INTO pglinter.rule_messages (code, rule_msg)
+            SELECT $1, $2::jsonb
+            WHERE NOT EXISTS (SELECT 1 FROM updated)";
+        for (code, rule_msg) in messages {
+            if let Err(e) = validate_rule_msg(rule_msg) {
+                errors.push(code, format!("Invalid rule_msg: {e}"));
+                continue;
+            }
             let rule_msg_json =
                 serde_json::to_string(rule_msg).unwrap_or_else(|_| "null".to_string());
-            match client.update(insert_query, None, &[code.into(), rule_msg_json.into()]) {
-                Ok(_) => inserted += 1,
-                Err(e) => errors.push(format!("{}: {}", code, e)),
+            let result = in_subtransaction(|| {
+                Spi::run_with_args(upsert_query, &[code.into(), rule_msg_json.into()])
+                    .map_err(|e| e.to_string())
+            });
+            match result {
+                Ok(()) => inserted += 1,
+                Err(e) => errors.push(code, e),
             }
         }
-        Ok(())
-    });
 
-    match result {
-        Ok(_) => {
-            let mut msg = format!("✅ Imported {} rule messages", inserted);
-            if !errors.is_empty() {
-                msg.push_str(&format!("\n⚠️  {} errors:", errors.len()));
-                for error in errors.iter().take(5) {
-                    msg.push_str(&format!("\n  - {}", error));
-                }
-                if errors.len() > 5 {
-                    msg.push_str(&format!("\n  ... and {} more errors", errors.len() - 5));
-                }
-            }
-            Ok(msg)
+        if errors.is_empty() {
+            Ok(format!("✅ Imported {} rule messages", inserted))
+        } else {
+            Err(errors)
         }
-        Err(e) => Err(format!("Database error: {}", e)),
-    }
+    })
+}
+
+/// Import rule messages from YAML format and replace all entries in pglinter.rule_messages
+pub fn import_rule_messages_from_yaml(yaml_content: &str) -> Result<String, ImportErrors> {
+    // Parse YAML into BTreeMap<String, Value>
+    let messages: BTreeMap<String, serde_json::Value> = match serde_yaml::from_str(yaml_content) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("YAML parsing error: {}", e).into()),
+    };
+
+    import_rule_messages(&messages, true)
+}
+
+/// Import rule messages from YAML file
+pub fn import_rule_messages_from_file(file_path: &str) -> Result<String, ImportErrors> {
+    let path = resolve_path(file_path, FileAccess::Read)?;
+    let yaml_content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("File read error: {}", e).into()),
+    };
+
+    pgrx::notice!("📂 Reading rule messages from: {}", file_path);
+    import_rule_messages_from_yaml(&yaml_content)
 }
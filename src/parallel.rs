@@ -0,0 +1,422 @@
+use crate::execute_rules::{
+    in_subtransaction, read_described_violation, read_violation, DescribedViolation, Violation,
+    STREAM_BATCH_SIZE,
+};
+use crate::templates::ObjectDescription;
+use pgrx::bgworkers::{
+    BackgroundWorker, BackgroundWorkerBuilder, BgWorkerStartTime, SignalWakeFlags,
+};
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::prelude::*;
+use pgrx::PgMemoryContexts;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Number of background workers running the rules, 0 runs them in the session.
+pub static MAX_PARALLEL_WORKERS: GucSetting<i32> = GucSetting::<i32>::new(0);
+
+pub fn init_gucs() {
+    GucRegistry::define_int_guc(
+        c"pglinter.max_parallel_workers",
+        c"Maximum number of background workers running the pglinter rules",
+        c"When greater than 0, the rules are spread over up to this many dynamic \
+          background workers, within max_parallel_workers and max_worker_processes. \
+          When 0, they run one after another in the session.",
+        &MAX_PARALLEL_WORKERS,
+        0,
+        64,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+}
+
+/// Number of workers to use, 0 when the parallel mode is disabled. The setting is
+/// capped by max_parallel_workers and max_worker_processes.
+pub fn max_workers() -> usize {
+    let available = unsafe { pg_sys::max_parallel_workers.min(pg_sys::max_worker_processes) };
+    MAX_PARALLEL_WORKERS.get().min(available).max(0) as usize
+}
+
+/// Workers take their own snapshot: they would miss the changes of the session
+/// once it has written data, or the older snapshot of a repeatable read
+/// transaction. The rules then run in the session.
+fn session_snapshot_shared() -> bool {
+    unsafe {
+        pg_sys::GetTopTransactionIdIfAny() == pg_sys::InvalidTransactionId
+            && pg_sys::XactIsoLevel < pg_sys::XACT_REPEATABLE_READ as i32
+    }
+}
+
+/// Outcome of one rule run by a worker, as run_rules records it.
+pub type TaskResult = (Result<Vec<DescribedViolation>, String>, Duration);
+
+/// Size of the queue through which each worker sends its results. Larger
+/// messages are split by shm_mq, the worker waits while the queue is full.
+const QUEUE_SIZE: usize = 64 * 1024;
+
+/// Start of the shared memory segment. The tasks, a JSON array of
+/// [rule_code, sql] pairs, follow the header, then one queue per worker.
+#[repr(C)]
+struct SharedHeader {
+    task_count: u32,
+    database: pg_sys::Oid,
+    user: pg_sys::Oid,
+    tasks_len: u32,
+    workers: u32,
+    described: bool,
+}
+
+fn maxalign(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+fn tasks_offset() -> usize {
+    maxalign(std::mem::size_of::<SharedHeader>())
+}
+
+fn queue_offset(tasks_len: usize, worker: usize) -> usize {
+    maxalign(tasks_offset() + tasks_len) + worker * QUEUE_SIZE
+}
+
+/// A message received for a task
+pub enum TaskMessage {
+    /// The next batch of rows, in the order of the query
+    Rows(Vec<DescribedViolation>),
+    /// The task is complete
+    Done(Duration),
+    /// The query failed, after the rows already received
+    Failed(String, Duration),
+    /// No worker runs the task anymore: its worker could not be started, or
+    /// exited before completing it
+    Lost,
+}
+
+/// The rules of a run spread over background workers. Worker `n` runs the
+/// tasks `n`, `n + workers`, `n + 2 * workers`... and sends their rows in
+/// batches through its own queue. The results are read task after task, so at
+/// most one batch per worker is in flight: a worker waits while its queue is
+/// full.
+///
+/// Workers connect to the current database as the current user and take their
+/// own snapshot, see `session_snapshot_shared`. Dropping the run detaches the
+/// queues, which stops the workers.
+pub struct ParallelRun {
+    seg: *mut pg_sys::dsm_segment,
+    handle: pg_sys::dsm_handle,
+    queues: Vec<Option<*mut pg_sys::shm_mq_handle>>,
+}
+
+impl ParallelRun {
+    /// Starts the workers for the (rule_code, sql) tasks, or returns `None` when
+    /// the tasks must run in the session. With `described`, the sql is a
+    /// described_query and the rows carry the description of their object.
+    pub fn start(tasks: &[(String, String)], described: bool) -> Option<ParallelRun> {
+        let workers = max_workers().min(tasks.len());
+        if workers == 0 || tasks.len() < 2 {
+            return None;
+        }
+        if !session_snapshot_shared() {
+            pgrx::debug1!("ParallelRun; The transaction has its own snapshot, running serially");
+            return None;
+        }
+
+        let tasks_json = Value::from(
+            tasks
+                .iter()
+                .map(|(code, sql)| json!([code, sql]))
+                .collect::<Vec<_>>(),
+        )
+        .to_string();
+        let size = queue_offset(tasks_json.len(), workers);
+
+        // The queue handles are used by every call of a set returning function
+        unsafe {
+            PgMemoryContexts::TopTransactionContext.switch_to(|_| {
+                let seg = pg_sys::dsm_create(size, pg_sys::DSM_CREATE_NULL_IF_MAXSEGMENTS as i32);
+                if seg.is_null() {
+                    pgrx::debug1!("ParallelRun; No dynamic shared memory segment available");
+                    return None;
+                }
+                let base = pg_sys::dsm_segment_address(seg) as *mut u8;
+                base.cast::<SharedHeader>().write(SharedHeader {
+                    task_count: tasks.len() as u32,
+                    database: pg_sys::MyDatabaseId,
+                    user: pg_sys::GetUserId(),
+                    tasks_len: tasks_json.len() as u32,
+                    workers: workers as u32,
+                    described,
+                });
+                std::ptr::copy_nonoverlapping(
+                    tasks_json.as_ptr(),
+                    base.add(tasks_offset()),
+                    tasks_json.len(),
+                );
+
+                let handle = pg_sys::dsm_segment_handle(seg);
+                let mut queues = Vec::with_capacity(workers);
+                for worker in 0..workers {
+                    let mq = pg_sys::shm_mq_create(
+                        base.add(queue_offset(tasks_json.len(), worker)).cast(),
+                        QUEUE_SIZE,
+                    );
+                    pg_sys::shm_mq_set_receiver(mq, pg_sys::MyProc);
+                    let builder =
+                        BackgroundWorkerBuilder::new(&format!("pglinter worker {worker}"))
+                            .set_type("pglinter worker")
+                            .set_library("pglinter")
+                            .set_function("pglinter_worker_main")
+                            .set_argument(Some(pg_sys::Datum::from(handle)))
+                            .set_extra(&worker.to_string())
+                            .enable_spi_access()
+                            .set_start_time(BgWorkerStartTime::ConsistentState)
+                            .set_notify_pid(pg_sys::MyProcPid);
+                    let mut bgw: pg_sys::BackgroundWorker = (&builder).into();
+                    let mut bgw_handle: *mut pg_sys::BackgroundWorkerHandle = std::ptr::null_mut();
+                    if pg_sys::RegisterDynamicBackgroundWorker(&mut bgw, &mut bgw_handle) {
+                        // With the worker handle, receiving reports a worker that exited early
+                        queues.push(Some(pg_sys::shm_mq_attach(mq, seg, bgw_handle)));
+                    } else {
+                        // No worker slot left, the tasks of this worker run in the session
+                        queues.push(None);
+                    }
+                }
+                let started = queues.iter().flatten().count();
+                pgrx::debug1!(
+                    "ParallelRun; {} of {} workers started for {} rules",
+                    started,
+                    workers,
+                    tasks.len()
+                );
+                if started == 0 {
+                    pg_sys::dsm_detach(seg);
+                    return None;
+                }
+                Some(ParallelRun {
+                    seg,
+                    handle,
+                    queues,
+                })
+            })
+        }
+    }
+
+    /// Waits for the next message of `task`. Tasks must be read in order.
+    pub fn receive(&mut self, task: usize) -> TaskMessage {
+        let worker = task % self.queues.len();
+        let Some(mqh) = self.queues[worker] else {
+            return TaskMessage::Lost;
+        };
+        let mut nbytes: pg_sys::Size = 0;
+        let mut data: *mut std::ffi::c_void = std::ptr::null_mut();
+        let result = unsafe { pg_sys::shm_mq_receive(mqh, &mut nbytes, &mut data, false) };
+        if result != pg_sys::shm_mq_result::SHM_MQ_SUCCESS {
+            self.queues[worker] = None;
+            return TaskMessage::Lost;
+        }
+        let message = unsafe { std::slice::from_raw_parts(data as *const u8, nbytes) };
+        match parse_message(message) {
+            Some((sent_task, message)) if sent_task == task => message,
+            _ => {
+                pgrx::debug1!("ParallelRun; Unexpected message from worker {}", worker);
+                self.queues[worker] = None;
+                TaskMessage::Lost
+            }
+        }
+    }
+}
+
+impl Drop for ParallelRun {
+    fn drop(&mut self) {
+        // An aborted transaction has already detached the segment
+        unsafe {
+            if pg_sys::dsm_find_mapping(self.handle) == self.seg {
+                pg_sys::dsm_detach(self.seg);
+            }
+        }
+    }
+}
+
+/// Runs the q4 query of each (rule_code, q4) task in background workers and
+/// returns the results in the order of the tasks. A task is `None` when no
+/// worker completed it, the caller runs those in the session. With `described`,
+/// the q4 are described_query, see ParallelRun::start.
+pub fn run_tasks(tasks: &[(String, String)], described: bool) -> Vec<Option<TaskResult>> {
+    let mut results: Vec<Option<TaskResult>> = (0..tasks.len()).map(|_| None).collect();
+    let Some(mut run) = ParallelRun::start(tasks, described) else {
+        return results;
+    };
+    for (task, slot) in results.iter_mut().enumerate() {
+        let mut violations = Vec::new();
+        *slot = loop {
+            match run.receive(task) {
+                TaskMessage::Rows(rows) => violations.extend(rows),
+                TaskMessage::Done(duration) => break Some((Ok(violations), duration)),
+                TaskMessage::Failed(e, duration) => break Some((Err(e), duration)),
+                TaskMessage::Lost => break None,
+            }
+        };
+    }
+    results
+}
+
+/// Reads a {"task", "rows"} or {"task", "ms"[, "error"]} message sent by a worker.
+fn parse_message(message: &[u8]) -> Option<(usize, TaskMessage)> {
+    let message: Value = serde_json::from_slice(message).ok()?;
+    let task = message["task"].as_u64()? as usize;
+    if let Some(rows) = message["rows"].as_array() {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let violation = Violation {
+                    classid: row[0].as_i64().unwrap_or_default() as i32,
+                    objid: row[1].as_i64().unwrap_or_default() as i32,
+                    objsubid: row[2].as_i64().unwrap_or_default() as i32,
+                    details: Some(row[3].clone()).filter(|details| !details.is_null()),
+                };
+                let description = serde_json::from_value::<ObjectDescription>(row[4].clone())
+                    .map_err(|_| "object not found".to_string());
+                (violation, description)
+            })
+            .collect();
+        return Some((task, TaskMessage::Rows(rows)));
+    }
+    let duration = Duration::from_secs_f64(message["ms"].as_f64().unwrap_or_default() / 1000.0);
+    match message["error"].as_str() {
+        Some(error) => Some((task, TaskMessage::Failed(error.to_string(), duration))),
+        None => Some((task, TaskMessage::Done(duration))),
+    }
+}
+
+fn rows_message(task: usize, rows: &[DescribedViolation]) -> String {
+    json!({
+        "task": task,
+        "rows": rows
+            .iter()
+            .map(|(v, description)| {
+                json!([v.classid, v.objid, v.objsubid, v.details, description.as_ref().ok()])
+            })
+            .collect::<Vec<_>>(),
+    })
+    .to_string()
+}
+
+fn end_message(task: usize, result: &Result<(), String>, started: Instant) -> String {
+    let ms = started.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(()) => json!({"task": task, "ms": ms}),
+        Err(e) => json!({"task": task, "ms": ms, "error": e}),
+    }
+    .to_string()
+}
+
+/// Sends a message to the session, false when the session stopped listening.
+fn send(mqh: *mut pg_sys::shm_mq_handle, message: &str) -> bool {
+    #[cfg(feature = "pg14")]
+    let sent = unsafe { pg_sys::shm_mq_send(mqh, message.len(), message.as_ptr().cast(), false) };
+    #[cfg(not(feature = "pg14"))]
+    let sent =
+        unsafe { pg_sys::shm_mq_send(mqh, message.len(), message.as_ptr().cast(), false, true) };
+    sent == pg_sys::shm_mq_result::SHM_MQ_SUCCESS
+}
+
+/// Runs the sql of a task behind a cursor and sends its rows batch by batch.
+/// Returns `Ok(false)` when the session stopped listening or the worker was
+/// asked to stop.
+fn send_task_rows(
+    mqh: *mut pg_sys::shm_mq_handle,
+    task: usize,
+    sql: &str,
+    described: bool,
+) -> Result<bool, String> {
+    Spi::connect(|client| {
+        let mut cursor = client.try_open_cursor(sql, &[])?;
+        loop {
+            let rows = cursor.fetch(STREAM_BATCH_SIZE)?;
+            let columns = if rows.is_empty() { 0 } else { rows.columns()? };
+            let mut batch = Vec::with_capacity(rows.len());
+            for row in rows {
+                batch.push(if described {
+                    read_described_violation(&row, columns)?
+                } else {
+                    (
+                        read_violation(&row, columns > 3)?,
+                        Err("object not described".to_string()),
+                    )
+                });
+            }
+            if !batch.is_empty() && !send(mqh, &rows_message(task, &batch)) {
+                return Ok(false);
+            }
+            if (batch.len() as i64) < STREAM_BATCH_SIZE {
+                return Ok(true);
+            }
+            if BackgroundWorker::sigterm_received() {
+                return Ok(false);
+            }
+        }
+    })
+    .map_err(|e: spi::SpiError| format!("SPI error executing q4: {e}"))
+}
+
+/// Entry point of the workers started by ParallelRun. Each worker runs its
+/// tasks in a subtransaction and sends their rows to the session, until no
+/// task is left or the session stops listening.
+#[pg_guard]
+#[no_mangle]
+pub extern "C-unwind" fn pglinter_worker_main(arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGTERM);
+    let worker: usize = BackgroundWorker::get_extra().parse().unwrap_or_default();
+
+    unsafe {
+        let seg = pg_sys::dsm_attach(arg.value() as pg_sys::dsm_handle);
+        if seg.is_null() {
+            // The session is already gone
+            return;
+        }
+        let base = pg_sys::dsm_segment_address(seg) as *mut u8;
+        let header = &*(base as *const SharedHeader);
+        if worker >= header.workers as usize {
+            pg_sys::dsm_detach(seg);
+            return;
+        }
+        let tasks_len = header.tasks_len as usize;
+        let mq = base
+            .add(queue_offset(tasks_len, worker))
+            .cast::<pg_sys::shm_mq>();
+        pg_sys::shm_mq_set_sender(mq, pg_sys::MyProc);
+        let mqh = pg_sys::shm_mq_attach(mq, seg, std::ptr::null_mut());
+
+        BackgroundWorker::connect_worker_to_spi_by_oid(Some(header.database), Some(header.user));
+        let tasks_json = std::slice::from_raw_parts(base.add(tasks_offset()), tasks_len);
+        let tasks: Vec<(String, String)> = serde_json::from_slice(tasks_json).unwrap_or_default();
+        let described = header.described;
+
+        for task in (worker..header.task_count as usize).step_by(header.workers as usize) {
+            if BackgroundWorker::sigterm_received() {
+                break;
+            }
+            let Some((code, sql)) = tasks.get(task) else {
+                break;
+            };
+            let started = Instant::now();
+            let sql = sql.clone();
+            let result: Result<bool, String> = BackgroundWorker::transaction(move || {
+                in_subtransaction(|| send_task_rows(mqh, task, &sql, described))
+            });
+            let result = match result {
+                // The session stopped listening, or the worker must stop
+                Ok(false) => break,
+                Ok(true) => Ok(()),
+                Err(e) => {
+                    pgrx::debug1!("pglinter_worker_main; Error for rule {}: {}", code, e);
+                    Err(e)
+                }
+            };
+            if !send(mqh, &end_message(task, &result, started)) {
+                break;
+            }
+        }
+        pg_sys::shm_mq_detach(mqh);
+        pg_sys::dsm_detach(seg);
+    }
+}
@@ -0,0 +1,53 @@
+use pgrx::prelude::*;
+use std::ffi::CString;
+
+/// Role allowed to manage rules, messages, profiles and files
+pub const ADMIN_ROLE: &str = "pglinter_admin";
+
+/// Whether the calling user has the privileges of `role`. The pglinter functions
+/// run as security definer, so the user checked is the one outside of them.
+/// Superusers have the privileges of every role.
+pub fn caller_has_privs_of(role: pg_sys::Oid) -> bool {
+    unsafe { pg_sys::has_privs_of_role(pg_sys::GetOuterUserId(), role) }
+}
+
+/// Same as `caller_has_privs_of`, for a role given by name
+pub fn caller_has_role(role_name: &str) -> bool {
+    let Ok(name) = CString::new(role_name) else {
+        return false;
+    };
+    let role = unsafe { pg_sys::get_role_oid(name.as_ptr(), true) };
+    if role == pg_sys::InvalidOid {
+        // Only superusers pass when the role was dropped
+        unsafe { pg_sys::superuser_arg(pg_sys::GetOuterUserId()) }
+    } else {
+        caller_has_privs_of(role)
+    }
+}
+
+/// Stops `function` with a permission error unless the caller is a pglinter
+/// administrator
+pub fn require_admin(function: &str) {
+    if !caller_has_role(ADMIN_ROLE) {
+        ereport!(
+            ERROR,
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            format!("permission denied for function {}", function),
+            format!("Only members of {} can manage pglinter.", ADMIN_ROLE)
+        );
+    }
+}
+
+/// Runs `f` with the privileges of the calling user instead of the owner of the
+/// security definer function. `f` must run in a subtransaction: when it raises
+/// an error, the rollback of the subtransaction restores the current user.
+pub fn as_caller<T>(f: impl FnOnce() -> T) -> T {
+    let (mut user, mut sec_context) = (pg_sys::InvalidOid, 0);
+    unsafe {
+        pg_sys::GetUserIdAndSecContext(&mut user, &mut sec_context);
+        pg_sys::SetUserIdAndSecContext(pg_sys::GetOuterUserId(), sec_context);
+    }
+    let result = f();
+    unsafe { pg_sys::SetUserIdAndSecContext(user, sec_context) };
+    result
+}
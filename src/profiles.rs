@@ -0,0 +1,495 @@
+use crate::execute_rules::in_subtransaction;
+use crate::manage_rules::{validate_levels, ImportErrors};
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A rule of a profile, with the thresholds and severity it overrides.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileRule {
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning_level: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_level: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+}
+
+/// A named set of rules, such as dev, staging or prod.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<ProfileRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfilesExportMetadata {
+    pub export_timestamp: String,
+    pub total_profiles: usize,
+    pub format_version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfilesExport {
+    pub metadata: ProfilesExportMetadata,
+    pub profiles: Vec<Profile>,
+}
+
+pub fn profile_exists(profile_name: &str) -> Result<bool, String> {
+    Spi::get_one_with_args::<bool>(
+        "SELECT EXISTS (SELECT 1 FROM pglinter.profiles WHERE name = $1)",
+        &[profile_name.into()],
+    )
+    .map(|exists| exists.unwrap_or(false))
+    .map_err(|e| format!("Database error: {e}"))
+}
+
+/// Returns the codes of the list that match no rule.
+fn unknown_rule_codes(rule_codes: &[String]) -> Result<Vec<String>, String> {
+    Spi::get_one_with_args::<Vec<String>>(
+        "SELECT array_agg(c ORDER BY c)
+         FROM unnest($1::TEXT[]) AS c
+         WHERE NOT EXISTS (SELECT 1 FROM pglinter.rules WHERE code = c)",
+        &[rule_codes.to_vec().into()],
+    )
+    .map(Option::unwrap_or_default)
+    .map_err(|e| format!("Database error: {e}"))
+}
+
+/// Create a profile from a list of rule codes, or from the currently enabled rules when no
+/// list is given. The current thresholds of the rules are copied into the profile.
+pub fn create_profile(
+    profile_name: &str,
+    rule_codes: Option<Vec<String>>,
+    description: Option<&str>,
+) -> Result<bool, String> {
+    if profile_exists(profile_name)? {
+        pgrx::warning!("⚠️  Profile {} already exists", profile_name);
+        return Ok(false);
+    }
+    if let Some(codes) = &rule_codes {
+        let unknown = unknown_rule_codes(codes)?;
+        if !unknown.is_empty() {
+            return Err(format!("Unknown rule code(s): {}", unknown.join(", ")));
+        }
+    }
+
+    let insert_rules_query = "
+        INSERT INTO pglinter.profile_rules (profile_name, rule_code, warning_level, error_level)
+        SELECT DISTINCT ON (code) $1, code, warning_level, error_level
+        FROM pglinter.rules
+        WHERE CASE WHEN $2::TEXT[] IS NULL THEN enable ELSE code = ANY($2) END
+        ORDER BY code, id";
+
+    let result: Result<usize, spi::SpiError> = Spi::connect_mut(|client| {
+        client.update(
+            "INSERT INTO pglinter.profiles (name, description) VALUES ($1, $2)",
+            None,
+            &[profile_name.into(), description.into()],
+        )?;
+        let inserted = client.update(
+            insert_rules_query,
+            None,
+            &[profile_name.into(), rule_codes.into()],
+        )?;
+        Ok(inserted.len())
+    });
+
+    match result {
+        Ok(count) => {
+            pgrx::notice!("✅ Profile {} created with {} rule(s)", profile_name, count);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+pub fn drop_profile(profile_name: &str) -> Result<bool, String> {
+    let result: Result<usize, spi::SpiError> = Spi::connect_mut(|client| {
+        let deleted = client.update(
+            "DELETE FROM pglinter.profiles WHERE name = $1",
+            None,
+            &[profile_name.into()],
+        )?;
+        Ok(deleted.len())
+    });
+
+    match result {
+        Ok(0) => {
+            pgrx::warning!("⚠️  Profile {} not found", profile_name);
+            Ok(false)
+        }
+        Ok(_) => {
+            pgrx::notice!("🗑️  Profile {} has been dropped", profile_name);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Add a rule to a profile, or update the overrides of a rule already in the profile.
+pub fn set_profile_rule(
+    profile_name: &str,
+    rule_code: &str,
+    warning_level: Option<i32>,
+    error_level: Option<i32>,
+    severity: Option<&str>,
+) -> Result<bool, String> {
+    validate_levels(warning_level, error_level)?;
+    if !profile_exists(profile_name)? {
+        pgrx::warning!("⚠️  Profile {} not found", profile_name);
+        return Ok(false);
+    }
+    if !unknown_rule_codes(&[rule_code.to_string()])?.is_empty() {
+        return Err(format!("Rule '{rule_code}' not found"));
+    }
+
+    let upsert_query = "
+        INSERT INTO pglinter.profile_rules
+            (profile_name, rule_code, warning_level, error_level, severity)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (profile_name, rule_code)
+        DO UPDATE SET
+            warning_level = EXCLUDED.warning_level,
+            error_level = EXCLUDED.error_level,
+            severity = EXCLUDED.severity";
+
+    let result: Result<(), spi::SpiError> = Spi::connect_mut(|client| {
+        client.update(
+            upsert_query,
+            None,
+            &[
+                profile_name.into(),
+                rule_code.into(),
+                warning_level.into(),
+                error_level.into(),
+                severity.map(str::to_uppercase).into(),
+            ],
+        )?;
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            pgrx::notice!("✅ Rule {} set in profile {}", rule_code, profile_name);
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+pub fn remove_profile_rule(profile_name: &str, rule_code: &str) -> Result<bool, String> {
+    let result: Result<usize, spi::SpiError> = Spi::connect_mut(|client| {
+        let deleted = client.update(
+            "DELETE FROM pglinter.profile_rules WHERE profile_name = $1 AND rule_code = $2",
+            None,
+            &[profile_name.into(), rule_code.into()],
+        )?;
+        Ok(deleted.len())
+    });
+
+    match result {
+        Ok(0) => {
+            pgrx::warning!("⚠️  Rule {} is not in profile {}", rule_code, profile_name);
+            Ok(false)
+        }
+        Ok(_) => {
+            pgrx::notice!(
+                "🔴 Rule {} removed from profile {}",
+                rule_code,
+                profile_name
+            );
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Make a profile the active configuration: its rules are enabled, every other rule is
+/// disabled, and its threshold and severity overrides are written to the rules.
+///
+/// The previous configuration is overwritten and cannot be restored from the profile.
+/// A severity override replaces the severity of every translation of the message too.
+pub fn apply_profile(profile_name: &str) -> Result<bool, String> {
+    if !profile_exists(profile_name)? {
+        pgrx::warning!("⚠️  Profile {} not found", profile_name);
+        return Ok(false);
+    }
+
+    let enable_query = "
+        UPDATE pglinter.rules r
+        SET enable = EXISTS (
+            SELECT 1
+            FROM pglinter.profile_rules pr
+            WHERE pr.profile_name = $1 AND pr.rule_code = r.code
+        )
+        RETURNING enable";
+    let levels_query = "
+        UPDATE pglinter.rules r
+        SET warning_level = COALESCE(pr.warning_level, r.warning_level),
+            error_level = COALESCE(pr.error_level, r.error_level)
+        FROM pglinter.profile_rules pr
+        WHERE pr.profile_name = $1 AND pr.rule_code = r.code";
+    let severity_query = "
+        UPDATE pglinter.rule_messages m
+        SET rule_msg = CASE
+            WHEN jsonb_typeof(m.rule_msg->'locales') = 'object' THEN jsonb_set(
+                jsonb_set(m.rule_msg, '{severity}', to_jsonb(pr.severity)),
+                '{locales}',
+                COALESCE((
+                    SELECT jsonb_object_agg(l.locale, l.translation - 'severity')
+                    FROM jsonb_each(m.rule_msg->'locales') AS l (locale, translation)
+                ), '{}'::jsonb)
+            )
+            ELSE jsonb_set(m.rule_msg, '{severity}', to_jsonb(pr.severity))
+        END
+        FROM pglinter.profile_rules pr
+        WHERE pr.profile_name = $1
+          AND pr.rule_code = m.code
+          AND pr.severity IS NOT NULL";
+
+    let result: Result<usize, spi::SpiError> = Spi::connect_mut(|client| {
+        let mut enabled = 0;
+        for row in client.update(enable_query, None, &[profile_name.into()])? {
+            if row.get::<bool>(1)?.unwrap_or(false) {
+                enabled += 1;
+            }
+        }
+        client.update(levels_query, None, &[profile_name.into()])?;
+        client.update(severity_query, None, &[profile_name.into()])?;
+        Ok(enabled)
+    });
+
+    match result {
+        Ok(enabled) => {
+            pgrx::notice!(
+                "✅ Profile {} applied: {} rule(s) enabled",
+                profile_name,
+                enabled
+            );
+            Ok(true)
+        }
+        Err(e) => Err(format!("Database error: {e}")),
+    }
+}
+
+/// Returns the severity overrides of a profile, keyed by rule code.
+pub fn profile_severities(profile_name: &str) -> Result<BTreeMap<String, String>, String> {
+    Spi::connect(|client| {
+        let mut severities = BTreeMap::new();
+        let rows = client.select(
+            "SELECT rule_code, severity
+             FROM pglinter.profile_rules
+             WHERE profile_name = $1 AND severity IS NOT NULL",
+            None,
+            &[profile_name.into()],
+        )?;
+        for row in rows {
+            let code: String = row.get(1)?.unwrap_or_default();
+            let severity: String = row.get(2)?.unwrap_or_default();
+            severities.insert(code, severity);
+        }
+        Ok(severities)
+    })
+    .map_err(|e: spi::SpiError| format!("Database error: {e}"))
+}
+
+/// Replaces the severity of a message built by get_sanitized_message.
+pub fn with_severity(message: String, severity: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(&message) {
+        Ok(mut json_val) => match json_val
+            .get_mut("rule_msg")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            Some(rule_msg) => {
+                rule_msg.insert("severity".to_string(), severity.into());
+                json_val.to_string()
+            }
+            None => message,
+        },
+        Err(_) => message,
+    }
+}
+
+/// Load all profiles with their rules, ordered by name
+pub fn load_profiles() -> Result<Vec<Profile>, spi::SpiError> {
+    let query = "
+        SELECT p.name, p.description,
+               pr.rule_code, pr.warning_level, pr.error_level, pr.severity
+        FROM pglinter.profiles p
+        LEFT JOIN pglinter.profile_rules pr ON pr.profile_name = p.name
+        ORDER BY p.name, pr.rule_code";
+
+    Spi::connect(|client| {
+        let mut profiles: Vec<Profile> = Vec::new();
+        for row in client.select(query, None, &[])? {
+            let name: String = row.get(1)?.unwrap_or_default();
+            if profiles.last().map(|p| &p.name) != Some(&name) {
+                profiles.push(Profile {
+                    name,
+                    description: row.get(2)?,
+                    rules: Vec::new(),
+                });
+            }
+            if let Some(code) = row.get::<String>(3)? {
+                if let Some(profile) = profiles.last_mut() {
+                    profile.rules.push(ProfileRule {
+                        code,
+                        warning_level: row.get(4)?,
+                        error_level: row.get(5)?,
+                        severity: row.get(6)?,
+                    });
+                }
+            }
+        }
+        Ok(profiles)
+    })
+}
+
+/// Export all profiles to YAML format
+pub fn export_profiles_to_yaml() -> Result<String, String> {
+    let result = load_profiles();
+
+    match result {
+        Ok(profiles) => {
+            let export_data = ProfilesExport {
+                metadata: ProfilesExportMetadata {
+                    export_timestamp: chrono::Utc::now().to_rfc3339(),
+                    total_profiles: profiles.len(),
+                    format_version: "1.0".to_string(),
+                },
+                profiles,
+            };
+
+            match serde_yaml::to_string(&export_data) {
+                Ok(yaml) => Ok(yaml),
+                Err(e) => Err(format!("YAML serialization error: {}", e)),
+            }
+        }
+        Err(e) => Err(format!("Database error: {}", e)),
+    }
+}
+
+/// Import profiles, an existing profile is replaced by the imported one. With
+/// `replace` the profiles missing from the list are deleted. Either every profile
+/// is imported, or nothing is written and all the errors found are returned.
+pub fn import_profiles(profiles: &[Profile], replace: bool) -> Result<String, ImportErrors> {
+    let mut errors = ImportErrors::default();
+    for profile in profiles {
+        let codes: Vec<String> = profile.rules.iter().map(|r| r.code.clone()).collect();
+        let unknown = unknown_rule_codes(&codes)?;
+        for rule in &profile.rules {
+            if unknown.contains(&rule.code) {
+                errors.push(&profile.name, format!("rule {} not found", rule.code));
+            } else if let Err(e) = validate_levels(rule.warning_level, rule.error_level) {
+                errors.push(&profile.name, format!("rule {}: {}", rule.code, e));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    in_subtransaction(|| {
+        let mut imported_count = 0;
+        let mut updated_count = 0;
+        let mut errors = ImportErrors::default();
+
+        for profile in profiles {
+            let is_new = !profile_exists(&profile.name)?;
+            let result = in_subtransaction(|| {
+                write_profile(profile).map_err(|e: spi::SpiError| e.to_string())
+            });
+
+            match result {
+                Ok(()) if is_new => imported_count += 1,
+                Ok(()) => updated_count += 1,
+                Err(e) => errors.push(&profile.name, e),
+            }
+        }
+
+        let mut result_msg = format!(
+            "✅ Import completed: {} new profiles, {} updated profiles",
+            imported_count, updated_count
+        );
+        if replace {
+            let names: Vec<String> = profiles.iter().map(|p| p.name.clone()).collect();
+            let removed = Spi::connect_mut(|client| {
+                client
+                    .update(
+                        "DELETE FROM pglinter.profiles WHERE name <> ALL($1)",
+                        None,
+                        &[names.into()],
+                    )
+                    .map(|deleted| deleted.len())
+            });
+            match removed {
+                Ok(count) => result_msg.push_str(&format!(", {} removed profiles", count)),
+                Err(e) => errors.push("", format!("Removing profiles: {}", e)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(result_msg)
+        } else {
+            Err(errors)
+        }
+    })
+}
+
+/// Import profiles from YAML format, an existing profile is replaced by the imported one.
+pub fn import_profiles_from_yaml(yaml_content: &str) -> Result<String, ImportErrors> {
+    let import_data: ProfilesExport = match serde_yaml::from_str(yaml_content) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("YAML parsing error: {}", e).into()),
+    };
+
+    pgrx::notice!(
+        "📥 Importing {} profiles from YAML (format v{})",
+        import_data.metadata.total_profiles,
+        import_data.metadata.format_version
+    );
+
+    import_profiles(&import_data.profiles, false)
+}
+
+/// Create or replace a profile and its rules
+fn write_profile(profile: &Profile) -> Result<(), spi::SpiError> {
+    Spi::connect_mut(|client| {
+        client.update(
+            "INSERT INTO pglinter.profiles (name, description) VALUES ($1, $2)
+             ON CONFLICT (name) DO UPDATE SET description = EXCLUDED.description",
+            None,
+            &[
+                profile.name.as_str().into(),
+                profile.description.clone().into(),
+            ],
+        )?;
+        client.update(
+            "DELETE FROM pglinter.profile_rules WHERE profile_name = $1",
+            None,
+            &[profile.name.as_str().into()],
+        )?;
+        for rule in &profile.rules {
+            client.update(
+                "INSERT INTO pglinter.profile_rules
+                    (profile_name, rule_code, warning_level, error_level, severity)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (profile_name, rule_code) DO NOTHING",
+                None,
+                &[
+                    profile.name.as_str().into(),
+                    rule.code.as_str().into(),
+                    rule.warning_level.into(),
+                    rule.error_level.into(),
+                    rule.severity.as_ref().map(|s| s.to_uppercase()).into(),
+                ],
+            )?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,1004 @@
+use crate::execute_rules::{
+    count_checked_objects, run_described_rules, run_enabled_rules, DescribedViolation,
+    ObjectIdentity, RuleMessage, RuleRun,
+};
+use crate::templates::{described_context, localize, preferred_languages};
+use md5::{Digest, Md5};
+use pgrx::prelude::*;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Rule scopes in the order they are reported, custom scopes follow alphabetically.
+const SCOPE_ORDER: [&str; 3] = ["BASE", "SCHEMA", "CLUSTER"];
+
+/// Groups rule runs by scope, keeping BASE, SCHEMA and CLUSTER first.
+fn group_by_scope(runs: Vec<RuleRun>) -> Vec<(String, Vec<RuleRun>)> {
+    let mut groups: Vec<(String, Vec<RuleRun>)> = Vec::new();
+    for run in runs {
+        match groups.iter().position(|(scope, _)| *scope == run.scope) {
+            Some(i) => groups[i].1.push(run),
+            None => groups.push((run.scope.clone(), vec![run])),
+        }
+    }
+    groups.sort_by_key(|(scope, _)| {
+        let rank = SCOPE_ORDER
+            .iter()
+            .position(|s| s == scope)
+            .unwrap_or(SCOPE_ORDER.len());
+        (rank, scope.clone())
+    });
+    groups
+}
+
+/// A violation with its resolved object and its rendered rule message.
+struct ReportedViolation {
+    classid: i32,
+    objid: i32,
+    objsubid: i32,
+    object: Option<ObjectIdentity>,
+    details: Option<Value>,
+    message: String,
+    /// Severity of the rendered message, as get_violations returns it
+    severity: Option<String>,
+    advices: Option<String>,
+    infos: Vec<String>,
+}
+
+impl ReportedViolation {
+    /// Renders the `message` of the rule, read once for all its violations, for a
+    /// violation described by its q4 query.
+    fn new(
+        rule_code: &str,
+        message: Option<&RuleMessage>,
+        (violation, description): &DescribedViolation,
+    ) -> Self {
+        let (classid, objid, objsubid) = violation.location();
+        let object = description.as_ref().ok().map(|object| ObjectIdentity {
+            object_type: object.object_type.clone(),
+            schema: object.schema.clone(),
+            name: object.name.clone(),
+            identity: object.quoted_identity.clone(),
+        });
+        // The message is {"rule_msg": {...}} or a plain text fallback
+        let sanitized = RuleMessage::render_or_error(
+            message,
+            rule_code,
+            described_context(classid, objid, objsubid, description.clone()),
+            violation.details.as_ref(),
+        );
+        let rule_msg = serde_json::from_str::<Value>(&sanitized)
+            .ok()
+            .and_then(|json| json.get("rule_msg").cloned());
+
+        let (message, severity, advices, infos) = match rule_msg {
+            Some(rule_msg) => (
+                rule_msg["message"].as_str().unwrap_or_default().to_string(),
+                rule_msg["severity"].as_str().map(str::to_string),
+                rule_msg["advices"].as_str().map(str::to_string),
+                rule_msg["infos"]
+                    .as_array()
+                    .map(|infos| {
+                        infos
+                            .iter()
+                            .filter_map(|info| info.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            ),
+            None => (sanitized, None, None, Vec::new()),
+        };
+
+        ReportedViolation {
+            classid,
+            objid,
+            objsubid,
+            object,
+            details: violation.details.clone(),
+            message,
+            severity,
+            advices,
+            infos,
+        }
+    }
+
+    /// Object identity, or the raw location when the object cannot be resolved.
+    fn identity(&self) -> String {
+        match &self.object {
+            Some(object) => object.identity.clone(),
+            None => format!("{}/{}/{}", self.classid, self.objid, self.objsubid),
+        }
+    }
+
+    /// Object type and identity, or the raw location when the object cannot be resolved.
+    fn description(&self) -> String {
+        match &self.object {
+            Some(object) => format!("{} {}", object.object_type, object.identity),
+            None => format!(
+                "classid={}, objid={}, objsubid={}",
+                self.classid, self.objid, self.objsubid
+            ),
+        }
+    }
+
+    fn to_json(&self, rule_code: &str) -> Value {
+        json!({
+            "rule_code": rule_code,
+            "severity": self.severity,
+            "classid": self.classid,
+            "objid": self.objid,
+            "objsubid": self.objsubid,
+            "object": self.object.as_ref().map(|object| json!({
+                "type": object.object_type,
+                "schema": object.schema,
+                "name": object.name,
+                "identity": object.identity,
+            })),
+            "details": self.details,
+            "message": self.message,
+            "advices": self.advices,
+            "infos": self.infos,
+        })
+    }
+}
+
+/// Where and when a report was generated.
+struct ReportMetadata {
+    database: Option<String>,
+    server_version: Option<String>,
+    extension_version: Option<String>,
+    generated_at: String,
+}
+
+fn report_metadata() -> Result<ReportMetadata, String> {
+    Spi::connect(|client| {
+        let row = client
+            .select(
+                "SELECT current_database()::TEXT,
+                        current_setting('server_version'),
+                        (SELECT extversion FROM pg_catalog.pg_extension WHERE extname = 'pglinter')",
+                None,
+                &[],
+            )?
+            .first();
+        Ok::<_, spi::SpiError>(ReportMetadata {
+            database: row.get::<String>(1)?,
+            server_version: row.get::<String>(2)?,
+            extension_version: row.get::<String>(3)?,
+            generated_at: chrono::Utc::now().to_rfc3339(),
+        })
+    })
+    .map_err(|e| format!("Database error fetching report metadata: {e}"))
+}
+
+/// Returns the severity of the rule_msg of a rule in the language of the messages, if any.
+/// Violations carry the severity of their rendered message, this one is used for the rule.
+fn rule_severity(rule_code: &str) -> Result<Option<String>, String> {
+    Spi::connect(|client| {
+        let mut rows = client.select(
+            "SELECT rule_msg::TEXT, current_setting('lc_messages')
+             FROM pglinter.rule_messages WHERE code = $1",
+            None,
+            &[rule_code.into()],
+        )?;
+        let Some(row) = rows.next() else {
+            return Ok(None);
+        };
+        let lc_messages: Option<String> = row.get(2)?;
+        Ok(row
+            .get::<String>(1)?
+            .and_then(|rule_msg| serde_json::from_str::<Value>(&rule_msg).ok())
+            .map(|rule_msg| localize(rule_msg, &preferred_languages(lc_messages.as_deref())))
+            .and_then(|rule_msg| rule_msg["severity"].as_str().map(str::to_string)))
+    })
+    .map_err(|e: spi::SpiError| {
+        format!(
+            "Database error fetching severity for rule '{}': {e}",
+            rule_code
+        )
+    })
+}
+
+/// Maps a rule_msg severity to one of the GitLab code quality severity levels.
+fn gitlab_severity(severity: Option<&str>) -> &'static str {
+    match severity.map(str::to_uppercase).as_deref() {
+        Some("INFO") | Some("NOTICE") => "info",
+        Some("ERROR") => "major",
+        Some("CRITICAL") => "critical",
+        Some("BLOCKER") => "blocker",
+        _ => "minor",
+    }
+}
+
+/// Stable fingerprint of a violation: the md5 of the rule code and the object identity.
+fn fingerprint(rule_code: &str, identity: &str) -> String {
+    Md5::digest(format!("{rule_code}:{identity}").as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds a JUnit XML report: one testsuite per scope, one testcase per enabled rule.
+///
+/// Each violation is a `<failure>` of its rule testcase, a rule whose q4 failed is an `<error>`.
+pub fn get_violations_junit() -> Result<String, String> {
+    let groups = group_by_scope(run_described_rules()?);
+
+    let mut suites = String::new();
+    let (mut total_tests, mut total_failures, mut total_errors) = (0, 0, 0);
+    let mut total_time = 0.0;
+
+    for (scope, runs) in &groups {
+        let (mut failures, mut errors) = (0, 0);
+        let mut time = 0.0;
+        let mut cases = String::new();
+
+        for run in runs {
+            let seconds = run.duration.as_secs_f64();
+            time += seconds;
+            cases.push_str(&format!(
+                "    <testcase classname=\"pglinter.{}\" name=\"{} {}\" time=\"{:.3}\"",
+                xml_escape(scope),
+                xml_escape(&run.code),
+                xml_escape(&run.name),
+                seconds
+            ));
+
+            match &run.violations {
+                Ok(violations) if violations.is_empty() => cases.push_str("/>\n"),
+                Ok(violations) => {
+                    failures += 1;
+                    cases.push_str(">\n");
+                    let message = RuleMessage::find(&run.code);
+                    for found in violations {
+                        let violation = ReportedViolation::new(&run.code, message.as_ref(), found);
+                        cases.push_str(&format!(
+                            "      <failure type=\"{}\" message=\"{}\">{}</failure>\n",
+                            xml_escape(&run.code),
+                            xml_escape(&violation.message),
+                            xml_escape(&violation.description())
+                        ));
+                    }
+                    cases.push_str("    </testcase>\n");
+                }
+                Err(e) => {
+                    errors += 1;
+                    cases.push_str(&format!(
+                        ">\n      <error type=\"RuleExecutionError\" message=\"{}\"/>\n    </testcase>\n",
+                        xml_escape(e)
+                    ));
+                }
+            }
+        }
+
+        suites.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n{}  </testsuite>\n",
+            xml_escape(scope),
+            runs.len(),
+            failures,
+            errors,
+            time,
+            cases
+        ));
+        total_tests += runs.len();
+        total_failures += failures;
+        total_errors += errors;
+        total_time += time;
+    }
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"pglinter\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">\n{}</testsuites>\n",
+        total_tests, total_failures, total_errors, total_time, suites
+    ))
+}
+
+/// Builds a GitLab Code Quality report (Code Climate JSON array), one issue per violation.
+///
+/// Rules whose q4 failed cannot be reported as issues, they are skipped with a warning.
+pub fn get_violations_codequality() -> Result<Value, String> {
+    let mut issues = Vec::new();
+
+    for run in run_described_rules()? {
+        let violations = match run.violations {
+            Ok(violations) => violations,
+            Err(e) => {
+                pgrx::warning!("Rule {} skipped from code quality report: {}", run.code, e);
+                continue;
+            }
+        };
+        if violations.is_empty() {
+            continue;
+        }
+
+        let message = RuleMessage::find(&run.code);
+        for found in &violations {
+            let violation = ReportedViolation::new(&run.code, message.as_ref(), found);
+            let severity = gitlab_severity(violation.severity.as_deref());
+            let identity = violation.identity();
+            let description = if violation.message.is_empty() {
+                format!("{}: {}", run.name, identity)
+            } else {
+                violation.message
+            };
+
+            issues.push(json!({
+                "type": "issue",
+                "check_name": run.code,
+                "description": description,
+                "severity": severity,
+                "fingerprint": fingerprint(&run.code, &identity),
+                "location": {
+                    "path": identity,
+                    "lines": { "begin": 1 }
+                }
+            }));
+        }
+    }
+
+    Ok(Value::Array(issues))
+}
+
+/// Builds a single JSON document with run metadata, per-rule summaries and every violation.
+pub fn get_report_json() -> Result<Value, String> {
+    let metadata = report_metadata()?;
+
+    let mut rules = Vec::new();
+    let mut violations = Vec::new();
+    let (mut failed, mut errored) = (0, 0);
+
+    for run in run_described_rules()? {
+        let mut severity = None;
+        let (status, count, error) = match &run.violations {
+            Ok(found) if found.is_empty() => ("passed", 0, None),
+            Ok(found) => {
+                failed += 1;
+                let message = RuleMessage::find(&run.code);
+                for location in found {
+                    let violation = ReportedViolation::new(&run.code, message.as_ref(), location);
+                    severity = severity.or_else(|| violation.severity.clone());
+                    violations.push(violation.to_json(&run.code));
+                }
+                ("failed", found.len(), None)
+            }
+            Err(e) => {
+                errored += 1;
+                ("error", 0, Some(e.clone()))
+            }
+        };
+
+        let severity = match severity {
+            Some(severity) => Some(severity),
+            None => rule_severity(&run.code)?,
+        };
+        rules.push(json!({
+            "code": run.code,
+            "name": run.name,
+            "scope": run.scope,
+            "severity": severity,
+            "status": status,
+            "violation_count": count,
+            "duration_ms": run.duration.as_secs_f64() * 1000.0,
+            "error": error,
+        }));
+    }
+
+    Ok(json!({
+        "metadata": {
+            "tool": "pglinter",
+            "format_version": "1.0",
+            "database": metadata.database,
+            "server_version": metadata.server_version,
+            "extension_version": metadata.extension_version,
+            "generated_at": metadata.generated_at,
+        },
+        "summary": {
+            "rules_executed": rules.len(),
+            "rules_passed": rules.len() - failed - errored,
+            "rules_failed": failed,
+            "rules_errored": errored,
+            "total_violations": violations.len(),
+        },
+        "rules": rules,
+        "violations": violations,
+    }))
+}
+
+/// Everything a human readable report shows about one enabled rule.
+struct RuleSection {
+    code: String,
+    name: String,
+    scope: String,
+    severity: Option<String>,
+    explanation: String,
+    fixes: Vec<String>,
+    advices: Option<String>,
+    error: Option<String>,
+    violation_count: usize,
+    /// Violations grouped by the schema of their object, in schema name order.
+    schemas: BTreeMap<String, Vec<ReportedViolation>>,
+}
+
+impl RuleSection {
+    fn status(&self) -> &'static str {
+        match (&self.error, self.violation_count) {
+            (Some(_), _) => "error",
+            (None, 0) => "passed",
+            _ => "failed",
+        }
+    }
+}
+
+/// Label used to group violations whose object has no schema (cluster objects, unresolved ones).
+const NO_SCHEMA: &str = "(no schema)";
+
+fn rule_sections() -> Result<Vec<RuleSection>, String> {
+    let details_query = "
+        SELECT r.code, r.message, r.fixes, m.rule_msg::TEXT, current_setting('lc_messages')
+        FROM pglinter.rules r
+        LEFT JOIN LATERAL (
+            SELECT rule_msg FROM pglinter.rule_messages WHERE code = r.code LIMIT 1
+        ) m ON true
+        WHERE r.enable = true";
+
+    type RuleDetails = (String, Vec<String>, Option<String>, Option<String>);
+    let mut details: BTreeMap<String, RuleDetails> = Spi::connect(|client| {
+        let mut details = BTreeMap::new();
+        for row in client.select(details_query, None, &[])? {
+            let code: String = row.get(1)?.unwrap_or_default();
+            let explanation: String = row.get(2)?.unwrap_or_default();
+            let fixes: Vec<Option<String>> = row.get(3)?.unwrap_or_default();
+            let rule_msg = row
+                .get::<String>(4)?
+                .and_then(|rule_msg| serde_json::from_str::<Value>(&rule_msg).ok())
+                .map(|rule_msg| {
+                    let lc_messages: Option<String> = row.get(5).ok().flatten();
+                    localize(rule_msg, &preferred_languages(lc_messages.as_deref()))
+                })
+                .unwrap_or_default();
+            let severity = rule_msg["severity"].as_str().map(str::to_string);
+            let advices = rule_msg["advices"].as_str().map(str::to_string);
+            details.insert(
+                code,
+                (
+                    explanation,
+                    fixes.into_iter().flatten().collect(),
+                    severity,
+                    advices,
+                ),
+            );
+        }
+        Ok(details)
+    })
+    .map_err(|e: spi::SpiError| format!("Database error fetching rule details: {e}"))?;
+
+    let mut sections = Vec::new();
+    for (_, runs) in group_by_scope(run_described_rules()?) {
+        for run in runs {
+            let (explanation, fixes, severity, advices) =
+                details.remove(&run.code).unwrap_or_default();
+            let mut section = RuleSection {
+                code: run.code,
+                name: run.name,
+                scope: run.scope,
+                severity,
+                explanation,
+                fixes,
+                advices,
+                error: None,
+                violation_count: 0,
+                schemas: BTreeMap::new(),
+            };
+            match run.violations {
+                Ok(found) => {
+                    section.violation_count = found.len();
+                    let message = RuleMessage::find(&section.code);
+                    for location in &found {
+                        let violation =
+                            ReportedViolation::new(&section.code, message.as_ref(), location);
+                        let schema = violation
+                            .object
+                            .as_ref()
+                            .and_then(|object| object.schema.clone())
+                            .unwrap_or_else(|| NO_SCHEMA.to_string());
+                        section.schemas.entry(schema).or_default().push(violation);
+                    }
+                }
+                Err(e) => section.error = Some(e),
+            }
+            sections.push(section);
+        }
+    }
+    Ok(sections)
+}
+
+/// Characters escaped in the text inserted in a Markdown report, so that names and
+/// messages cannot open a code span, a heading, a link, HTML or a table cell.
+const MARKDOWN_SPECIAL: [char; 10] = ['\\', '`', '*', '_', '[', ']', '<', '>', '#', '|'];
+
+/// Escapes a value for use in a Markdown report, as inline text or in a table cell.
+fn markdown_cell(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\r' | '\n' => escaped.push(' '),
+            c if MARKDOWN_SPECIAL.contains(&c) => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_markdown(metadata: &ReportMetadata, sections: &[RuleSection]) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(
+        "# pglinter report: {}\n\n",
+        markdown_cell(metadata.database.as_deref().unwrap_or_default())
+    ));
+    out.push_str(&format!(
+        "Generated at {} by pglinter {} on PostgreSQL {}.\n\n",
+        metadata.generated_at,
+        metadata.extension_version.as_deref().unwrap_or("unknown"),
+        metadata.server_version.as_deref().unwrap_or("unknown")
+    ));
+
+    out.push_str("## Summary\n\n");
+    out.push_str("| Rule | Name | Scope | Severity | Status | Violations |\n");
+    out.push_str("|------|------|-------|----------|--------|-----------:|\n");
+    for section in sections {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            markdown_cell(&section.code),
+            markdown_cell(&section.name),
+            markdown_cell(&section.scope),
+            markdown_cell(section.severity.as_deref().unwrap_or_default()),
+            section.status(),
+            section.violation_count
+        ));
+    }
+
+    for section in sections.iter().filter(|s| s.status() != "passed") {
+        out.push_str(&format!(
+            "\n## {} - {}\n\n",
+            markdown_cell(&section.code),
+            markdown_cell(&section.name)
+        ));
+        out.push_str(&format!(
+            "**Scope:** {} | **Severity:** {} | **Violations:** {}\n\n",
+            markdown_cell(&section.scope),
+            markdown_cell(section.severity.as_deref().unwrap_or("-")),
+            section.violation_count
+        ));
+        if let Some(error) = &section.error {
+            out.push_str(&format!(
+                "**Rule execution failed:** {}\n\n",
+                markdown_cell(error)
+            ));
+            continue;
+        }
+        if !section.explanation.is_empty() {
+            out.push_str(&format!("{}\n\n", markdown_cell(&section.explanation)));
+        }
+        if let Some(advices) = &section.advices {
+            out.push_str(&format!("**Advice:** {}\n\n", markdown_cell(advices)));
+        }
+        if !section.fixes.is_empty() {
+            out.push_str("**How to fix:**\n\n");
+            for fix in &section.fixes {
+                out.push_str(&format!("- {}\n", markdown_cell(fix)));
+            }
+            out.push('\n');
+        }
+        for (schema, violations) in &section.schemas {
+            out.push_str(&format!("### Schema {}\n\n", markdown_cell(schema)));
+            out.push_str("| Object | Message |\n");
+            out.push_str("|--------|---------|\n");
+            for violation in violations {
+                out.push_str(&format!(
+                    "| {} | {} |\n",
+                    markdown_cell(&violation.description()),
+                    markdown_cell(&violation.message)
+                ));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn render_html(metadata: &ReportMetadata, sections: &[RuleSection]) -> String {
+    let database = xml_escape(metadata.database.as_deref().unwrap_or_default());
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>pglinter report: {}</title>\n", database));
+    out.push_str(
+        "<style>\nbody { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n\
+         .passed { color: #2e7d32; } .failed { color: #c62828; } .error { color: #ef6c00; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    out.push_str(&format!("<h1>pglinter report: {}</h1>\n", database));
+    out.push_str(&format!(
+        "<p>Generated at {} by pglinter {} on PostgreSQL {}.</p>\n",
+        xml_escape(&metadata.generated_at),
+        xml_escape(metadata.extension_version.as_deref().unwrap_or("unknown")),
+        xml_escape(metadata.server_version.as_deref().unwrap_or("unknown"))
+    ));
+
+    out.push_str("<h2>Summary</h2>\n<table>\n");
+    out.push_str(
+        "<tr><th>Rule</th><th>Name</th><th>Scope</th><th>Severity</th><th>Status</th><th>Violations</th></tr>\n",
+    );
+    for section in sections {
+        out.push_str(&format!(
+            "<tr><td><a href=\"#{code}\">{code}</a></td><td>{}</td><td>{}</td><td>{}</td><td class=\"{status}\">{status}</td><td>{}</td></tr>\n",
+            xml_escape(&section.name),
+            xml_escape(&section.scope),
+            xml_escape(section.severity.as_deref().unwrap_or_default()),
+            section.violation_count,
+            code = xml_escape(&section.code),
+            status = section.status(),
+        ));
+    }
+    out.push_str("</table>\n");
+
+    for section in sections.iter().filter(|s| s.status() != "passed") {
+        out.push_str(&format!(
+            "<h2 id=\"{code}\">{code} - {}</h2>\n",
+            xml_escape(&section.name),
+            code = xml_escape(&section.code)
+        ));
+        out.push_str(&format!(
+            "<p><strong>Scope:</strong> {} | <strong>Severity:</strong> {} | <strong>Violations:</strong> {}</p>\n",
+            xml_escape(&section.scope),
+            xml_escape(section.severity.as_deref().unwrap_or("-")),
+            section.violation_count
+        ));
+        if let Some(error) = &section.error {
+            out.push_str(&format!(
+                "<p class=\"error\"><strong>Rule execution failed:</strong> <code>{}</code></p>\n",
+                xml_escape(error)
+            ));
+            continue;
+        }
+        if !section.explanation.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", xml_escape(&section.explanation)));
+        }
+        if let Some(advices) = &section.advices {
+            out.push_str(&format!(
+                "<p><strong>Advice:</strong> {}</p>\n",
+                xml_escape(advices)
+            ));
+        }
+        if !section.fixes.is_empty() {
+            out.push_str("<p><strong>How to fix:</strong></p>\n<ul>\n");
+            for fix in &section.fixes {
+                out.push_str(&format!("<li>{}</li>\n", xml_escape(fix)));
+            }
+            out.push_str("</ul>\n");
+        }
+        for (schema, violations) in &section.schemas {
+            out.push_str(&format!(
+                "<h3>Schema {}</h3>\n<table>\n",
+                xml_escape(schema)
+            ));
+            out.push_str("<tr><th>Object</th><th>Message</th></tr>\n");
+            for violation in violations {
+                out.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>\n",
+                    xml_escape(&violation.description()),
+                    xml_escape(&violation.message)
+                ));
+            }
+            out.push_str("</table>\n");
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders a full human readable report, `format` is `markdown` or `html`.
+pub fn get_report(format: &str) -> Result<String, String> {
+    let render = match format.to_lowercase().as_str() {
+        "markdown" | "md" => render_markdown,
+        "html" => render_html,
+        other => {
+            return Err(format!(
+                "Unsupported report format '{}', expected 'markdown' or 'html'",
+                other
+            ))
+        }
+    };
+    let metadata = report_metadata()?;
+    let sections = rule_sections()?;
+    Ok(render(&metadata, &sections))
+}
+
+/// Escapes a Prometheus label value.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Appends one metric family in the Prometheus text exposition format.
+fn push_metric_family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[String]) {
+    out.push_str(&format!("# HELP {} {}\n", name, help));
+    out.push_str(&format!("# TYPE {} {}\n", name, kind));
+    for sample in samples {
+        out.push_str(name);
+        out.push_str(sample);
+        out.push('\n');
+    }
+}
+
+/// Runs all enabled rules and exposes the results in the Prometheus text format.
+pub fn metrics() -> Result<String, String> {
+    let metadata = report_metadata()?;
+    let database = label_value(metadata.database.as_deref().unwrap_or_default());
+    let runs = run_described_rules()?;
+
+    let mut violations = Vec::new();
+    let mut rule_violations = Vec::new();
+    let mut durations = Vec::new();
+    let mut errors = Vec::new();
+
+    for run in &runs {
+        let severity = rule_severity(&run.code)?.unwrap_or_default().to_lowercase();
+        let rule_labels = format!(
+            "database=\"{}\",rule=\"{}\"",
+            database,
+            label_value(&run.code)
+        );
+
+        let found: &[DescribedViolation] = run.violations.as_deref().unwrap_or_default();
+        let mut per_schema: BTreeMap<String, usize> = BTreeMap::new();
+        for (_, description) in found {
+            let schema = description
+                .as_ref()
+                .ok()
+                .and_then(|object| object.schema.clone())
+                .unwrap_or_default();
+            *per_schema.entry(schema).or_default() += 1;
+        }
+        for (schema, count) in per_schema {
+            violations.push(format!(
+                "{{{},severity=\"{}\",schema=\"{}\"}} {}",
+                rule_labels,
+                label_value(&severity),
+                label_value(&schema),
+                count
+            ));
+        }
+
+        rule_violations.push(format!(
+            "{{{},severity=\"{}\"}} {}",
+            rule_labels,
+            label_value(&severity),
+            found.len()
+        ));
+        durations.push(format!(
+            "{{{}}} {}",
+            rule_labels,
+            run.duration.as_secs_f64()
+        ));
+        errors.push(format!(
+            "{{{}}} {}",
+            rule_labels,
+            u8::from(run.violations.is_err())
+        ));
+    }
+
+    let mut out = String::new();
+    push_metric_family(
+        &mut out,
+        "pglinter_violations",
+        "gauge",
+        "Number of violations found by a rule, per severity and schema.",
+        &violations,
+    );
+    push_metric_family(
+        &mut out,
+        "pglinter_rule_violations",
+        "gauge",
+        "Number of violations found by a rule during the last run.",
+        &rule_violations,
+    );
+    push_metric_family(
+        &mut out,
+        "pglinter_rule_duration_seconds",
+        "gauge",
+        "Execution time of the rule query during the last run.",
+        &durations,
+    );
+    push_metric_family(
+        &mut out,
+        "pglinter_rule_errors",
+        "gauge",
+        "1 when the rule query raised an error during the last run, 0 otherwise.",
+        &errors,
+    );
+    push_metric_family(
+        &mut out,
+        "pglinter_rules_executed",
+        "gauge",
+        "Number of enabled rules executed during the last run.",
+        &[format!("{{database=\"{}\"}} {}", database, runs.len())],
+    );
+    push_metric_family(
+        &mut out,
+        "pglinter_last_run_timestamp_seconds",
+        "gauge",
+        "Unix time of the last run.",
+        &[format!(
+            "{{database=\"{}\"}} {}",
+            database,
+            chrono::Utc::now().timestamp()
+        )],
+    );
+    Ok(out)
+}
+
+/// Ratio of the violations of one enabled rule to the number of objects it checks.
+pub struct RuleSummary {
+    pub code: String,
+    pub name: String,
+    pub violations: i64,
+    pub total: Option<i64>,
+    pub ratio: Option<f64>,
+    pub warning_level: Option<i32>,
+    pub error_level: Option<i32>,
+    pub level: &'static str,
+    pub score: Option<f64>,
+    pub summary: String,
+}
+
+/// Message, warning and error levels and q1 query of a rule.
+type RuleThresholds = (String, Option<i32>, Option<i32>, Option<String>);
+
+/// Reads the message, the thresholds and the q1 query of every enabled rule at once.
+fn rule_thresholds() -> Result<BTreeMap<String, RuleThresholds>, String> {
+    Spi::connect(|client| {
+        let mut thresholds = BTreeMap::new();
+        for row in client.select(
+            "SELECT code, message, warning_level, error_level, q1
+             FROM pglinter.rules WHERE enable = true",
+            None,
+            &[],
+        )? {
+            let code: String = row.get(1)?.unwrap_or_default();
+            thresholds.insert(
+                code,
+                (
+                    row.get::<String>(2)?.unwrap_or_default(),
+                    row.get::<i32>(3)?,
+                    row.get::<i32>(4)?,
+                    row.get::<String>(5)?,
+                ),
+            );
+        }
+        Ok(thresholds)
+    })
+    .map_err(|e: spi::SpiError| format!("Database error fetching rule thresholds: {e}"))
+}
+
+fn round_one_decimal(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+/// Runs all enabled rules and compares their violations to the objects they check.
+///
+/// A rule with a q1 query gets a ratio, checked against its warning and error levels, and
+/// a score of `100 - ratio`. A rule without q1, or without thresholds, is in warning as soon
+/// as it reports a violation and scores 0 in that case. A rule whose query failed is not
+/// scored.
+pub fn rules_summary() -> Result<Vec<RuleSummary>, String> {
+    let mut summaries = Vec::new();
+    let mut thresholds = rule_thresholds()?;
+
+    for run in run_enabled_rules()? {
+        let (message, warning_level, error_level, q1_sql) =
+            thresholds.remove(&run.code).unwrap_or_default();
+        let found = match &run.violations {
+            Ok(found) => found.len() as i64,
+            Err(e) => {
+                summaries.push(RuleSummary {
+                    code: run.code,
+                    name: run.name,
+                    violations: 0,
+                    total: None,
+                    ratio: None,
+                    warning_level,
+                    error_level,
+                    level: "unknown",
+                    score: None,
+                    summary: format!("rule query failed: {e}"),
+                });
+                continue;
+            }
+        };
+        let total = match count_checked_objects(q1_sql.as_deref()) {
+            Ok(total) => total,
+            Err(e) => {
+                pgrx::warning!("Failed to count objects checked by {}: {}", run.code, e);
+                None
+            }
+        };
+        let ratio = total
+            .filter(|&total| total > 0)
+            .map(|total| round_one_decimal(found as f64 * 100.0 / total as f64));
+
+        let exceeds = |level: Option<i32>| match (ratio, level) {
+            (Some(ratio), Some(level)) => Some(ratio > f64::from(level)),
+            _ => None,
+        };
+        let (level, threshold) = match (exceeds(error_level), exceeds(warning_level)) {
+            (Some(true), _) => ("error", format!(" (error > {}%)", error_level.unwrap_or(0))),
+            (_, Some(true)) => (
+                "warning",
+                format!(" (warning > {}%)", warning_level.unwrap_or(0)),
+            ),
+            (Some(false), _) | (_, Some(false)) => ("ok", String::new()),
+            (None, None) if found > 0 => ("warning", String::new()),
+            (None, None) => ("ok", String::new()),
+        };
+
+        let (score, summary) = match (ratio, total) {
+            (Some(ratio), Some(total)) => (
+                100.0 - ratio.min(100.0),
+                format!("{ratio:.1}% ({found}/{total}) {message}{threshold}"),
+            ),
+            _ => (
+                if found > 0 { 0.0 } else { 100.0 },
+                format!("{found} {message}{threshold}"),
+            ),
+        };
+
+        summaries.push(RuleSummary {
+            code: run.code,
+            name: run.name,
+            violations: found,
+            total,
+            ratio,
+            warning_level,
+            error_level,
+            level,
+            score: Some(round_one_decimal(score)),
+            summary,
+        });
+    }
+
+    Ok(summaries)
+}
+
+/// Overall database score between 0 and 100: the average score of the enabled rules.
+pub fn database_score() -> Result<Option<f64>, String> {
+    let scores: Vec<f64> = rules_summary()?
+        .into_iter()
+        .filter_map(|summary| summary.score)
+        .collect();
+    if scores.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(round_one_decimal(
+        scores.iter().sum::<f64>() / scores.len() as f64,
+    )))
+}
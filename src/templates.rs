@@ -0,0 +1,377 @@
+use pgrx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+
+/// Language of the rule messages, lc_messages is used while it is not set.
+pub static LANGUAGE: GucSetting<Option<CString>> = GucSetting::<Option<CString>>::new(None);
+
+pub fn init_gucs() {
+    GucRegistry::define_string_guc(
+        c"pglinter.language",
+        c"Language of the pglinter rule messages",
+        c"A locale such as fr or de_DE. When empty, lc_messages is used. \
+          Messages without a translation are shown in English.",
+        &LANGUAGE,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// Locales to look for in a rule_msg, most specific first: fr_FR.UTF-8 gives
+/// fr_FR then fr. The C and POSIX locales give none.
+pub fn language_candidates(locale: &str) -> Vec<String> {
+    let locale = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .replace('-', "_");
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let mut candidates = vec![locale.clone()];
+    if let Some((language, _)) = locale.split_once('_') {
+        candidates.push(language.to_string());
+    }
+    candidates
+}
+
+/// Locales asked by pglinter.language, or by `lc_messages` when it is not set
+pub fn preferred_languages(lc_messages: Option<&str>) -> Vec<String> {
+    let language = LANGUAGE
+        .get()
+        .and_then(|language| language.to_str().ok().map(str::to_string))
+        .filter(|language| !language.trim().is_empty());
+    match language {
+        Some(language) => language_candidates(&language),
+        None => language_candidates(lc_messages.unwrap_or_default()),
+    }
+}
+
+/// Replaces the fields of a rule_msg by those of the first of `languages`
+/// found in its `locales`. Fields missing from the translation keep their
+/// English value. The `locales` are removed from the result.
+pub fn localize(mut rule_msg: Value, languages: &[String]) -> Value {
+    let Some(base) = rule_msg.as_object_mut() else {
+        return rule_msg;
+    };
+    let Some(Value::Object(locales)) = base.remove("locales") else {
+        return rule_msg;
+    };
+    let translation = languages.iter().find_map(|language| {
+        locales
+            .iter()
+            .find(|(locale, _)| locale.replace('-', "_").eq_ignore_ascii_case(language))
+            .map(|(_, translation)| translation)
+    });
+    if let Some(Value::Object(fields)) = translation {
+        for (field, value) in fields {
+            base.insert(field.clone(), value.clone());
+        }
+    }
+    rule_msg
+}
+
+/// Placeholders describing the object of a violation. `{object}` and `{0}` are
+/// kept for the messages written before the other placeholders existed.
+pub const OBJECT_PLACEHOLDERS: [&str; 12] = [
+    "object",
+    "0",
+    "schema",
+    "name",
+    "identity",
+    "quoted_identity",
+    "type",
+    "column",
+    "owner",
+    "classid",
+    "objid",
+    "objsubid",
+];
+
+/// Values of the placeholders of a message template
+#[derive(Debug, Default, Clone)]
+pub struct TemplateContext {
+    values: BTreeMap<String, String>,
+}
+
+impl TemplateContext {
+    pub fn insert(&mut self, key: &str, value: impl Into<String>) {
+        self.values.insert(key.to_string(), value.into());
+    }
+
+    /// Add rule-specific fields. They do not replace the placeholders already
+    /// set, non-string values are rendered as JSON.
+    pub fn with_fields(mut self, fields: &serde_json::Map<String, Value>) -> Self {
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            self.values.entry(key.clone()).or_insert(value);
+        }
+        self
+    }
+
+    /// Add the details returned by q4: `{details}` is the whole document and
+    /// `{details.key}` one of its fields, nested fields are joined by dots.
+    /// Arrays of strings or numbers render as a comma separated list.
+    pub fn with_details(mut self, details: Option<&Value>) -> Self {
+        fn flatten(context: &mut TemplateContext, key: String, value: &Value) {
+            if let Value::Object(fields) = value {
+                for (field, value) in fields {
+                    flatten(context, format!("{key}.{field}"), value);
+                }
+            }
+            let rendered = match value {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                Value::Array(items)
+                    if items
+                        .iter()
+                        .all(|item| item.is_string() || item.is_number()) =>
+                {
+                    items
+                        .iter()
+                        .map(|item| {
+                            item.as_str()
+                                .map_or_else(|| item.to_string(), str::to_string)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+                other => other.to_string(),
+            };
+            context.insert(&key, rendered);
+        }
+        if let Some(details) = details {
+            flatten(&mut self, "details".to_string(), details);
+        }
+        self
+    }
+
+    /// Replace the `{placeholder}` of the template by their value. Unknown
+    /// placeholders are kept as they are, `{{` and `}}` render as braces.
+    pub fn render(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            rendered.push_str(&rest[..start]);
+            let tail = &rest[start..];
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                rendered.push_str(&tail[..1]);
+                rest = &tail[2..];
+                continue;
+            }
+            let placeholder = tail
+                .strip_prefix('{')
+                .and_then(|t| t.find('}').map(|end| &t[..end]))
+                .filter(|key| {
+                    !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+                });
+            match placeholder.and_then(|key| self.values.get(key).map(|v| (key, v))) {
+                Some((key, value)) => {
+                    rendered.push_str(value);
+                    rest = &tail[key.len() + 2..];
+                }
+                None => {
+                    rendered.push_str(&tail[..1]);
+                    rest = &tail[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// Render every string of a JSON document, such as a rule_msg
+    pub fn render_json(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.render(s),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.render_json(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.render_json(v)),
+            _ => {}
+        }
+    }
+}
+
+/// An object as reported by pg_identify_object, with the unquoted parts of its
+/// identity and its owner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectDescription {
+    pub object_type: String,
+    pub schema: Option<String>,
+    pub name: Option<String>,
+    pub quoted_identity: String,
+    pub names: Vec<String>,
+    pub owner: Option<String>,
+}
+
+/// Number of columns added by `described_query`
+pub const DESCRIPTION_COLUMNS: usize = 6;
+
+/// Describes the object at v.classid, v.objid, v.objsubid. pg_identify_object
+/// raises an error for the classids of other catalogs, their objects are looked
+/// up as a missing relation instead and are not resolved. Legacy pg_attribute
+/// (1249) column references are read as pg_class columns.
+const DESCRIPTION_SQL: &str = "
+    SELECT o.type, o.schema, o.name, o.identity, a.object_names,
+           pg_catalog.pg_get_userbyid(CASE n.classid
+               WHEN 'pg_catalog.pg_class'::regclass THEN
+                   (SELECT relowner FROM pg_catalog.pg_class WHERE oid = n.objid)
+               WHEN 'pg_catalog.pg_namespace'::regclass THEN
+                   (SELECT nspowner FROM pg_catalog.pg_namespace WHERE oid = n.objid)
+               WHEN 'pg_catalog.pg_proc'::regclass THEN
+                   (SELECT proowner FROM pg_catalog.pg_proc WHERE oid = n.objid)
+               WHEN 'pg_catalog.pg_type'::regclass THEN
+                   (SELECT typowner FROM pg_catalog.pg_type WHERE oid = n.objid)
+               WHEN 'pg_catalog.pg_database'::regclass THEN
+                   (SELECT datdba FROM pg_catalog.pg_database WHERE oid = n.objid)
+           END)::TEXT
+    FROM (
+        SELECT CASE WHEN l.known THEN l.classid ELSE 'pg_catalog.pg_class'::regclass END
+                   AS classid,
+               CASE WHEN l.known THEN l.objid ELSE 0 END AS objid,
+               l.objsubid
+        FROM (
+            SELECT l.classid, l.objid, l.objsubid, l.classid = ANY ('{
+                pg_class, pg_proc, pg_type, pg_cast, pg_collation, pg_constraint,
+                pg_conversion, pg_attrdef, pg_language, pg_largeobject, pg_operator,
+                pg_opclass, pg_opfamily, pg_am, pg_amop, pg_amproc, pg_rewrite,
+                pg_trigger, pg_namespace, pg_statistic_ext, pg_ts_parser, pg_ts_dict,
+                pg_ts_template, pg_ts_config, pg_authid, pg_database, pg_tablespace,
+                pg_foreign_data_wrapper, pg_foreign_server, pg_user_mapping,
+                pg_default_acl, pg_extension, pg_event_trigger, pg_policy,
+                pg_publication, pg_publication_rel, pg_subscription, pg_transform
+            }'::regclass[]) AS known
+            FROM (
+                SELECT CASE WHEN v.classid::oid = 'pg_catalog.pg_attribute'::regclass
+                                 AND v.objsubid <> 0
+                            THEN 'pg_catalog.pg_class'::regclass::oid
+                            ELSE v.classid::oid
+                       END AS classid,
+                       v.objid::oid AS objid,
+                       v.objsubid
+            ) l
+        ) l
+    ) n,
+    pg_catalog.pg_identify_object(n.classid, n.objid, n.objsubid) o,
+    pg_catalog.pg_identify_object_as_address(n.classid, n.objid, n.objsubid) a";
+
+/// Wraps a q4 query so that each row is followed by the DESCRIPTION_COLUMNS
+/// describing its object, read back with `read_description`.
+pub fn described_query(q4_sql: &str) -> String {
+    format!(
+        "SELECT v.*, d.*
+         FROM ({}\n) AS v (classid, objid, objsubid)
+         LEFT JOIN LATERAL ({DESCRIPTION_SQL}) d ON TRUE",
+        q4_sql.trim().trim_end_matches(';')
+    )
+}
+
+/// Reads the description columns starting at `first_column`
+pub fn read_description(
+    row: &spi::SpiHeapTupleData<'_>,
+    first_column: usize,
+) -> spi::Result<Result<ObjectDescription, String>> {
+    let (Some(object_type), Some(quoted_identity)) = (
+        row.get::<String>(first_column)?,
+        row.get::<String>(first_column + 3)?,
+    ) else {
+        return Ok(Err("object not found".to_string()));
+    };
+    Ok(Ok(ObjectDescription {
+        object_type,
+        schema: row.get(first_column + 1)?,
+        name: row.get(first_column + 2)?,
+        quoted_identity,
+        names: row.get(first_column + 4)?.unwrap_or_default(),
+        owner: row.get(first_column + 5)?,
+    }))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+fn describe_object(classid: i32, objid: i32, objsubid: i32) -> Result<ObjectDescription, String> {
+    let sql = format!(
+        "SELECT d.* FROM (SELECT $1::oid AS classid, $2::oid AS objid, $3 AS objsubid) v,
+         LATERAL ({DESCRIPTION_SQL}) d"
+    );
+    Spi::connect(|client| {
+        let rows = client.select(&sql, None, &[classid.into(), objid.into(), objsubid.into()])?;
+        match rows.into_iter().next() {
+            Some(row) => read_description(&row, 1),
+            None => Ok(Err("object not found".to_string())),
+        }
+    })
+    .map_err(|e: spi::SpiError| format!("SPI error: {e}"))?
+}
+
+/// Placeholders describing the object at (classid, objid, objsubid). When the
+/// object cannot be resolved, `{object}` shows its location and the other
+/// object placeholders are empty.
+#[cfg(any(test, feature = "pg_test"))]
+pub fn object_context(classid: i32, objid: i32, objsubid: i32) -> TemplateContext {
+    described_context(
+        classid,
+        objid,
+        objsubid,
+        describe_object(classid, objid, objsubid),
+    )
+}
+
+/// Placeholders of the object at (classid, objid, objsubid), from its
+/// description read with the violation
+pub fn described_context(
+    classid: i32,
+    objid: i32,
+    objsubid: i32,
+    object: Result<ObjectDescription, String>,
+) -> TemplateContext {
+    let mut context = TemplateContext::default();
+    for key in OBJECT_PLACEHOLDERS {
+        context.insert(key, "");
+    }
+    context.insert("classid", classid.to_string());
+    context.insert("objid", objid.to_string());
+    context.insert("objsubid", objsubid.to_string());
+    let location = format!("classid={classid}, objid={objid}, objsubid={objsubid}");
+
+    let object = match object {
+        Ok(object) => object,
+        Err(e) => {
+            pgrx::debug1!("Could not resolve object name for {}: {}", location, e);
+            context.insert("object", location.clone());
+            context.insert("0", location);
+            return context;
+        }
+    };
+
+    // object_names is (schema, name, column) for the objects of a schema
+    let names = &object.names;
+    let (schema, name) = if object.schema.is_some() {
+        (names.first(), names.get(1))
+    } else if object.object_type == "schema" {
+        (names.first(), names.first())
+    } else {
+        (None, names.first())
+    };
+    let column = (objsubid != 0).then(|| names.last()).flatten();
+
+    context.insert("object", object.quoted_identity.clone());
+    context.insert("0", object.quoted_identity.clone());
+    context.insert("quoted_identity", object.quoted_identity);
+    context.insert("identity", names.join("."));
+    context.insert("type", object.object_type);
+    context.insert("schema", schema.cloned().unwrap_or_default());
+    context.insert("name", name.cloned().unwrap_or_default());
+    context.insert("column", column.cloned().unwrap_or_default());
+    context.insert("owner", object.owner.unwrap_or_default());
+    context
+}
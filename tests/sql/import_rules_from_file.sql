@@ -32,6 +32,9 @@
 \! echo "    q2: \"SELECT 1 as problems\"" >> /tmp/test_rules_import.yaml
 CREATE EXTENSION pglinter;
 
+-- File access is limited to the directory set by pglinter.file_directory
+SET pglinter.file_directory = '/tmp';
+
 -- Test 1: Import rules from file
 SELECT pglinter.import_rules_from_file('/tmp/test_rules_import.yaml') AS file_import_result;
 
@@ -55,6 +58,10 @@ SELECT pglinter.import_rules_from_file('/tmp/invalid_rules.yaml') AS invalid_fil
 
 SELECT pglinter.import_rules_from_file('/tmp/empty_rules.yaml') AS empty_file_result;
 
+-- Test 5: Paths outside of pglinter.file_directory are rejected
+SELECT pglinter.import_rules_from_file('../etc/passwd') AS traversal_result;
+SELECT pglinter.import_rules_from_file('/etc/passwd') AS outside_result;
+
 -- Verify final state - should still have our valid imported rules
 SELECT COUNT(*) AS imported_rules_count
 FROM pglinter.rules